@@ -0,0 +1,55 @@
+// examples/host_sim.rs
+// AltOSRust
+//
+// Created by Daniel Seitz on 2/24/17
+
+//! Runs a few tasks with the kernel simulated on the host.
+//!
+//! Run it with `cargo run --example host_sim --features host`, it exits successfully once the
+//! tasks have taken turns with the shared counter.
+
+extern crate altos_core;
+
+use altos_core::{start_scheduler, Priority};
+use altos_core::args::Args;
+use altos_core::sync::Mutex;
+use altos_core::syscall::{new_task, sleep_for, FOREVER_CHAN};
+use altos_core::tick;
+use std::process;
+
+static COUNT: Mutex<usize> = Mutex::new(0);
+
+fn main() {
+  new_task(ping_task, Args::empty(), 512, Priority::Normal, "ping");
+  new_task(pong_task, Args::empty(), 512, Priority::Normal, "pong");
+  new_task(finish_task, Args::empty(), 512, Priority::Low, "finish");
+  start_scheduler();
+}
+
+fn count(name: &str) {
+  let mut count = COUNT.lock();
+  *count += 1;
+  println!("[{:>4}] {} {}", tick::get_tick(), name, *count);
+}
+
+fn ping_task(_args: &mut Args) {
+  loop {
+    count("ping");
+    sleep_for(FOREVER_CHAN, 10);
+  }
+}
+
+fn pong_task(_args: &mut Args) {
+  loop {
+    count("pong");
+    sleep_for(FOREVER_CHAN, 15);
+  }
+}
+
+fn finish_task(_args: &mut Args) {
+  sleep_for(FOREVER_CHAN, 100);
+  let count = *COUNT.lock();
+  println!("[{:>4}] counted to {}", tick::get_tick(), count);
+  // Ping runs about 10 times and pong about 7 in 100 ticks
+  process::exit(if count >= 15 { 0 } else { 1 });
+}
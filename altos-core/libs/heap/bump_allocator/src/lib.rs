@@ -6,17 +6,72 @@
 #![allocator]
 #![no_std]
 
+use core::ptr;
+
 static mut BUMP_ALLOCATOR: BumpAllocator = BumpAllocator::new();
+static mut HOOKS: Option<AllocHooks> = None;
+static mut FAILED_REQUEST: (usize, usize) = (0, 0);
+
+/// Hooks that get a chance to serve an allocation before it goes to the global heap.
+///
+/// `allocate` returns `None` if the global heap should serve the allocation, and `deallocate`
+/// returns true if it has taken care of freeing the memory.
+#[derive(Copy, Clone)]
+pub struct AllocHooks {
+  pub allocate: fn(usize, usize) -> Option<*mut u8>,
+  pub deallocate: fn(*mut u8, usize, usize) -> bool,
+}
 
 /// Call this before doing any heap allocation
 pub fn init_heap(heap_start: usize, heap_size: usize) {
   unsafe { BUMP_ALLOCATOR.init(heap_start, heap_size) };
 }
 
+/// Install hooks to route allocations somewhere other than the global heap.
+pub fn set_hooks(hooks: AllocHooks) {
+  unsafe { HOOKS = Some(hooks) };
+}
+
+/// Returns the size and alignment of the most recent allocation that could not be served.
+pub fn failed_request() -> (usize, usize) {
+  unsafe { FAILED_REQUEST }
+}
+
+/// Statistics about the usage of the heap.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct HeapStats {
+  /// The number of bytes currently allocated, including any padding.
+  pub used: usize,
+  /// The number of bytes that are free.
+  pub free: usize,
+  /// The highest number of bytes that have been allocated at one time.
+  pub peak: usize,
+  /// The number of allocations that have not been freed yet.
+  pub allocations: usize,
+  /// The size of the largest block that could be allocated.
+  pub largest_free: usize,
+}
+
+/// Returns the current usage statistics for the heap.
+///
+/// Memory is never freed by the bump allocator, so `used` and `peak` are always the same.
+pub fn stats() -> HeapStats {
+  unsafe { BUMP_ALLOCATOR.stats() }
+}
+
+/// Verifies the integrity of the heap.
+///
+/// The bump allocator doesn't keep any bookkeeping in the heap itself, so the only thing that can
+/// be checked is that the next pointer is still within the heap.
+pub fn check_heap() -> Result<(), usize> {
+  unsafe { BUMP_ALLOCATOR.check() }
+}
+
 struct BumpAllocator {
   heap_start: usize,
   heap_size: usize,
   next: usize,
+  allocations: usize,
 }
 
 impl BumpAllocator {
@@ -27,6 +82,7 @@ impl BumpAllocator {
       heap_start: 0,
       heap_size: 0,
       next: 0,
+      allocations: 0,
     }
   }
 
@@ -59,6 +115,7 @@ impl BumpAllocator {
 
     let result = if alloc_end <= self.heap_start + self.heap_size {
       self.next = alloc_end;
+      self.allocations += 1;
       Some(alloc_start as *mut u8)
     }
     else {
@@ -74,6 +131,27 @@ impl BumpAllocator {
     }
     result
   }
+
+  fn stats(&self) -> HeapStats {
+    let used = self.next - self.heap_start;
+    let free = self.heap_size - used;
+    HeapStats {
+      used: used,
+      free: free,
+      peak: used,
+      allocations: self.allocations,
+      largest_free: free,
+    }
+  }
+
+  fn check(&self) -> Result<(), usize> {
+    if self.next < self.heap_start || self.next > self.heap_start + self.heap_size {
+      Err(self.next)
+    }
+    else {
+      Ok(())
+    }
+  }
 }
 
 /// Align downwards. Returns the greatest x with alignment `align` so that x <= addr. The alignment
@@ -99,12 +177,26 @@ pub fn align_up(addr: usize, align: usize) -> usize {
 #[no_mangle]
 pub extern fn __rust_allocate(size: usize, align: usize) -> *mut u8 {
   unsafe {
-    BUMP_ALLOCATOR.allocate(size, align).expect("out of memory")
+    let ptr = match HOOKS.and_then(|hooks| (hooks.allocate)(size, align)) {
+      Some(ptr) => ptr,
+      None => BUMP_ALLOCATOR.allocate(size, align).unwrap_or(ptr::null_mut()),
+    };
+    if ptr.is_null() {
+      FAILED_REQUEST = (size, align);
+    }
+    ptr
   }
 }
 
 #[no_mangle]
-pub extern fn __rust_deallocate(_ptr: *mut u8, _size: usize, _align: usize) {
+pub extern fn __rust_deallocate(ptr: *mut u8, size: usize, align: usize) {
+  unsafe {
+    if let Some(hooks) = HOOKS {
+      if (hooks.deallocate)(ptr, size, align) {
+        return;
+      }
+    }
+  }
   // leak it...
 }
 
@@ -120,10 +212,12 @@ pub extern fn __rust_reallocate_inplace(_ptr: *mut u8, size: usize, _new_size: u
 
 #[no_mangle]
 pub extern fn __rust_reallocate(ptr: *mut u8, size: usize, new_size: usize, align: usize) -> *mut u8 {
-  use core::{ptr, cmp};
+  use core::cmp;
 
   let new_ptr = __rust_allocate(new_size, align);
-  unsafe { ptr::copy(ptr, new_ptr, cmp::min(size, new_size)) };
-  __rust_deallocate(ptr, size, align);
+  if !new_ptr.is_null() {
+    unsafe { ptr::copy(ptr, new_ptr, cmp::min(size, new_size)) };
+    __rust_deallocate(ptr, size, align);
+  }
   new_ptr
 }
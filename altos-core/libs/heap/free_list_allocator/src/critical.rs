@@ -0,0 +1,68 @@
+// critical.rs
+// AltOSRust
+//
+// Created by Daniel Seitz on 2/12/17
+
+//! Interrupt masking for the allocator.
+//!
+//! The allocator can't depend on the kernel, so it carries its own copy of the kernel's
+//! `CriticalSection`. Holding a `CriticalSectionGuard` masks interrupts so an interrupt handler can
+//! never observe the free list in the middle of being modified.
+
+pub struct CriticalSection;
+
+impl CriticalSection {
+  /// Marks the beginning of a critical section, returns a `CriticalSectionGuard` that will end the
+  /// critical section upon falling out of scope.
+  pub fn begin() -> CriticalSectionGuard {
+    CriticalSectionGuard(begin_critical())
+  }
+}
+
+/// Tracks the lifetime of a critical section.
+#[must_use]
+pub struct CriticalSectionGuard(usize);
+
+impl Drop for CriticalSectionGuard {
+  fn drop(&mut self) {
+    end_critical(self.0);
+  }
+}
+
+#[cfg(target_arch="arm")]
+fn begin_critical() -> usize {
+  let primask: usize;
+  unsafe {
+    asm!(
+      concat!(
+        "mrs $0, PRIMASK\n",
+        "cpsid i\n")
+      : "=r"(primask)
+      : /* no inputs */
+      : /* no clobbers */
+      : "volatile");
+  }
+  primask
+}
+
+#[cfg(target_arch="arm")]
+fn end_critical(primask: usize) {
+  unsafe {
+    asm!("msr PRIMASK, $0"
+      : /* no outputs */
+      : "r"(primask)
+      : /* no clobbers */
+      : "volatile");
+  }
+}
+
+#[cfg(not(target_arch="arm"))]
+fn begin_critical() -> usize {
+  // no-op
+  0
+}
+
+#[cfg(not(target_arch="arm"))]
+fn end_critical(_primask: usize) {
+  // no-op
+}
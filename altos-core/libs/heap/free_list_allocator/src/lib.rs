@@ -0,0 +1,513 @@
+// lib.rs
+// AltOSRust
+//
+// Created by Daniel Seitz on 2/12/17
+
+//! A general purpose free list allocator.
+//!
+//! Unlike the bump allocator this allocator reclaims memory when it is freed. Free blocks of
+//! memory (holes) are kept in a singly linked list sorted by address, with the list nodes stored
+//! inside the holes themselves. Allocation takes the first hole that is big enough, and freeing a
+//! block merges it with any neighboring holes so the heap doesn't fragment into tiny pieces.
+//!
+//! All operations on the free list are done with interrupts masked, so it is safe to allocate from
+//! both tasks and interrupt handlers.
+//!
+//! With the `debug` feature enabled every allocation is given a header containing a canary value.
+//! The canary is verified whenever the block is freed, and the whole heap can be verified on demand
+//! with `check_heap`.
+
+#![feature(const_fn)]
+#![feature(asm)]
+
+#![cfg_attr(not(test), feature(allocator))]
+#![cfg_attr(not(test), allocator)]
+#![no_std]
+
+#[cfg(test)]
+#[macro_use]
+extern crate std;
+
+mod critical;
+mod tests;
+
+use core::{mem, ptr};
+use critical::CriticalSection;
+
+static mut FREE_LIST_ALLOCATOR: FreeListAllocator = FreeListAllocator::new();
+static mut HOOKS: Option<AllocHooks> = None;
+static mut FAILED_REQUEST: (usize, usize) = (0, 0);
+
+/// Hooks that get a chance to serve an allocation before it goes to the global heap.
+///
+/// `allocate` returns `None` if the global heap should serve the allocation, and `deallocate`
+/// returns true if it has taken care of freeing the memory.
+#[derive(Copy, Clone)]
+pub struct AllocHooks {
+  pub allocate: fn(usize, usize) -> Option<*mut u8>,
+  pub deallocate: fn(*mut u8, usize, usize) -> bool,
+}
+
+/// Call this before doing any heap allocation
+pub fn init_heap(heap_start: usize, heap_size: usize) {
+  let _g = CriticalSection::begin();
+  unsafe { FREE_LIST_ALLOCATOR.init(heap_start, heap_size) };
+}
+
+/// Install hooks to route allocations somewhere other than the global heap.
+pub fn set_hooks(hooks: AllocHooks) {
+  unsafe { HOOKS = Some(hooks) };
+}
+
+/// Returns the size and alignment of the most recent allocation that could not be served.
+pub fn failed_request() -> (usize, usize) {
+  unsafe { FAILED_REQUEST }
+}
+
+/// Statistics about the usage of the heap.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct HeapStats {
+  /// The number of bytes currently allocated, including any overhead.
+  pub used: usize,
+  /// The number of bytes that are free.
+  pub free: usize,
+  /// The highest number of bytes that have been allocated at one time.
+  pub peak: usize,
+  /// The number of allocations that have not been freed yet.
+  pub allocations: usize,
+  /// The size of the largest block that could be allocated.
+  pub largest_free: usize,
+}
+
+/// Returns the current usage statistics for the heap.
+pub fn stats() -> HeapStats {
+  let _g = CriticalSection::begin();
+  unsafe { FREE_LIST_ALLOCATOR.stats() }
+}
+
+/// Verifies the integrity of the heap.
+///
+/// Returns the address of the first corrupted block if any is found. Without the `debug` feature
+/// only the free list can be verified.
+pub fn check_heap() -> Result<(), usize> {
+  let _g = CriticalSection::begin();
+  unsafe { FREE_LIST_ALLOCATOR.check() }
+}
+
+/// A free block of memory, stored at the start of the block itself.
+struct Hole {
+  size: usize,
+  next: *mut Hole,
+}
+
+/// The header placed in front of every allocation when the `debug` feature is enabled.
+#[cfg(feature="debug")]
+struct Header {
+  size: usize,
+  canary: usize,
+}
+
+/// The smallest block of memory that can be handed out, every block must be able to hold a `Hole`
+/// once it is freed.
+const MIN_BLOCK_SIZE: usize = mem::size_of::<Hole>();
+const BLOCK_ALIGN: usize = mem::align_of::<Hole>();
+
+#[cfg(feature="debug")]
+const HEADER_SIZE: usize = mem::size_of::<Header>();
+#[cfg(not(feature="debug"))]
+const HEADER_SIZE: usize = 0;
+
+#[cfg(feature="debug")]
+const CANARY: usize = 0xA110_C8ED;
+
+/// A first fit allocator backed by an address ordered list of free blocks.
+pub struct FreeListAllocator {
+  head: Hole,
+  heap_start: usize,
+  heap_end: usize,
+  used: usize,
+  peak: usize,
+  allocations: usize,
+}
+
+impl FreeListAllocator {
+  /// Create a new allocator with no memory to allocate from.
+  pub const fn new() -> Self {
+    FreeListAllocator {
+      head: Hole { size: 0, next: ptr::null_mut() },
+      heap_start: 0,
+      heap_end: 0,
+      used: 0,
+      peak: 0,
+      allocations: 0,
+    }
+  }
+
+  /// Give the allocator the memory in the range [heap_start..heap_start + heap_size) to allocate
+  /// from.
+  ///
+  /// This must only be called once, and the memory must not be used by anything else.
+  pub unsafe fn init(&mut self, heap_start: usize, heap_size: usize) {
+    let start = align_up(heap_start, BLOCK_ALIGN);
+    let end = align_down(heap_start + heap_size, BLOCK_ALIGN);
+    if end <= start || end - start < MIN_BLOCK_SIZE {
+      return;
+    }
+
+    let hole = start as *mut Hole;
+    ptr::write(hole, Hole { size: end - start, next: ptr::null_mut() });
+    self.head.next = hole;
+    self.heap_start = start;
+    self.heap_end = end;
+  }
+
+  /// Allocates a block of memory with the given size and alignment.
+  ///
+  /// Returns `None` if there is no hole large enough to satisfy the request.
+  pub fn allocate(&mut self, size: usize, align: usize) -> Option<*mut u8> {
+    let size = block_size(size);
+    let align = if align > BLOCK_ALIGN { align } else { BLOCK_ALIGN };
+
+    let block = match self.allocate_block(size, align) {
+      Some(block) => block,
+      None => return None,
+    };
+
+    self.used += size;
+    self.allocations += 1;
+    if self.used > self.peak {
+      self.peak = self.used;
+    }
+    // UNSAFE: The block was just allocated, so we own the memory
+    unsafe { Some(write_header(block, size)) }
+  }
+
+  /// Finds a hole to fit a block of `size` bytes in, with the memory after the header aligned to
+  /// `align`.
+  fn allocate_block(&mut self, size: usize, align: usize) -> Option<*mut u8> {
+    // UNSAFE: Every pointer in the free list points to a hole within the heap that we own
+    unsafe {
+      let mut prev: *mut Hole = &mut self.head;
+      while !(*prev).next.is_null() {
+        let hole = (*prev).next;
+        if let Some((front, back)) = split(hole, size, align) {
+          let next = (*hole).next;
+          let alloc_start = hole as usize + front;
+
+          // Whatever is left over on either side of the allocation goes back into the list
+          let mut link = prev;
+          if front > 0 {
+            (*hole).size = front;
+            link = hole;
+          }
+          if back > 0 {
+            let back_hole = (alloc_start + size) as *mut Hole;
+            ptr::write(back_hole, Hole { size: back, next: next });
+            (*link).next = back_hole;
+          }
+          else {
+            (*link).next = next;
+          }
+          return Some(alloc_start as *mut u8);
+        }
+        prev = hole;
+      }
+    }
+    None
+  }
+
+  /// Returns a block of memory to the free list, merging it with any adjacent holes.
+  ///
+  /// `ptr` and `size` must describe a block previously returned by `allocate`.
+  pub unsafe fn deallocate(&mut self, ptr: *mut u8, size: usize) {
+    let size = block_size(size);
+    let addr = read_header(ptr, size) as usize;
+
+    self.used -= size;
+    self.allocations -= 1;
+    self.deallocate_block(addr, size);
+  }
+
+  /// Puts the block at `addr` back into the free list.
+  unsafe fn deallocate_block(&mut self, addr: usize, size: usize) {
+    let head: *mut Hole = &mut self.head;
+
+    // Find the hole right before the block
+    let mut prev = head;
+    while !(*prev).next.is_null() && ((*prev).next as usize) < addr {
+      prev = (*prev).next;
+    }
+    let next = (*prev).next;
+
+    let hole = if prev != head && prev as usize + (*prev).size == addr {
+      // Merge into the previous hole
+      (*prev).size += size;
+      prev
+    }
+    else {
+      let hole = addr as *mut Hole;
+      ptr::write(hole, Hole { size: size, next: next });
+      (*prev).next = hole;
+      hole
+    };
+
+    // Merge the next hole into this one
+    if !next.is_null() && hole as usize + (*hole).size == next as usize {
+      (*hole).size += (*next).size;
+      (*hole).next = (*next).next;
+    }
+  }
+
+  /// Returns the total number of free bytes in the heap.
+  pub fn free_bytes(&self) -> usize {
+    let mut total = 0;
+    let mut current = self.head.next;
+    while !current.is_null() {
+      // UNSAFE: Every pointer in the free list points to a hole within the heap that we own
+      unsafe {
+        total += (*current).size;
+        current = (*current).next;
+      }
+    }
+    total
+  }
+
+  /// Returns the current usage statistics for the heap.
+  pub fn stats(&self) -> HeapStats {
+    let mut free = 0;
+    let mut largest_free = 0;
+    let mut current = self.head.next;
+    while !current.is_null() {
+      // UNSAFE: Every pointer in the free list points to a hole within the heap that we own
+      unsafe {
+        free += (*current).size;
+        if (*current).size > largest_free {
+          largest_free = (*current).size;
+        }
+        current = (*current).next;
+      }
+    }
+
+    HeapStats {
+      used: self.used,
+      free: free,
+      peak: self.peak,
+      allocations: self.allocations,
+      largest_free: largest_free.saturating_sub(HEADER_SIZE),
+    }
+  }
+
+  /// Verifies the integrity of the heap.
+  ///
+  /// The heap is walked from start to end, every block must either be a hole in the free list or an
+  /// allocation with a valid header. Returns the address of the first block that is corrupted.
+  #[cfg(feature="debug")]
+  pub fn check(&self) -> Result<(), usize> {
+    let mut addr = self.heap_start;
+    let mut hole = self.head.next;
+    // UNSAFE: We only read memory within the bounds of the heap
+    unsafe {
+      while addr < self.heap_end {
+        let size = if addr == hole as usize {
+          let size = (*hole).size;
+          hole = (*hole).next;
+          size
+        }
+        else {
+          match check_header(addr) {
+            Some(size) => size,
+            None => return Err(addr),
+          }
+        };
+        if size < MIN_BLOCK_SIZE || size > self.heap_end - addr {
+          return Err(addr);
+        }
+        addr += size;
+      }
+    }
+    if hole.is_null() { Ok(()) } else { Err(hole as usize) }
+  }
+
+  /// Verifies the integrity of the free list.
+  ///
+  /// Every hole must lie within the heap and the holes must be sorted by address without
+  /// overlapping. Returns the address of the first hole that is corrupted.
+  #[cfg(not(feature="debug"))]
+  pub fn check(&self) -> Result<(), usize> {
+    let mut min_addr = self.heap_start;
+    let mut hole = self.head.next;
+    // UNSAFE: We only read holes once we know they are within the bounds of the heap
+    unsafe {
+      while !hole.is_null() {
+        let addr = hole as usize;
+        if addr < min_addr || addr >= self.heap_end {
+          return Err(addr);
+        }
+        let size = (*hole).size;
+        if size < MIN_BLOCK_SIZE || size > self.heap_end - addr {
+          return Err(addr);
+        }
+        min_addr = addr + size;
+        hole = (*hole).next;
+      }
+    }
+    Ok(())
+  }
+}
+
+/// Writes the debug header to the front of a block, returning the pointer to hand out.
+#[cfg(feature="debug")]
+unsafe fn write_header(block: *mut u8, size: usize) -> *mut u8 {
+  ptr::write(block as *mut Header, Header { size: size, canary: CANARY ^ size });
+  block.offset(HEADER_SIZE as isize)
+}
+
+#[cfg(not(feature="debug"))]
+unsafe fn write_header(block: *mut u8, _size: usize) -> *mut u8 {
+  block
+}
+
+/// Verifies the debug header of an allocation that's being freed, returning the start of the block.
+///
+/// # Panics
+///
+/// This function will panic if the header has been corrupted.
+#[cfg(feature="debug")]
+unsafe fn read_header(ptr: *mut u8, size: usize) -> *mut u8 {
+  let block = ptr.offset(-(HEADER_SIZE as isize));
+  if check_header(block as usize) != Some(size) {
+    panic!("free_list_allocator - heap corruption detected at {:#x}", block as usize);
+  }
+  block
+}
+
+#[cfg(not(feature="debug"))]
+unsafe fn read_header(ptr: *mut u8, _size: usize) -> *mut u8 {
+  ptr
+}
+
+/// Checks the header of the allocated block at `addr`, returning the size of the block if the
+/// header is intact.
+#[cfg(feature="debug")]
+unsafe fn check_header(addr: usize) -> Option<usize> {
+  let header = &*(addr as *const Header);
+  if header.canary == CANARY ^ header.size {
+    Some(header.size)
+  }
+  else {
+    None
+  }
+}
+
+
+/// Check if an allocation of `size` bytes fits in `hole`, with the memory after the header
+/// aligned to `align`.
+///
+/// Returns the number of bytes left over before and after the allocation. Leftover space on
+/// either side must be big enough to hold a hole of its own.
+unsafe fn split(hole: *mut Hole, size: usize, align: usize) -> Option<(usize, usize)> {
+  let hole_start = hole as usize;
+  let hole_end = hole_start + (*hole).size;
+
+  let mut alloc_start = align_up(hole_start + HEADER_SIZE, align) - HEADER_SIZE;
+  if alloc_start != hole_start && alloc_start - hole_start < MIN_BLOCK_SIZE {
+    alloc_start = align_up(hole_start + MIN_BLOCK_SIZE + HEADER_SIZE, align) - HEADER_SIZE;
+  }
+  let alloc_end = match alloc_start.checked_add(size) {
+    Some(end) => end,
+    None => return None,
+  };
+  if alloc_end > hole_end {
+    return None;
+  }
+
+  let back = hole_end - alloc_end;
+  if back != 0 && back < MIN_BLOCK_SIZE {
+    return None;
+  }
+  Some((alloc_start - hole_start, back))
+}
+
+/// Round a requested size up to a size that can hold a hole once it is freed, including room for
+/// the header.
+fn block_size(size: usize) -> usize {
+  let size = align_up(size + HEADER_SIZE, BLOCK_ALIGN);
+  if size < MIN_BLOCK_SIZE { MIN_BLOCK_SIZE } else { size }
+}
+
+/// Align downwards. Returns the greatest x with alignment `align` so that x <= addr. The alignment
+/// must be a power of 2.
+pub fn align_down(addr: usize, align: usize) -> usize {
+  if align.is_power_of_two() {
+    addr & !(align - 1)
+  }
+  else if align == 0 {
+    addr
+  }
+  else {
+    panic!("align_down - `align` must be a power of 2");
+  }
+}
+
+/// Align upwards. Returns the smallest x with alignment `align` so that x >= addr. The alignment
+/// must be a power of 2.
+pub fn align_up(addr: usize, align: usize) -> usize {
+  align_down(addr + align - 1, align)
+}
+
+#[cfg(not(test))]
+#[no_mangle]
+pub extern fn __rust_allocate(size: usize, align: usize) -> *mut u8 {
+  unsafe {
+    let ptr = match HOOKS.and_then(|hooks| (hooks.allocate)(size, align)) {
+      Some(ptr) => ptr,
+      None => {
+        let _g = CriticalSection::begin();
+        FREE_LIST_ALLOCATOR.allocate(size, align).unwrap_or(ptr::null_mut())
+      },
+    };
+    if ptr.is_null() {
+      FAILED_REQUEST = (size, align);
+    }
+    ptr
+  }
+}
+
+#[cfg(not(test))]
+#[no_mangle]
+pub extern fn __rust_deallocate(ptr: *mut u8, size: usize, align: usize) {
+  unsafe {
+    if let Some(hooks) = HOOKS {
+      if (hooks.deallocate)(ptr, size, align) {
+        return;
+      }
+    }
+    let _g = CriticalSection::begin();
+    FREE_LIST_ALLOCATOR.deallocate(ptr, size);
+  }
+}
+
+#[cfg(not(test))]
+#[no_mangle]
+pub extern fn __rust_usable_size(size: usize, _align: usize) -> usize {
+  size
+}
+
+#[cfg(not(test))]
+#[no_mangle]
+pub extern fn __rust_reallocate_inplace(_ptr: *mut u8, size: usize, _new_size: usize, _align: usize) -> usize {
+  size
+}
+
+#[cfg(not(test))]
+#[no_mangle]
+pub extern fn __rust_reallocate(ptr: *mut u8, size: usize, new_size: usize, align: usize) -> *mut u8 {
+  use core::cmp;
+
+  let new_ptr = __rust_allocate(new_size, align);
+  if !new_ptr.is_null() {
+    unsafe { ptr::copy(ptr, new_ptr, cmp::min(size, new_size)) };
+    __rust_deallocate(ptr, size, align);
+  }
+  new_ptr
+}
@@ -0,0 +1,201 @@
+// tests.rs
+// AltOSRust
+//
+// Created by Daniel Seitz on 2/12/17
+
+#![cfg(test)]
+
+use super::*;
+use std::vec::Vec;
+
+const HEAP_SIZE: usize = 1024;
+
+fn heap() -> (FreeListAllocator, Vec<usize>) {
+  let mut memory: Vec<usize> = vec![0; HEAP_SIZE / mem::size_of::<usize>()];
+  let mut allocator = FreeListAllocator::new();
+  unsafe { allocator.init(memory.as_mut_ptr() as usize, HEAP_SIZE) };
+  (allocator, memory)
+}
+
+#[test]
+fn init_single_hole() {
+  let (allocator, _memory) = heap();
+
+  assert_eq!(allocator.free_bytes(), HEAP_SIZE);
+}
+
+#[test]
+fn allocate_smoke() {
+  let (mut allocator, memory) = heap();
+  let ptr = allocator.allocate(16, 4).unwrap();
+
+  assert_eq!(ptr as usize, memory.as_ptr() as usize + HEADER_SIZE);
+  assert_eq!(allocator.free_bytes(), HEAP_SIZE - block_size(16));
+}
+
+#[test]
+fn allocate_rounds_up_small_blocks() {
+  let (mut allocator, _memory) = heap();
+  let first = allocator.allocate(1, 1).unwrap();
+  let second = allocator.allocate(1, 1).unwrap();
+
+  assert_eq!(second as usize - first as usize, block_size(1));
+}
+
+#[test]
+fn allocate_aligns() {
+  let (mut allocator, _memory) = heap();
+  allocator.allocate(MIN_BLOCK_SIZE, 1).unwrap();
+  let aligned = allocator.allocate(16, 64).unwrap();
+
+  assert_eq!(aligned as usize % 64, 0);
+}
+
+#[test]
+fn allocate_out_of_memory() {
+  let (mut allocator, _memory) = heap();
+
+  assert!(allocator.allocate(HEAP_SIZE - HEADER_SIZE + 1, 1).is_none());
+  assert!(allocator.allocate(HEAP_SIZE - HEADER_SIZE, 1).is_some());
+  assert!(allocator.allocate(1, 1).is_none());
+}
+
+#[test]
+fn deallocate_reuses_memory() {
+  let (mut allocator, _memory) = heap();
+  let first = allocator.allocate(HEAP_SIZE - HEADER_SIZE, 1).unwrap();
+  unsafe { allocator.deallocate(first, HEAP_SIZE - HEADER_SIZE) };
+  let second = allocator.allocate(HEAP_SIZE - HEADER_SIZE, 1).unwrap();
+
+  assert_eq!(first, second);
+}
+
+#[test]
+fn deallocate_coalesces_neighbors() {
+  let (mut allocator, _memory) = heap();
+  let a = allocator.allocate(64, 4).unwrap();
+  let b = allocator.allocate(64, 4).unwrap();
+  let c = allocator.allocate(64, 4).unwrap();
+
+  // Free out of order so we merge with both the previous and the next hole
+  unsafe {
+    allocator.deallocate(a, 64);
+    allocator.deallocate(c, 64);
+    allocator.deallocate(b, 64);
+  }
+  assert_eq!(allocator.free_bytes(), HEAP_SIZE);
+
+  // Everything merged back together, so we should be able to get the whole heap again
+  assert!(allocator.allocate(HEAP_SIZE - HEADER_SIZE, 1).is_some());
+}
+
+#[test]
+fn allocate_first_fit() {
+  let (mut allocator, _memory) = heap();
+  let a = allocator.allocate(64, 4).unwrap();
+  let _b = allocator.allocate(64, 4).unwrap();
+  unsafe { allocator.deallocate(a, 64) };
+
+  // Fits in the hole left by `a`
+  let c = allocator.allocate(32, 4).unwrap();
+  assert_eq!(a, c);
+}
+
+#[test]
+fn allocate_and_free_many() {
+  let (mut allocator, _memory) = heap();
+  let mut blocks = Vec::new();
+  for size in 1..20 {
+    blocks.push((allocator.allocate(size, 4).unwrap(), size));
+  }
+  for &(ptr, size) in blocks.iter().rev() {
+    unsafe { allocator.deallocate(ptr, size) };
+  }
+
+  assert_eq!(allocator.free_bytes(), HEAP_SIZE);
+}
+
+#[test]
+fn stats_track_usage() {
+  let (mut allocator, _memory) = heap();
+  let a = allocator.allocate(64, 4).unwrap();
+  let b = allocator.allocate(32, 4).unwrap();
+  let stats = allocator.stats();
+
+  assert_eq!(stats.used, block_size(64) + block_size(32));
+  assert_eq!(stats.free, HEAP_SIZE - stats.used);
+  assert_eq!(stats.allocations, 2);
+  assert_eq!(stats.peak, stats.used);
+
+  unsafe { allocator.deallocate(a, 64) };
+  let stats = allocator.stats();
+  assert_eq!(stats.used, block_size(32));
+  assert_eq!(stats.allocations, 1);
+  assert_eq!(stats.peak, block_size(64) + block_size(32));
+
+  unsafe { allocator.deallocate(b, 32) };
+  assert_eq!(allocator.stats().used, 0);
+}
+
+#[test]
+fn stats_largest_free_block() {
+  let (mut allocator, _memory) = heap();
+  let a = allocator.allocate(64, 4).unwrap();
+  let _b = allocator.allocate(64, 4).unwrap();
+  unsafe { allocator.deallocate(a, 64) };
+  let stats = allocator.stats();
+
+  assert_eq!(stats.largest_free, HEAP_SIZE - 2 * block_size(64) - HEADER_SIZE);
+  // The largest free block really can be allocated
+  assert!(allocator.allocate(stats.largest_free, 1).is_some());
+}
+
+#[test]
+fn check_intact_heap() {
+  let (mut allocator, _memory) = heap();
+  let a = allocator.allocate(64, 4).unwrap();
+  let _b = allocator.allocate(16, 64).unwrap();
+  let _c = allocator.allocate(8, 4).unwrap();
+  unsafe { allocator.deallocate(a, 64) };
+
+  assert_eq!(allocator.check(), Ok(()));
+}
+
+#[test]
+fn check_detects_corrupt_free_list() {
+  let (mut allocator, memory) = heap();
+  let a = allocator.allocate(64, 4).unwrap();
+  let _b = allocator.allocate(64, 4).unwrap();
+  unsafe {
+    allocator.deallocate(a, 64);
+    // Trample the size of the hole left behind by `a`
+    *(memory.as_ptr() as *mut usize) = 2 * HEAP_SIZE;
+  }
+
+  assert_eq!(allocator.check(), Err(memory.as_ptr() as usize));
+}
+
+#[cfg(feature="debug")]
+#[test]
+fn check_detects_corrupt_header() {
+  let (mut allocator, _memory) = heap();
+  let _a = allocator.allocate(16, 4).unwrap();
+  let b = allocator.allocate(16, 4).unwrap();
+  // Overflow the end of `a` into the header of `b`
+  let header = unsafe { b.offset(-(HEADER_SIZE as isize)) };
+  unsafe { *header = 0xFF };
+
+  assert_eq!(allocator.check(), Err(header as usize));
+}
+
+#[cfg(feature="debug")]
+#[test]
+#[should_panic]
+fn deallocate_detects_corrupt_header() {
+  let (mut allocator, _memory) = heap();
+  let a = allocator.allocate(16, 4).unwrap();
+  unsafe {
+    *a.offset(-1) = 0xFF;
+    allocator.deallocate(a, 16);
+  }
+}
@@ -0,0 +1,321 @@
+// arch/host.rs
+// AltOS Rust
+//
+// Created by Daniel Seitz on 2/24/17
+
+//! This module lets the kernel run as a normal process on a desktop OS, for simulating
+//! applications off-target.
+//!
+//! Every task runs on its own OS thread, but only one of them ever holds the simulated CPU at a
+//! time. A context switch hands the CPU to the new task's thread and parks the old one until it is
+//! scheduled again. A separate thread plays the part of the tick timer, the ticks it generates are
+//! delivered the next time the running task enters the kernel or leaves a critical section, or
+//! right away if the idle task is waiting for an interrupt. Context switches requested inside a
+//! critical section are deferred until it ends, the same way PendSV waits on hardware.
+//!
+//! When a task is destroyed its thread unwinds and exits the next time it wakes up. Unlike on
+//! hardware the values on the task's stack are dropped as it unwinds, any calls into the kernel
+//! that they make are ignored since the thread no longer owns a task.
+//!
+//! # Limitations
+//!
+//! The simulation doesn't cover everything the hardware does:
+//!
+//! * A tick can only be delivered at the points above, so a task that spins without ever calling
+//!   into the kernel is never preempted. On hardware the tick interrupt preempts a task wherever it
+//!   is. Doing that to a thread would mean running the kernel from a signal handler, which isn't
+//!   safe while the interrupted thread could be holding the allocator's lock. Applications that
+//!   are simulated need to sleep, yield, lock or enter a critical section regularly.
+//! * Applications have to be written against `altos_core` alone. Anything that uses a port's
+//!   peripherals, like the application in `src/lib.rs`, only runs on the target.
+//! * Task arenas don't capture allocations since std provides the heap.
+
+use volatile::Volatile;
+use task::args::Args;
+use alloc::boxed::Box;
+use sched::{self, CURRENT_TASK};
+use syscall;
+use atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::cell::Cell;
+use std::vec::Vec;
+use std::panic::{self, AssertUnwindSafe};
+use std::sync::{Mutex, Condvar, Once, ONCE_INIT};
+use std::thread;
+use std::time::Duration;
+use super::Arch;
+
+/// The id of the context that the main thread runs in before the scheduler starts.
+const MAIN_CONTEXT: usize = 0;
+
+struct Turn {
+  /// The context that is allowed to run.
+  running: usize,
+  /// Contexts whose tasks have been destroyed, their threads haven't exited yet.
+  released: Vec<usize>,
+}
+
+/// The panic payload used to unwind the thread of a destroyed task.
+struct TaskExited;
+
+struct Cpu {
+  running: Mutex<Turn>,
+  turn: Condvar,
+  /// The number of ticks that have been generated but not delivered yet.
+  pending_ticks: Mutex<usize>,
+  tick: Condvar,
+}
+
+static CPU_INIT: Once = ONCE_INIT;
+static mut CPU: *const Cpu = 0 as *const Cpu;
+
+static NEXT_CONTEXT: AtomicUsize = AtomicUsize::new(MAIN_CONTEXT + 1);
+static STARTED: AtomicBool = AtomicBool::new(false);
+static SWITCH_PENDING: AtomicBool = AtomicBool::new(false);
+static TICK_PERIOD_US: AtomicUsize = AtomicUsize::new(1000);
+
+thread_local! {
+  static KERNEL_MODE: Cell<bool> = Cell::new(true);
+  static MASKED: Cell<bool> = Cell::new(false);
+  static EXITED: Cell<bool> = Cell::new(false);
+}
+
+fn cpu() -> &'static Cpu {
+  CPU_INIT.call_once(|| {
+    let cpu = Box::new(Cpu {
+      running: Mutex::new(Turn { running: MAIN_CONTEXT, released: Vec::new() }),
+      turn: Condvar::new(),
+      pending_ticks: Mutex::new(0),
+      tick: Condvar::new(),
+    });
+    // UNSAFE: Only written once, before anyone can read it
+    unsafe { CPU = Box::into_raw(cpu) };
+  });
+  // UNSAFE: Initialized above and never freed
+  unsafe { &*CPU }
+}
+
+/// Sets how often the simulated tick timer fires, in microseconds. The default is 1000.
+///
+/// This must be called before the scheduler is started.
+pub fn set_tick_period(micros: usize) {
+  TICK_PERIOD_US.store(micros, Ordering::SeqCst);
+}
+
+/// Lets the thread of a destroyed task exit, `stack_top` is the top of the task's stack.
+#[doc(hidden)]
+pub fn release_task(stack_top: *const usize) {
+  // UNSAFE: The context id was stored at the top of the task's stack by `initialize_stack`
+  let context = unsafe { *stack_top };
+  let cpu = cpu();
+  cpu.running.lock().unwrap().released.push(context);
+  cpu.turn.notify_all();
+}
+
+pub struct HostArch;
+
+impl Arch for HostArch {
+  fn yield_cpu() {
+    if exited() {
+      return;
+    }
+    SWITCH_PENDING.store(true, Ordering::SeqCst);
+    if !MASKED.with(|masked| masked.get()) {
+      run_pending();
+    }
+  }
+
+  fn initialize_stack(stack_ptr: Volatile<usize>, code: fn(&mut Args), args: &Box<Args>) -> usize {
+    let context = NEXT_CONTEXT.fetch_add(1, Ordering::SeqCst);
+    let args = &**args as *const Args as usize;
+    thread::Builder::new()
+      .name(format!("altos context {}", context))
+      .spawn(move || {
+        KERNEL_MODE.with(|kernel| kernel.set(false));
+        if !wait_turn(context) {
+          return;
+        }
+        let result = panic::catch_unwind(AssertUnwindSafe(|| {
+          // UNSAFE: The arguments live as long as the task does
+          code(unsafe { &mut *(args as *mut Args) });
+          syscall::exit();
+        }));
+        if let Err(payload) = result {
+          if !payload.is::<TaskExited>() {
+            panic::resume_unwind(payload);
+          }
+        }
+      })
+      .expect("initialize_stack - couldn't spawn a thread for the task!");
+
+    // The task's context id is the only thing we keep on its stack
+    unsafe {
+      stack_ptr.offset(-1).store(context);
+      stack_ptr.offset(-1).as_ptr() as usize
+    }
+  }
+
+  fn start_first_task() {
+    SWITCH_PENDING.store(false, Ordering::SeqCst);
+    STARTED.store(true, Ordering::SeqCst);
+
+    hand_over(current_context());
+    // The main thread has no task of its own, it never runs again
+    loop {
+      thread::park();
+    }
+  }
+
+  fn in_kernel_mode() -> bool {
+    KERNEL_MODE.with(|kernel| kernel.get())
+  }
+
+  fn begin_critical() -> usize {
+    MASKED.with(|masked| {
+      let old = masked.get();
+      masked.set(true);
+      old as usize
+    })
+  }
+
+  fn end_critical(mask: usize) {
+    if exited() {
+      return;
+    }
+    MASKED.with(|masked| masked.set(mask != 0));
+    if mask == 0 {
+      run_pending();
+    }
+  }
+
+  fn start_tick_source() {
+    let micros = TICK_PERIOD_US.load(Ordering::SeqCst);
+    let period = Duration::new((micros / 1_000_000) as u64, ((micros % 1_000_000) * 1000) as u32);
+    thread::Builder::new()
+      .name("altos tick".into())
+      .spawn(move || {
+        loop {
+          thread::sleep(period);
+          let cpu = cpu();
+          *cpu.pending_ticks.lock().unwrap() += 1;
+          cpu.tick.notify_all();
+        }
+      })
+      .expect("start_tick_source - couldn't spawn the tick thread!");
+  }
+
+  fn wait_for_interrupt() {
+    let cpu = cpu();
+    let mut pending = cpu.pending_ticks.lock().unwrap();
+    while *pending == 0 {
+      pending = cpu.tick.wait(pending).unwrap();
+    }
+  }
+
+  fn system_call(number: usize, args: [usize; 3]) -> usize {
+    if exited() {
+      return 0;
+    }
+    let old = Self::in_kernel_mode();
+    KERNEL_MODE.with(|kernel| kernel.set(true));
+    let result = syscall::svc::dispatch(number, args);
+    KERNEL_MODE.with(|kernel| kernel.set(old));
+    result
+  }
+}
+
+arch_port!(HostArch);
+
+/// Delivers any ticks and context switches that came in while the CPU was busy.
+///
+/// They're handled with interrupts "masked", so a critical section inside the tick handler doesn't
+/// deliver the next tick when it ends.
+fn run_pending() {
+  if !STARTED.load(Ordering::SeqCst) {
+    return;
+  }
+  MASKED.with(|masked| masked.set(true));
+  loop {
+    if take_tick() {
+      in_kernel(syscall::system_tick);
+    }
+    else if SWITCH_PENDING.swap(false, Ordering::SeqCst) {
+      in_kernel(switch);
+    }
+    else {
+      break;
+    }
+  }
+  MASKED.with(|masked| masked.set(false));
+}
+
+fn take_tick() -> bool {
+  let mut pending = cpu().pending_ticks.lock().unwrap();
+  if *pending > 0 {
+    *pending -= 1;
+    true
+  }
+  else {
+    false
+  }
+}
+
+fn in_kernel(f: fn()) {
+  let old = HostArch::in_kernel_mode();
+  KERNEL_MODE.with(|kernel| kernel.set(true));
+  f();
+  KERNEL_MODE.with(|kernel| kernel.set(old));
+}
+
+/// Picks the next task to run and hands it the CPU, this thread waits until it's picked again.
+///
+/// If the old task is destroyed instead this thread unwinds back to where it was spawned.
+fn switch() {
+  let old = current_context();
+  sched::switch_context();
+  let new = current_context();
+  if new != old {
+    hand_over(new);
+    if !wait_turn(old) {
+      EXITED.with(|exited| exited.set(true));
+      panic::resume_unwind(Box::new(TaskExited));
+    }
+  }
+}
+
+/// Returns true if this thread's task has been destroyed and the thread is unwinding.
+fn exited() -> bool {
+  EXITED.with(|exited| exited.get())
+}
+
+fn current_context() -> usize {
+  // UNSAFE: Accessing CURRENT_TASK, and the context id was stored at the top of the task's stack
+  // by `initialize_stack`
+  unsafe {
+    match CURRENT_TASK.as_ref() {
+      Some(task) => *task.stack_top(),
+      None => panic!("current_context - current task doesn't exist!"),
+    }
+  }
+}
+
+fn hand_over(context: usize) {
+  let cpu = cpu();
+  cpu.running.lock().unwrap().running = context;
+  cpu.turn.notify_all();
+}
+
+/// Waits until `context` is handed the CPU, returns false if its task is destroyed instead.
+fn wait_turn(context: usize) -> bool {
+  let cpu = cpu();
+  let mut turn = cpu.running.lock().unwrap();
+  loop {
+    if let Some(i) = turn.released.iter().position(|&released| released == context) {
+      turn.released.swap_remove(i);
+      return false;
+    }
+    if turn.running == context {
+      return true;
+    }
+    turn = cpu.turn.wait(turn).unwrap();
+  }
+}
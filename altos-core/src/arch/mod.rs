@@ -0,0 +1,218 @@
+// arch/mod.rs
+// AltOS Rust
+//
+// Created by Daniel Seitz on 2/25/17
+
+//! The architecture layer.
+//!
+//! The kernel itself doesn't know anything about the processor it runs on, everything that does is
+//! behind the `Arch` trait. A port implements `Arch` for a type of its own and then registers it
+//! with the `arch_port!` macro, which links the kernel's calls through to the implementation. Only
+//! one port can be registered in a program.
+//!
+//! # Examples
+//!
+//! ```rust,ignore
+//! #[macro_use]
+//! extern crate altos_core;
+//!
+//! use altos_core::arch::Arch;
+//!
+//! pub struct MyArch;
+//!
+//! impl Arch for MyArch {
+//!   // ...
+//! }
+//!
+//! arch_port!(MyArch);
+//! ```
+
+use volatile::Volatile;
+use task::args::Args;
+use alloc::boxed::Box;
+
+/// The operations the kernel needs from the processor it runs on.
+///
+/// None of these take `self`, an architecture is just a type to hang the functions off of.
+pub trait Arch {
+  /// Requests a context switch.
+  ///
+  /// The switch must not happen inside a critical section, it should be deferred until the
+  /// critical section ends. To switch, the arch saves the running task's context, calls
+  /// `switch_context` and then restores the context of the new `CURRENT_TASK`.
+  fn yield_cpu();
+
+  /// Sets up a new task's stack so that the first time it's switched to it starts running `code`
+  /// with `args`, returning the new top of the stack. If `code` returns the task should exit.
+  fn initialize_stack(stack_ptr: Volatile<usize>, code: fn(&mut Args), args: &Box<Args>) -> usize;
+
+  /// Starts running `CURRENT_TASK`, this is only called once by `start_scheduler` and never
+  /// returns on real hardware.
+  fn start_first_task();
+
+  /// Returns true if the kernel is running, rather than a task. Interrupt handlers count as the
+  /// kernel.
+  fn in_kernel_mode() -> bool;
+
+  /// Disables preemption and interrupts, returning a mask that restores the previous state.
+  fn begin_critical() -> usize;
+
+  /// Restores the state from before the matching `begin_critical`.
+  fn end_critical(mask: usize);
+
+  /// Starts the timer that calls `syscall::system_tick` every tick. This is called right before
+  /// the first task is started.
+  fn start_tick_source();
+
+  /// Puts the processor to sleep until an interrupt is pending, this is called by the idle task
+  /// with interrupts disabled. An arch that can't sleep can just return.
+  fn wait_for_interrupt();
+
+  /// Traps into the kernel to make syscall `number`, returning its result. This is only called
+  /// from a task.
+  ///
+  /// The arch's supervisor call handler passes the number and arguments on to
+  /// `syscall::svc::dispatch` in handler mode, and hands its result back to the task. If the arch
+  /// can't trap while interrupts are masked it should run the call through
+  /// `syscall::svc::dispatch_in_place` instead.
+  fn system_call(number: usize, args: [usize; 3]) -> usize;
+}
+
+/// Registers the type implementing `Arch` that the kernel should run on.
+///
+/// This must be used exactly once in a program, usually by the port crate.
+#[macro_export]
+macro_rules! arch_port {
+  ($arch:ty) => {
+    #[no_mangle]
+    #[doc(hidden)]
+    pub fn __altos_arch_yield_cpu() {
+      <$arch as $crate::arch::Arch>::yield_cpu()
+    }
+
+    #[no_mangle]
+    #[doc(hidden)]
+    pub fn __altos_arch_initialize_stack(stack_ptr: $crate::volatile::Volatile<usize>,
+                                         code: fn(&mut $crate::args::Args),
+                                         args: &$crate::alloc::boxed::Box<$crate::args::Args>)
+                                         -> usize {
+      <$arch as $crate::arch::Arch>::initialize_stack(stack_ptr, code, args)
+    }
+
+    #[no_mangle]
+    #[doc(hidden)]
+    pub fn __altos_arch_start_first_task() {
+      <$arch as $crate::arch::Arch>::start_first_task()
+    }
+
+    #[no_mangle]
+    #[doc(hidden)]
+    pub fn __altos_arch_in_kernel_mode() -> bool {
+      <$arch as $crate::arch::Arch>::in_kernel_mode()
+    }
+
+    #[no_mangle]
+    #[doc(hidden)]
+    pub fn __altos_arch_begin_critical() -> usize {
+      <$arch as $crate::arch::Arch>::begin_critical()
+    }
+
+    #[no_mangle]
+    #[doc(hidden)]
+    pub fn __altos_arch_end_critical(mask: usize) {
+      <$arch as $crate::arch::Arch>::end_critical(mask)
+    }
+
+    #[no_mangle]
+    #[doc(hidden)]
+    pub fn __altos_arch_start_tick_source() {
+      <$arch as $crate::arch::Arch>::start_tick_source()
+    }
+
+    #[no_mangle]
+    #[doc(hidden)]
+    pub fn __altos_arch_wait_for_interrupt() {
+      <$arch as $crate::arch::Arch>::wait_for_interrupt()
+    }
+
+    #[no_mangle]
+    #[doc(hidden)]
+    pub fn __altos_arch_system_call(number: usize, args: [usize; 3]) -> usize {
+      <$arch as $crate::arch::Arch>::system_call(number, args)
+    }
+  };
+}
+
+#[cfg(test)]
+mod test;
+#[cfg(test)]
+pub use self::test::set_kernel_mode;
+
+#[cfg(all(not(test), feature="host"))]
+mod host;
+#[cfg(all(not(test), feature="host"))]
+pub use self::host::set_tick_period;
+#[cfg(all(not(test), feature="host"))]
+#[doc(hidden)]
+pub use self::host::release_task;
+
+extern "Rust" {
+  fn __altos_arch_yield_cpu();
+  fn __altos_arch_initialize_stack(stack_ptr: Volatile<usize>, code: fn(&mut Args),
+                                   args: &Box<Args>) -> usize;
+  fn __altos_arch_start_first_task();
+  fn __altos_arch_in_kernel_mode() -> bool;
+  fn __altos_arch_begin_critical() -> usize;
+  fn __altos_arch_end_critical(mask: usize);
+  fn __altos_arch_start_tick_source();
+  fn __altos_arch_wait_for_interrupt();
+  fn __altos_arch_system_call(number: usize, args: [usize; 3]) -> usize;
+}
+
+// The rest of the kernel calls the registered port through these.
+// UNSAFE: The symbols are defined by `arch_port!`, which forwards them to a safe implementation
+
+#[doc(hidden)]
+pub fn yield_cpu() {
+  unsafe { __altos_arch_yield_cpu() }
+}
+
+#[doc(hidden)]
+pub fn initialize_stack(stack_ptr: Volatile<usize>, code: fn(&mut Args), args: &Box<Args>) -> usize {
+  unsafe { __altos_arch_initialize_stack(stack_ptr, code, args) }
+}
+
+#[doc(hidden)]
+pub fn start_first_task() {
+  unsafe { __altos_arch_start_first_task() }
+}
+
+#[doc(hidden)]
+pub fn in_kernel_mode() -> bool {
+  unsafe { __altos_arch_in_kernel_mode() }
+}
+
+#[doc(hidden)]
+pub fn begin_critical() -> usize {
+  unsafe { __altos_arch_begin_critical() }
+}
+
+#[doc(hidden)]
+pub fn end_critical(mask: usize) {
+  unsafe { __altos_arch_end_critical(mask) }
+}
+
+#[doc(hidden)]
+pub fn start_tick_source() {
+  unsafe { __altos_arch_start_tick_source() }
+}
+
+#[doc(hidden)]
+pub fn wait_for_interrupt() {
+  unsafe { __altos_arch_wait_for_interrupt() }
+}
+
+#[doc(hidden)]
+pub fn system_call(number: usize, args: [usize; 3]) -> usize {
+  unsafe { __altos_arch_system_call(number, args) }
+}
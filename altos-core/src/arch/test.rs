@@ -9,30 +9,67 @@ use volatile::Volatile;
 use task::args::Args;
 use alloc::boxed::Box;
 use sched;
+use syscall;
+use atomic::{AtomicBool, Ordering};
+use super::Arch;
 
-pub fn yield_cpu() {
-  // no-op
-  sched::switch_context();
-}
+static KERNEL_MODE: AtomicBool = AtomicBool::new(true);
+static MASKED: AtomicBool = AtomicBool::new(false);
 
-pub fn initialize_stack(stack_ptr: Volatile<usize>, _code: fn(&mut Args), _args: &Box<Args>) -> usize {
-  // no-op
-  stack_ptr.as_ptr() as usize
-}
+pub struct TestArch;
 
-pub fn start_first_task() {
-  // no-op
-}
-pub fn in_kernel_mode() -> bool {
-  // no-op
-  true
-}
+impl Arch for TestArch {
+  fn yield_cpu() {
+    // no-op
+    sched::switch_context();
+  }
+
+  fn initialize_stack(stack_ptr: Volatile<usize>, _code: fn(&mut Args), _args: &Box<Args>) -> usize {
+    // no-op
+    stack_ptr.as_ptr() as usize
+  }
+
+  fn start_first_task() {
+    // no-op
+  }
 
-pub fn begin_critical() -> usize {
-  // no-op
-  0
+  fn in_kernel_mode() -> bool {
+    KERNEL_MODE.load(Ordering::SeqCst)
+  }
+
+  fn begin_critical() -> usize {
+    MASKED.swap(true, Ordering::SeqCst) as usize
+  }
+
+  fn end_critical(mask: usize) {
+    MASKED.store(mask != 0, Ordering::SeqCst);
+  }
+
+  fn start_tick_source() {
+    // no-op, tests call system_tick themselves
+  }
+
+  fn wait_for_interrupt() {
+    // no-op
+  }
+
+  fn system_call(number: usize, args: [usize; 3]) -> usize {
+    // Like on the Cortex-M0, a task can't trap while it's in a critical section
+    if MASKED.load(Ordering::SeqCst) && !Self::in_kernel_mode() {
+      return syscall::svc::dispatch_in_place(number, args);
+    }
+    // Pretend to trap into the kernel
+    let old = Self::in_kernel_mode();
+    set_kernel_mode(true);
+    let result = syscall::svc::dispatch(number, args);
+    set_kernel_mode(old);
+    result
+  }
 }
 
-pub fn end_critical(_mask: usize) {
-  // no-op
+arch_port!(TestArch);
+
+/// Pretend to switch between running a task and running in the kernel.
+pub fn set_kernel_mode(kernel: bool) {
+  KERNEL_MODE.store(kernel, Ordering::SeqCst);
 }
@@ -0,0 +1,116 @@
+// heap.rs
+// AltOSRust
+//
+// Created by Daniel Seitz on 2/16/17
+
+//! Heap statistics, integrity checks, and out of memory handling.
+//!
+//! The statistics and checks query whichever allocator the kernel was built with. Building with the
+//! `heap_debug` feature makes the free list allocator put a canary in front of every allocation,
+//! which lets `check` find blocks that have been overwritten.
+
+use alloc::boxed::Box;
+use alloc::{self, heap};
+use core::{mem, ptr};
+
+static mut OOM_HANDLER: Option<fn(usize, usize)> = None;
+
+// We do this cfg for testing purposes, the allocator isn't linked in when running tests or on the
+// host, where std provides the heap.
+#[cfg(not(any(test, feature="host")))]
+pub use allocator::HeapStats;
+
+/// Returns the current usage statistics for the global heap.
+///
+/// Memory allocated from a task's arena is counted as part of the arena's own allocation, not
+/// individually.
+///
+/// # Examples
+///
+/// ```rust,no_run
+/// use altos_core::heap;
+///
+/// let stats = heap::stats();
+/// if stats.largest_free < 256 {
+///   // The heap is running low or is badly fragmented
+/// }
+/// ```
+#[cfg(not(any(test, feature="host")))]
+pub fn stats() -> HeapStats {
+  ::allocator::stats()
+}
+
+/// Verifies the integrity of the global heap.
+///
+/// If the heap has been corrupted the address of the first bad block is returned. How thorough the
+/// check is depends on the allocator, without the `heap_debug` feature allocated blocks can't be
+/// verified.
+#[cfg(not(any(test, feature="host")))]
+pub fn check() -> Result<(), usize> {
+  ::allocator::check_heap()
+}
+
+/// Sets a function to be called when the kernel runs out of memory.
+///
+/// The handler is passed the size and alignment of the allocation that failed. It can log the
+/// failure or reset the device, if it returns then the kernel aborts as usual.
+pub fn set_oom_handler(handler: fn(usize, usize)) {
+  // UNSAFE: Function pointers are written in a single store
+  unsafe { OOM_HANDLER = Some(handler) };
+}
+
+/// Reports that an allocation of `size` bytes aligned to `align` could not be served.
+///
+/// The handler set by `set_oom_handler` is called before aborting.
+pub fn out_of_memory(size: usize, align: usize) -> ! {
+  // UNSAFE: The handler is only ever written once during initialization
+  if let Some(handler) = unsafe { OOM_HANDLER } {
+    handler(size, align);
+  }
+  alloc::oom()
+}
+
+/// Tries to move `value` onto the heap.
+///
+/// Unlike `Box::new` this does not abort if the heap is out of memory, `value` is handed back as an
+/// `Err` instead.
+pub fn try_box<T>(value: T) -> Result<Box<T>, T> {
+  let size = mem::size_of::<T>();
+  if size == 0 {
+    return Ok(Box::new(value));
+  }
+  // UNSAFE: We allocate exactly enough memory for a `T` and move the value into it before handing
+  // ownership of the memory to the `Box`
+  unsafe {
+    let ptr = heap::allocate(size, mem::align_of::<T>()) as *mut T;
+    if ptr.is_null() {
+      return Err(value);
+    }
+    ptr::write(ptr, value);
+    Ok(Box::from_raw(ptr))
+  }
+}
+
+/// Called by the alloc crate when an allocation fails, like when `Box::new` runs out of memory.
+#[doc(hidden)]
+#[cfg(not(any(test, feature="host")))]
+pub fn alloc_oom() -> ! {
+  let (size, align) = ::allocator::failed_request();
+  out_of_memory(size, align)
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn try_box_smoke() {
+    let boxed = try_box([1usize; 4]).ok().unwrap();
+    assert_eq!(*boxed, [1; 4]);
+  }
+
+  #[test]
+  fn try_box_zero_sized() {
+    assert!(try_box(()).is_ok());
+  }
+}
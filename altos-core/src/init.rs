@@ -5,10 +5,12 @@
 
 // FIXME: Try to see if there's a better way to handle this for testing
 // We do this cfg for testing purposes, this allows doctests to run without any compilation errors.
-#![cfg(not(test))]
+#![cfg(not(any(test, feature="host")))]
 
 //! Contains functions used for initialization of the kernel
 
+pub use heap::set_oom_handler;
+
 /// Initialize the heap so memory can be dynamically allocated
 ///
 /// # Examples
@@ -37,5 +39,14 @@
 ///   }
 /// ```
 pub fn init_heap(heap_start: usize, heap_size: usize) {
+  use task::arena;
+  use alloc::oom;
+
   ::allocator::init_heap(heap_start, heap_size);
+  oom::set_oom_handler(::heap::alloc_oom);
+  // Route allocations made by tasks with an arena to their arenas
+  ::allocator::set_hooks(::allocator::AllocHooks {
+    allocate: arena::allocate,
+    deallocate: arena::deallocate,
+  });
 }
@@ -12,6 +12,9 @@
 #![feature(asm)]
 #![feature(naked_functions)]
 #![feature(const_fn)]
+#![feature(associated_consts)]
+#![feature(struct_field_attributes)]
+#![feature(stmt_expr_attributes)]
 #![feature(alloc)]
 #![feature(collections)]
 #![feature(drop_types_in_const)]
@@ -22,12 +25,17 @@
 #![deny(trivial_numeric_casts)]
 #![no_std]
 
-#[cfg(test)]
+#[cfg(any(test, feature="host"))]
 #[macro_use]
 extern crate std;
 
-#[cfg(all(not(test), feature="bump_allocator"))]
+// The free list allocator takes precedence so it can be enabled without turning off the default
+// features. Neither is used on the host, std provides the heap there.
+#[cfg(all(not(any(test, feature="host")), feature="bump_allocator",
+          not(feature="free_list_allocator")))]
 extern crate bump_allocator as allocator;
+#[cfg(all(not(any(test, feature="host")), feature="free_list_allocator"))]
+extern crate free_list_allocator as allocator;
 
 pub extern crate alloc;
 pub extern crate collections;
@@ -39,24 +47,48 @@ pub extern crate volatile;
 #[macro_use]
 mod test;
 
-#[cfg(all(not(test), target_arch="arm", feature="cm0"))]
-#[path = "arch/cm0.rs"]
-mod arch;
+#[macro_use]
+pub mod arch;
+#[macro_use]
+pub mod log;
+
+#[cfg(feature="trace")]
+pub mod trace;
 
-#[cfg(test)]
-#[path = "arch/test.rs"]
-mod arch;
+/// Records a kernel event in the trace buffer, compiles to nothing without the `trace` feature.
+#[cfg(feature="trace")]
+macro_rules! trace_event {
+  ($event:ident($arg:expr)) => { ::trace::record(::trace::Event::$event($arg)) };
+}
+
+#[cfg(not(feature="trace"))]
+macro_rules! trace_event {
+  ($event:ident($arg:expr)) => {};
+}
 
 pub mod tick;
-pub mod syscall;
+#[macro_use]
 mod task;
-mod sched;
+pub mod syscall;
+pub mod sched;
 pub mod sync;
 pub mod queue;
+#[macro_use]
+pub mod pool;
+pub mod heap;
 pub mod init;
+#[cfg(feature="watchdog")]
+pub mod watchdog;
+
+/// Controls for simulating the kernel as a host process with the `host` feature.
+#[cfg(all(not(test), feature="host"))]
+pub mod host {
+  pub use arch::set_tick_period;
+}
 
 #[cfg(target_has_atomic="ptr")]
 pub use core::sync::atomic as atomic;
-pub use task::{TaskHandle, Priority};
+pub use task::{TaskHandle, HandleError, Priority, StaticTask};
 pub use sched::{CURRENT_TASK, switch_context, start_scheduler};
 pub use task::args;
+pub use task::local;
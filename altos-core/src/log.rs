@@ -0,0 +1,479 @@
+// log.rs
+// AltOSRust
+//
+// Created by Daniel Seitz on 3/4/17
+
+//! Leveled logging with deferred output.
+//!
+//! The `error!`, `warn!`, `info!`, `debug!` and `trace!` macros format a message into a record in
+//! a fixed size ring buffer in RAM and return right away, they never wait on a device. A low
+//! priority logger task started with `start_logger` takes the records out of the buffer and hands
+//! them to a sink, like a serial port, when nothing more important is running. That keeps logging
+//! cheap enough to use from Critical tasks. Without a logger task the records just stay in RAM,
+//! where they can be read with a debugger or taken out with `pop`. Once the buffer is full the
+//! oldest records are overwritten.
+//!
+//! Levels can be turned off at compile time with the `max_level_*` features, a log statement above
+//! the maximum level compiles to nothing. At run time the level can be lowered further for
+//! everything with `set_level`, or for the modules under a path with `set_module_level`.
+//!
+//! # Examples
+//!
+//! ```rust,ignore
+//! #[macro_use]
+//! extern crate altos_core;
+//!
+//! use altos_core::log::{self, Level};
+//!
+//! log::set_module_level("app::radio", Level::Warn);
+//!
+//! info!("starting up with {} tasks", 4);
+//! ```
+
+use core::fmt::{self, Write};
+use core::str;
+use sync::{SpinMutex, CriticalSection};
+use atomic::{AtomicBool, AtomicUsize, ATOMIC_BOOL_INIT, Ordering};
+use task::args::Args;
+use task::{TaskHandle, Priority};
+use syscall;
+use tick;
+
+/// The number of records the log buffer can hold.
+pub const LOG_BUFFER_LEN: usize = 16;
+
+/// The number of bytes of a message that are kept, anything longer is cut off.
+pub const LOG_MESSAGE_LEN: usize = 64;
+
+/// The number of module filters that can be set at one time.
+pub const MAX_FILTERS: usize = 8;
+
+/// The most verbose level that is compiled in, as a number. Log statements above it are removed
+/// at compile time.
+#[cfg(feature="max_level_off")]
+pub const STATIC_MAX_LEVEL: usize = 0;
+#[cfg(all(not(feature="max_level_off"), feature="max_level_error"))]
+pub const STATIC_MAX_LEVEL: usize = 1;
+#[cfg(all(not(any(feature="max_level_off", feature="max_level_error")), feature="max_level_warn"))]
+pub const STATIC_MAX_LEVEL: usize = 2;
+#[cfg(all(not(any(feature="max_level_off", feature="max_level_error", feature="max_level_warn")),
+          feature="max_level_info"))]
+pub const STATIC_MAX_LEVEL: usize = 3;
+#[cfg(all(not(any(feature="max_level_off", feature="max_level_error", feature="max_level_warn",
+                  feature="max_level_info")),
+          feature="max_level_debug"))]
+pub const STATIC_MAX_LEVEL: usize = 4;
+#[cfg(not(any(feature="max_level_off", feature="max_level_error", feature="max_level_warn",
+              feature="max_level_info", feature="max_level_debug")))]
+pub const STATIC_MAX_LEVEL: usize = 5;
+
+/// How important a log record is, `Error` is the most important.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Level {
+  /// Something went wrong.
+  Error = 1,
+  /// Something might be wrong.
+  Warn,
+  /// Something normal but significant happened.
+  Info,
+  /// Details for debugging.
+  Debug,
+  /// Very fine grained details.
+  Trace,
+}
+
+impl fmt::Display for Level {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    let name = match *self {
+      Level::Error => "ERROR",
+      Level::Warn => "WARN",
+      Level::Info => "INFO",
+      Level::Debug => "DEBUG",
+      Level::Trace => "TRACE",
+    };
+    f.pad(name)
+  }
+}
+
+/// A message in the log buffer.
+#[derive(Copy, Clone)]
+pub struct Record {
+  /// How important the message is.
+  pub level: Level,
+  /// The tick the message was logged at.
+  pub tick: usize,
+  /// The path of the module that logged the message.
+  pub module: &'static str,
+  len: usize,
+  message: [u8; LOG_MESSAGE_LEN],
+}
+
+impl Record {
+  const EMPTY: Record = Record {
+    level: Level::Trace,
+    tick: 0,
+    module: "",
+    len: 0,
+    message: [0; LOG_MESSAGE_LEN],
+  };
+
+  /// Returns the formatted message, cut off at `LOG_MESSAGE_LEN` bytes.
+  pub fn message(&self) -> &str {
+    // UNSAFE: Only whole characters are ever copied into the message
+    unsafe { str::from_utf8_unchecked(&self.message[..self.len]) }
+  }
+}
+
+impl fmt::Display for Record {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    write!(f, "[{:>8}] {:<5} {}: {}", self.tick, self.level, self.module, self.message())
+  }
+}
+
+struct Buffer {
+  records: [Record; LOG_BUFFER_LEN],
+  head: usize,
+  len: usize,
+  lost: usize,
+}
+
+#[derive(Copy, Clone)]
+struct Filter {
+  prefix: &'static str,
+  level: usize,
+}
+
+static BUFFER: SpinMutex<Buffer> = SpinMutex::new(Buffer {
+  records: [Record::EMPTY; LOG_BUFFER_LEN],
+  head: 0,
+  len: 0,
+  lost: 0,
+});
+
+static FILTERS: SpinMutex<[Option<Filter>; MAX_FILTERS]> = SpinMutex::new([None; MAX_FILTERS]);
+static FILTER_COUNT: AtomicUsize = AtomicUsize::new(0);
+static MAX_LEVEL: AtomicUsize = AtomicUsize::new(STATIC_MAX_LEVEL);
+
+static LOGGER_WAITING: AtomicBool = ATOMIC_BOOL_INIT;
+static mut SINK: Option<fn(&Record)> = None;
+
+/// Sets the most verbose level that is logged, for modules without a filter of their own.
+pub fn set_level(level: Level) {
+  MAX_LEVEL.store(level as usize, Ordering::SeqCst);
+}
+
+/// Sets the most verbose level that is logged for the module at `path` and the modules under it.
+///
+/// If several filters match a module the one with the longest path is used. Setting a filter for a
+/// path that already has one replaces it. Returns false if `MAX_FILTERS` filters are already set.
+pub fn set_module_level(path: &'static str, level: Level) -> bool {
+  let _g = CriticalSection::begin();
+  let mut filters = FILTERS.lock();
+  let slot = match filters.iter().position(|f| f.map_or(false, |f| f.prefix == path)) {
+    Some(index) => index,
+    None => match filters.iter().position(|f| f.is_none()) {
+      Some(index) => {
+        FILTER_COUNT.fetch_add(1, Ordering::SeqCst);
+        index
+      },
+      None => return false,
+    },
+  };
+  filters[slot] = Some(Filter { prefix: path, level: level as usize });
+  true
+}
+
+/// Removes every module filter.
+pub fn clear_module_levels() {
+  let _g = CriticalSection::begin();
+  let mut filters = FILTERS.lock();
+  *filters = [None; MAX_FILTERS];
+  FILTER_COUNT.store(0, Ordering::SeqCst);
+}
+
+/// Returns true if a record at `level` from the module at `module` would be logged.
+pub fn enabled(level: Level, module: &str) -> bool {
+  let level = level as usize;
+  if level > STATIC_MAX_LEVEL {
+    return false;
+  }
+  if FILTER_COUNT.load(Ordering::SeqCst) == 0 {
+    return level <= MAX_LEVEL.load(Ordering::SeqCst);
+  }
+
+  let _g = CriticalSection::begin();
+  let filters = FILTERS.lock();
+  let mut best: Option<Filter> = None;
+  for filter in filters.iter().filter_map(|f| *f) {
+    let longer = best.map_or(true, |b| filter.prefix.len() > b.prefix.len());
+    if longer && matches(filter.prefix, module) {
+      best = Some(filter);
+    }
+  }
+  match best {
+    Some(filter) => level <= filter.level,
+    None => level <= MAX_LEVEL.load(Ordering::SeqCst),
+  }
+}
+
+/// Returns true if `module` is `prefix` or one of the modules under it.
+fn matches(prefix: &str, module: &str) -> bool {
+  module.starts_with(prefix) &&
+    (module.len() == prefix.len() || module[prefix.len()..].starts_with("::"))
+}
+
+/// Formats a record and adds it to the log buffer, this is used by the logging macros.
+#[doc(hidden)]
+pub fn __log(level: Level, module: &'static str, args: fmt::Arguments) {
+  // Format outside of the critical section so interrupts aren't held off while it happens
+  let mut record = Record::EMPTY;
+  record.level = level;
+  record.tick = tick::get_tick();
+  record.module = module;
+  {
+    let mut writer = MessageWriter { record: &mut record };
+    // A message that doesn't fit is just cut off
+    let _ = writer.write_fmt(args);
+  }
+
+  let _g = CriticalSection::begin();
+  {
+    let mut buffer = BUFFER.lock();
+    let tail = (buffer.head + buffer.len) % LOG_BUFFER_LEN;
+    buffer.records[tail] = record;
+    if buffer.len == LOG_BUFFER_LEN {
+      buffer.head = (buffer.head + 1) % LOG_BUFFER_LEN;
+      buffer.lost += 1;
+    }
+    else {
+      buffer.len += 1;
+    }
+  }
+  // Only pay for a wakeup when the logger task is actually waiting for one
+  if LOGGER_WAITING.swap(false, Ordering::SeqCst) {
+    syscall::wake(wchan());
+  }
+}
+
+/// Takes the oldest record out of the log buffer.
+pub fn pop() -> Option<Record> {
+  let _g = CriticalSection::begin();
+  let mut buffer = BUFFER.lock();
+  if buffer.len == 0 {
+    return None;
+  }
+  let record = buffer.records[buffer.head];
+  buffer.head = (buffer.head + 1) % LOG_BUFFER_LEN;
+  buffer.len -= 1;
+  Some(record)
+}
+
+/// Returns how many records have been overwritten since the last call, then resets the count.
+pub fn take_lost() -> usize {
+  let _g = CriticalSection::begin();
+  let mut buffer = BUFFER.lock();
+  ::core::mem::replace(&mut buffer.lost, 0)
+}
+
+/// Starts a Low priority task that passes every record to `sink` as it's logged.
+///
+/// The sink runs in the logger task, so it can block while writing to a device.
+///
+/// # Examples
+///
+/// ```rust,no_run
+/// use altos_core::log::{self, Record};
+///
+/// fn ram_sink(_record: &Record) {
+///   // Write the record somewhere...
+/// }
+///
+/// log::start_logger(ram_sink, 512);
+/// ```
+pub fn start_logger(sink: fn(&Record), stack_depth: usize) -> TaskHandle {
+  // UNSAFE: Function pointers are written in a single store, and this is done before the logger
+  // task starts
+  unsafe { SINK = Some(sink) };
+  syscall::new_task(logger_task, Args::empty(), stack_depth, Priority::Low, "logger")
+}
+
+fn logger_task(_args: &mut Args) {
+  loop {
+    while let Some(record) = pop() {
+      // UNSAFE: The sink is only written before the logger task is started
+      if let Some(sink) = unsafe { SINK } {
+        sink(&record);
+      }
+    }
+
+    let _g = CriticalSection::begin();
+    if BUFFER.lock().len == 0 {
+      LOGGER_WAITING.store(true, Ordering::SeqCst);
+      syscall::sleep(wchan());
+    }
+  }
+}
+
+fn wchan() -> usize {
+  &LOGGER_WAITING as *const _ as usize
+}
+
+/// Copies whole characters into a record's message until it's full.
+struct MessageWriter<'a> {
+  record: &'a mut Record,
+}
+
+impl<'a> Write for MessageWriter<'a> {
+  fn write_str(&mut self, s: &str) -> fmt::Result {
+    let room = LOG_MESSAGE_LEN - self.record.len;
+    let mut len = ::core::cmp::min(room, s.len());
+    while !s.is_char_boundary(len) {
+      len -= 1;
+    }
+    let start = self.record.len;
+    self.record.message[start..start + len].copy_from_slice(&s.as_bytes()[..len]);
+    self.record.len += len;
+    if len < s.len() {
+      Err(fmt::Error)
+    }
+    else {
+      Ok(())
+    }
+  }
+}
+
+/// Logs a message at the given level.
+///
+/// # Examples
+///
+/// ```rust,ignore
+/// use altos_core::log::Level;
+///
+/// log!(Level::Info, "{} bytes free", 128);
+/// ```
+#[macro_export]
+macro_rules! log {
+  ($level:expr, $($arg:tt)+) => {{
+    let level = $level;
+    if (level as usize) <= $crate::log::STATIC_MAX_LEVEL &&
+       $crate::log::enabled(level, module_path!()) {
+      $crate::log::__log(level, module_path!(), format_args!($($arg)+));
+    }
+  }};
+}
+
+/// Logs a message at the `Error` level.
+#[macro_export]
+macro_rules! error {
+  ($($arg:tt)+) => (log!($crate::log::Level::Error, $($arg)+));
+}
+
+/// Logs a message at the `Warn` level.
+#[macro_export]
+macro_rules! warn {
+  ($($arg:tt)+) => (log!($crate::log::Level::Warn, $($arg)+));
+}
+
+/// Logs a message at the `Info` level.
+#[macro_export]
+macro_rules! info {
+  ($($arg:tt)+) => (log!($crate::log::Level::Info, $($arg)+));
+}
+
+/// Logs a message at the `Debug` level.
+#[macro_export]
+macro_rules! debug {
+  ($($arg:tt)+) => (log!($crate::log::Level::Debug, $($arg)+));
+}
+
+/// Logs a message at the `Trace` level.
+#[macro_export]
+macro_rules! trace {
+  ($($arg:tt)+) => (log!($crate::log::Level::Trace, $($arg)+));
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use test;
+
+  fn reset() {
+    while pop().is_some() {}
+    take_lost();
+    clear_module_levels();
+    set_level(Level::Trace);
+  }
+
+  #[test]
+  fn log_and_pop() {
+    let _g = test::set_up();
+    reset();
+    info!("{} + {} = {}", 1, 2, 3);
+    warn!("careful");
+
+    let record = pop().unwrap();
+    assert_eq!(record.level, Level::Info);
+    assert_eq!(record.message(), "1 + 2 = 3");
+    assert_eq!(record.module, module_path!());
+    assert_eq!(pop().unwrap().message(), "careful");
+    assert!(pop().is_none());
+  }
+
+  #[test]
+  fn long_message_cut_off() {
+    let _g = test::set_up();
+    reset();
+    error!("{:>100}", "end");
+
+    let record = pop().unwrap();
+    assert_eq!(record.message().len(), LOG_MESSAGE_LEN);
+  }
+
+  #[test]
+  fn overwrites_oldest() {
+    let _g = test::set_up();
+    reset();
+    for i in 0..LOG_BUFFER_LEN + 1 {
+      info!("{}", i);
+    }
+
+    assert_eq!(pop().unwrap().message(), "1");
+    assert_eq!(take_lost(), 1);
+  }
+
+  #[test]
+  fn global_level() {
+    let _g = test::set_up();
+    reset();
+    set_level(Level::Warn);
+    info!("dropped");
+    error!("kept");
+
+    assert_eq!(pop().unwrap().message(), "kept");
+    assert!(pop().is_none());
+    reset();
+  }
+
+  #[test]
+  fn module_filters() {
+    let _g = test::set_up();
+    reset();
+    set_level(Level::Error);
+    assert!(set_module_level("app::net", Level::Debug));
+    assert!(set_module_level("app::net::tcp", Level::Warn));
+
+    assert!(enabled(Level::Debug, "app::net"));
+    assert!(enabled(Level::Debug, "app::net::udp"));
+    assert_not!(enabled(Level::Info, "app::net::tcp"));
+    assert!(enabled(Level::Warn, "app::net::tcp::socket"));
+    assert_not!(enabled(Level::Warn, "app::network"));
+    assert_not!(enabled(Level::Warn, "app"));
+    reset();
+  }
+
+  #[test]
+  fn level_order() {
+    assert!(Level::Error < Level::Warn);
+    assert!(Level::Debug < Level::Trace);
+  }
+}
@@ -0,0 +1,420 @@
+// pool.rs
+// AltOSRust
+//
+// Created by Daniel Seitz on 2/15/17
+
+//! Fixed-size block memory pools.
+//!
+//! A `Pool` hands out blocks of memory that are each big enough to hold a single `T`. All of the
+//! memory for the pool is reserved statically, so allocating from a pool never touches the heap.
+//! Allocating and freeing a block is O(1), and `try_alloc` never blocks, so pools are safe to use
+//! from interrupt handlers. The one exception is freeing a block while a task is blocked waiting
+//! for one, then the waiting task has to be woken too. Declare a pool with the `pool!` macro.
+//!
+//! ```rust,no_run
+//! #[macro_use]
+//! extern crate altos_core;
+//!
+//! struct Message {
+//!   id: usize,
+//!   data: [u8; 8],
+//! }
+//!
+//! pool!(static MESSAGES: Message; 8);
+//!
+//! # fn main() {
+//! // Blocks until a message is available
+//! let msg = MESSAGES.alloc(Message { id: 0, data: [0; 8] });
+//! // The block is returned to the pool when `msg` is dropped
+//! # }
+//! ```
+
+use sync::CriticalSection;
+use atomic::{AtomicBool, ATOMIC_BOOL_INIT, Ordering};
+use core::cell::UnsafeCell;
+use core::marker::PhantomData;
+use core::ops::{Deref, DerefMut};
+use core::{mem, ptr};
+use syscall;
+use tick;
+
+/// A pool of fixed-size memory blocks.
+///
+/// The type parameter `S` is the type of the memory backing the pool, it must be at least large
+/// enough to hold the number of blocks the pool was created with. Declare these with the `pool!`
+/// macro rather than constructing them directly.
+pub struct Pool<T, S: 'static> {
+  free: FreeList,
+  initialized: AtomicBool,
+  capacity: usize,
+  storage: UnsafeCell<S>,
+  _marker: PhantomData<T>,
+}
+
+unsafe impl<T: Send, S: 'static> Sync for Pool<T, S> {}
+
+/// A free block in the pool, stored in the block itself.
+struct FreeBlock {
+  next: *mut FreeBlock,
+}
+
+/// The list of free blocks in a pool.
+///
+/// This is kept separate from the `Pool` so that a `PoolBox` can return its block without knowing
+/// what kind of storage the pool uses.
+struct FreeList {
+  head: UnsafeCell<*mut FreeBlock>,
+  available: UnsafeCell<usize>,
+  waiting: UnsafeCell<bool>,
+}
+
+impl FreeList {
+  /// Takes a block off of the list.
+  ///
+  /// Must be called from within a critical section.
+  unsafe fn pop(&self) -> Option<*mut FreeBlock> {
+    let block = *self.head.get();
+    if block.is_null() {
+      None
+    }
+    else {
+      *self.head.get() = (*block).next;
+      *self.available.get() -= 1;
+      Some(block)
+    }
+  }
+
+  /// Puts a block back onto the list.
+  ///
+  /// Must be called from within a critical section.
+  unsafe fn push(&self, block: *mut FreeBlock) {
+    (*block).next = *self.head.get();
+    *self.head.get() = block;
+    *self.available.get() += 1;
+  }
+
+  /// Puts the current task to sleep until a block is freed.
+  ///
+  /// Must be called from within a critical section, so a block can't be freed between checking
+  /// the list and going to sleep.
+  unsafe fn wait(&self, timeout: Option<usize>) {
+    *self.waiting.get() = true;
+    match timeout {
+      Some(ticks) => syscall::sleep_for(self.wchan(), ticks),
+      None => syscall::sleep(self.wchan()),
+    }
+  }
+
+  fn wchan(&self) -> usize {
+    self as *const _ as usize
+  }
+}
+
+impl<T, S: 'static> Pool<T, S> {
+  /// Creates a new pool that can hold up to `capacity` blocks, using `storage` as the pool's memory.
+  pub const fn new(storage: S, capacity: usize) -> Self {
+    Pool {
+      free: FreeList {
+        head: UnsafeCell::new(ptr::null_mut()),
+        available: UnsafeCell::new(0),
+        waiting: UnsafeCell::new(false),
+      },
+      initialized: ATOMIC_BOOL_INIT,
+      capacity: capacity,
+      storage: UnsafeCell::new(storage),
+      _marker: PhantomData,
+    }
+  }
+
+  /// Tries to allocate a block from the pool without blocking.
+  ///
+  /// If there is a free block `value` is moved into it, otherwise `value` is handed back as an
+  /// `Err`. This method is safe to call from an interrupt handler.
+  pub fn try_alloc(&'static self, value: T) -> Result<PoolBox<T>, T> {
+    let _g = CriticalSection::begin();
+    self.initialize();
+    // UNSAFE: We're in a critical section so we have exclusive access to the free list
+    unsafe {
+      match self.free.pop() {
+        Some(block) => {
+          let data = block as *mut T;
+          ptr::write(data, value);
+          Ok(PoolBox { data: data, free: &self.free })
+        },
+        None => Err(value),
+      }
+    }
+  }
+
+  /// Allocates a block from the pool, blocking until one is available.
+  ///
+  /// If there are no free blocks the task is put to sleep until another task frees one. This
+  /// method must not be called from an interrupt handler.
+  pub fn alloc(&'static self, value: T) -> PoolBox<T> {
+    let mut value = value;
+    loop {
+      let _g = CriticalSection::begin();
+      match self.try_alloc(value) {
+        Ok(block) => return block,
+        Err(returned) => value = returned,
+      }
+      // UNSAFE: We're in a critical section
+      unsafe { self.free.wait(None) };
+    }
+  }
+
+  /// Allocates a block from the pool, blocking for at most `timeout` ticks.
+  ///
+  /// If no block becomes available before the timeout then `value` is handed back as an `Err`.
+  /// This method must not be called from an interrupt handler.
+  pub fn alloc_timeout(&'static self, value: T, timeout: usize) -> Result<PoolBox<T>, T> {
+    let start = tick::get_tick();
+    let mut value = value;
+    loop {
+      let _g = CriticalSection::begin();
+      match self.try_alloc(value) {
+        Ok(block) => return Ok(block),
+        Err(returned) => value = returned,
+      }
+      let elapsed = tick::get_tick().wrapping_sub(start);
+      if elapsed >= timeout {
+        return Err(value);
+      }
+      // UNSAFE: We're in a critical section
+      unsafe { self.free.wait(Some(timeout - elapsed)) };
+    }
+  }
+
+  /// Returns the number of blocks that are currently free.
+  pub fn available(&self) -> usize {
+    let _g = CriticalSection::begin();
+    self.initialize();
+    // UNSAFE: We're in a critical section so we have exclusive access
+    unsafe { *self.free.available.get() }
+  }
+
+  /// Returns the total number of blocks in the pool.
+  pub fn capacity(&self) -> usize {
+    self.capacity
+  }
+
+  /// Threads the free list through the pool's memory the first time the pool is used.
+  ///
+  /// Must be called from within a critical section.
+  fn initialize(&self) {
+    if self.initialized.load(Ordering::Relaxed) {
+      return;
+    }
+    let block_size = block_size::<T>();
+    let start = align_up(self.storage.get() as usize, block_align::<T>());
+    let end = self.storage.get() as usize + mem::size_of::<S>();
+    if start + block_size * self.capacity > end {
+      panic!("Pool::initialize - pool storage is too small for its capacity!");
+    }
+
+    // UNSAFE: We're in a critical section and the memory isn't in use yet, and we've just checked
+    // that all the blocks fit in the storage
+    unsafe {
+      for i in (0..self.capacity).rev() {
+        self.free.push((start + i * block_size) as *mut FreeBlock);
+      }
+    }
+    self.initialized.store(true, Ordering::Relaxed);
+  }
+}
+
+/// A pointer to a value allocated from a `Pool`.
+///
+/// The value is dropped and its block is returned to the pool when the `PoolBox` goes out of scope.
+pub struct PoolBox<T: 'static> {
+  data: *mut T,
+  free: &'static FreeList,
+}
+
+unsafe impl<T: Send> Send for PoolBox<T> {}
+unsafe impl<T: Sync> Sync for PoolBox<T> {}
+
+impl<T> Deref for PoolBox<T> {
+  type Target = T;
+
+  fn deref(&self) -> &T {
+    // UNSAFE: The block is owned by this `PoolBox` until it's dropped
+    unsafe { &*self.data }
+  }
+}
+
+impl<T> DerefMut for PoolBox<T> {
+  fn deref_mut(&mut self) -> &mut T {
+    // UNSAFE: The block is owned by this `PoolBox` until it's dropped
+    unsafe { &mut *self.data }
+  }
+}
+
+impl<T> Drop for PoolBox<T> {
+  fn drop(&mut self) {
+    // UNSAFE: The block holds a valid `T` that we own, once it's dropped we give the block back
+    let waiting = unsafe { 
+      ptr::drop_in_place(self.data);
+      let _g = CriticalSection::begin();
+      self.free.push(self.data as *mut FreeBlock);
+      mem::replace(&mut *self.free.waiting.get(), false)
+    };
+    // Only pay for a wakeup when a task is actually waiting for a block
+    if waiting {
+      syscall::wake(self.free.wchan());
+    }
+  }
+}
+
+fn block_size<T>() -> usize {
+  let size = if mem::size_of::<T>() > mem::size_of::<FreeBlock>() {
+    mem::size_of::<T>()
+  }
+  else {
+    mem::size_of::<FreeBlock>()
+  };
+  align_up(size, block_align::<T>())
+}
+
+fn block_align<T>() -> usize {
+  if mem::align_of::<T>() > mem::align_of::<FreeBlock>() {
+    mem::align_of::<T>()
+  }
+  else {
+    mem::align_of::<FreeBlock>()
+  }
+}
+
+fn align_up(addr: usize, align: usize) -> usize {
+  (addr + align - 1) & !(align - 1)
+}
+
+/// Declares a new `Pool` that can hold a fixed number of values of a type.
+///
+/// Enough memory is reserved for the requested number of blocks, along with any padding needed to
+/// align them.
+#[macro_export]
+macro_rules! pool {
+  ($(#[$attr:meta])* static $name:ident: $t:ty; $n:expr) => {
+    $(#[$attr])*
+    static $name: $crate::pool::Pool<$t, [usize; pool!(@words $t; $n)]> =
+      $crate::pool::Pool::new([0; pool!(@words $t; $n)], $n);
+  };
+  ($(#[$attr:meta])* pub static $name:ident: $t:ty; $n:expr) => {
+    $(#[$attr])*
+    pub static $name: $crate::pool::Pool<$t, [usize; pool!(@words $t; $n)]> =
+      $crate::pool::Pool::new([0; pool!(@words $t; $n)], $n);
+  };
+  (@words $t:ty; $n:expr) => {
+    // Each block is at most one word bigger than the type, plus some room to align the first block
+    ($n * (::core::mem::size_of::<$t>() + ::core::mem::size_of::<usize>()) +
+      ::core::mem::align_of::<$t>()) / ::core::mem::size_of::<usize>() + 1
+  };
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use atomic::{AtomicUsize, ATOMIC_USIZE_INIT};
+  use task::State;
+  use sched;
+  use test;
+
+  #[test]
+  fn pool_alloc_smoke() {
+    pool!(static POOL: usize; 4);
+
+    assert_eq!(POOL.capacity(), 4);
+    assert_eq!(POOL.available(), 4);
+    let mut block = POOL.try_alloc(100).ok().unwrap();
+    assert_eq!(*block, 100);
+    *block = 200;
+    assert_eq!(*block, 200);
+    assert_eq!(POOL.available(), 3);
+
+    drop(block);
+    assert_eq!(POOL.available(), 4);
+  }
+
+  #[test]
+  fn pool_exhausted() {
+    pool!(static POOL: u8; 2);
+
+    let first = POOL.try_alloc(1).ok().unwrap();
+    let second = POOL.try_alloc(2).ok().unwrap();
+    assert_eq!(POOL.try_alloc(3).err(), Some(3));
+
+    drop(first);
+    let third = POOL.try_alloc(3).ok().unwrap();
+    assert_eq!(*second, 2);
+    assert_eq!(*third, 3);
+  }
+
+  #[test]
+  fn pool_blocks_are_distinct() {
+    pool!(static POOL: [u32; 3]; 3);
+
+    let a = POOL.try_alloc([1; 3]).ok().unwrap();
+    let b = POOL.try_alloc([2; 3]).ok().unwrap();
+    let c = POOL.try_alloc([3; 3]).ok().unwrap();
+    assert_eq!(*a, [1; 3]);
+    assert_eq!(*b, [2; 3]);
+    assert_eq!(*c, [3; 3]);
+  }
+
+  #[test]
+  fn pool_blocks_are_aligned() {
+    pool!(static POOL: u64; 4);
+
+    let a = POOL.try_alloc(1).ok().unwrap();
+    let b = POOL.try_alloc(2).ok().unwrap();
+    assert_eq!(&*a as *const u64 as usize % mem::align_of::<u64>(), 0);
+    assert_eq!(&*b as *const u64 as usize % mem::align_of::<u64>(), 0);
+  }
+
+  #[test]
+  fn pool_drops_values() {
+    static DROPPED: AtomicUsize = ATOMIC_USIZE_INIT;
+    struct DropCounter;
+    impl Drop for DropCounter {
+      fn drop(&mut self) {
+        DROPPED.fetch_add(1, Ordering::SeqCst);
+      }
+    }
+    pool!(static POOL: DropCounter; 2);
+
+    let block = POOL.try_alloc(DropCounter).ok().unwrap();
+    assert_eq!(DROPPED.load(Ordering::SeqCst), 0);
+    drop(block);
+    assert_eq!(DROPPED.load(Ordering::SeqCst), 1);
+  }
+
+  #[test]
+  fn pool_alloc_timeout_available() {
+    pool!(static POOL: usize; 1);
+
+    let block = POOL.alloc_timeout(10, 5);
+    assert!(block.is_ok());
+  }
+
+  #[test]
+  fn pool_free_wakes_waiter() {
+    pool!(static POOL: usize; 1);
+    let _g = test::set_up();
+    let (handle_1, _handle_2) = test::create_two_tasks();
+    sched::start_scheduler();
+
+    let block = POOL.try_alloc(1).ok().unwrap();
+    {
+      let _g = CriticalSection::begin();
+      // UNSAFE: We're in a critical section
+      unsafe { POOL.free.wait(None) };
+    }
+    assert_eq!(handle_1.state(), Ok(State::Blocked));
+
+    drop(block);
+    assert_eq!(handle_1.state(), Ok(State::Ready));
+    // UNSAFE: The test has the only reference to the pool
+    assert_not!(unsafe { *POOL.free.waiting.get() });
+  }
+}
@@ -3,8 +3,7 @@
 //
 // Created by Daniel Seitz on 12/3/16
 //! A synchronized wrapper around the Queue struct.
-use queue::{Queue, Node};
-use alloc::boxed::Box;
+use queue::{Queue, NodePtr};
 use sync::{SpinMutex, SpinGuard};
 
 /// A queue that is wrapped in a mutex lock.
@@ -31,13 +30,13 @@ impl<T> SyncQueue<T> {
   }
 
   /// Places an item onto the back of the queue.
-  pub fn enqueue(&self, elem: Box<Node<T>>) {
+  pub fn enqueue<N: Into<NodePtr<T>>>(&self, elem: N) {
     let mut queue = self.lock();
     queue.enqueue(elem);
   }
 
   /// Takes an item off of the front of the queue.
-  pub fn dequeue(&self) -> Option<Box<Node<T>>> {
+  pub fn dequeue(&self) -> Option<NodePtr<T>> {
     let mut queue = self.lock();
     queue.dequeue()
   }
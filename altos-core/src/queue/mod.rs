@@ -24,7 +24,7 @@ use core::ops::{Deref, DerefMut};
 #[repr(C)]
 pub struct Node<T> {
   data: T,
-  next: Option<Box<Node<T>>>,
+  next: Option<NodePtr<T>>,
 }
 
 impl<T> Node<T> {
@@ -61,3 +61,64 @@ impl<T> DerefMut for Node<T> {
     &mut self.data
   }
 }
+
+/// An owning pointer to a `Node<T>`, this is how nodes are held by AltOSRust collections.
+///
+/// Most nodes are allocated on the heap, but a node can also live in static memory that must never
+/// be freed. Dropping a `NodePtr` frees a heap node along with its data, a static node is left
+/// untouched.
+pub struct NodePtr<T>(Storage<T>);
+
+enum Storage<T> {
+  Owned(Box<Node<T>>),
+  Static(*mut Node<T>),
+}
+
+// A static node is only reachable through the one `NodePtr` made from it, so it's as safe to send
+// as a `Box`
+unsafe impl<T: Send> Send for NodePtr<T> {}
+
+impl<T> NodePtr<T> {
+  /// Returns true if the node lives in static memory.
+  pub fn is_static(&self) -> bool {
+    match self.0 {
+      Storage::Owned(_) => false,
+      Storage::Static(_) => true,
+    }
+  }
+}
+
+impl<T> From<Box<Node<T>>> for NodePtr<T> {
+  fn from(node: Box<Node<T>>) -> Self {
+    NodePtr(Storage::Owned(node))
+  }
+}
+
+impl<T: 'static> From<&'static mut Node<T>> for NodePtr<T> {
+  fn from(node: &'static mut Node<T>) -> Self {
+    NodePtr(Storage::Static(node))
+  }
+}
+
+impl<T> Deref for NodePtr<T> {
+  type Target = Node<T>;
+
+  fn deref(&self) -> &Self::Target {
+    match self.0 {
+      Storage::Owned(ref node) => node,
+      // UNSAFE: The pointer came from a `&'static mut`, so it's valid forever and nothing else
+      // refers to the node
+      Storage::Static(node) => unsafe { &*node },
+    }
+  }
+}
+
+impl<T> DerefMut for NodePtr<T> {
+  fn deref_mut(&mut self) -> &mut Self::Target {
+    match self.0 {
+      Storage::Owned(ref mut node) => node,
+      // UNSAFE: See `deref`
+      Storage::Static(node) => unsafe { &mut *node },
+    }
+  }
+}
@@ -5,12 +5,11 @@
 
 //! A collection of items that can be accessed through a FIFO interface.
 
-use alloc::boxed::Box;
-use super::Node;
+use super::{Node, NodePtr};
 
 /// A collection that provides FIFO queue functionality.
 pub struct Queue<T> {
-  head: Option<Box<Node<T>>>,
+  head: Option<NodePtr<T>>,
   tail: *mut Node<T>,
 }
 
@@ -45,8 +44,8 @@ impl<T> Queue<T> {
   ///
   /// queue.enqueue(Box::new(Node::new(0)));
   /// ```
-  pub fn enqueue(&mut self, elem: Box<Node<T>>) {
-    let mut new_tail = elem;
+  pub fn enqueue<N: Into<NodePtr<T>>>(&mut self, elem: N) {
+    let mut new_tail = elem.into();
     // Probably not necessary...
     new_tail.next = None;
 
@@ -82,7 +81,7 @@ impl<T> Queue<T> {
   /// assert!(queue.dequeue().is_some());
   /// assert!(queue.dequeue().is_none());
   /// ```
-  pub fn dequeue(&mut self) -> Option<Box<Node<T>>> {
+  pub fn dequeue(&mut self) -> Option<NodePtr<T>> {
     self.head.take().map(|mut head| {
       self.head = head.next.take();
       if self.head.is_none() {
@@ -319,7 +318,7 @@ impl<T> Drop for Queue<T> {
 pub struct IntoIter<T>(Queue<T>);
 
 impl<T> Iterator for IntoIter<T> {
-  type Item = Box<Node<T>>;
+  type Item = NodePtr<T>;
   fn next(&mut self) -> Option<Self::Item> {
     self.0.dequeue()
   }
@@ -9,15 +9,14 @@
 //! of having the collection sorted is not worth the overhead, think of using the unsorted
 //! collections.
 
-use super::Node;
-use alloc::boxed::Box;
+use super::{Node, NodePtr};
 
 /// A list where every insertion is in sorted order.
 ///
 /// The list will ensure that every item inserted into it goes in its proper place. This requires
 /// that the generic type wrapped by the list is `PartialOrd` so the values can be compared.
 pub struct SortedList<T: PartialOrd> {
-  head: Option<Box<Node<T>>>,
+  head: Option<NodePtr<T>>,
 }
 
 impl<T: PartialOrd> SortedList<T> {
@@ -51,7 +50,8 @@ impl<T: PartialOrd> SortedList<T> {
   /// list.insert(Box::new(Node::new(1)));
   /// list.insert(Box::new(Node::new(0)));
   /// ```
-  pub fn insert(&mut self, mut elem: Box<Node<T>>) {
+  pub fn insert<N: Into<NodePtr<T>>>(&mut self, elem: N) {
+    let mut elem = elem.into();
     if self.head.is_none() || **elem <= ***self.head.as_ref().unwrap() {
       elem.next = self.head.take();
       self.head = Some(elem);
@@ -90,7 +90,7 @@ impl<T: PartialOrd> SortedList<T> {
   ///
   /// assert_eq!(list.pop().map(|n| **n), Some(0));
   /// ```
-  pub fn pop(&mut self) -> Option<Box<Node<T>>> {
+  pub fn pop(&mut self) -> Option<NodePtr<T>> {
     match self.head.take() {
       Some(mut head) => {
         self.head = head.next.take();
@@ -301,7 +301,7 @@ impl<T: PartialOrd> Drop for SortedList<T> {
 pub struct IntoIter<T: PartialOrd>(SortedList<T>);
 
 impl<T: PartialOrd> Iterator for IntoIter<T> {
-  type Item = Box<Node<T>>;
+  type Item = NodePtr<T>;
   fn next(&mut self) -> Option<Self::Item> {
     self.0.pop()
   }
@@ -0,0 +1,230 @@
+// sched/edf.rs
+// AltOSRust
+//
+// Created by Daniel Seitz on 2/20/17
+
+//! Earliest deadline first scheduling.
+//!
+//! Tasks in the EDF scheduling class declare a period and a relative deadline instead of relying
+//! only on a fixed priority. Each period a new job of the task is released, and it must finish
+//! (by calling `syscall::wait_next_period`) before its deadline. Whenever there is a ready EDF
+//! task the scheduler runs the one with the nearest absolute deadline, EDF tasks always run before
+//! any fixed priority task.
+//!
+//! If a task is still running when its deadline passes the deadline miss hook is called, once per
+//! missed job.
+
+use task::TaskControl;
+use queue::{SyncQueue, Queue, NodePtr};
+use core::cell::Cell;
+
+static mut MISS_HOOK: Option<fn(DeadlineMiss)> = None;
+
+/// The timing parameters of a task in the EDF scheduling class.
+///
+/// All times are measured in system ticks.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct Deadline {
+  /// The number of ticks between releases of the task.
+  pub period: usize,
+  /// How many ticks after its release the task must be finished by.
+  pub relative: usize,
+  /// The tick the current job of the task was released at.
+  pub release: usize,
+  /// The tick the current job of the task must be finished by.
+  pub absolute: usize,
+  missed: bool,
+}
+
+impl Deadline {
+  /// Creates the timing parameters for a task whose first job is released at `now`.
+  pub fn new(period: usize, relative: usize, now: usize) -> Self {
+    Deadline {
+      period: period,
+      relative: relative,
+      release: now,
+      absolute: now.wrapping_add(relative),
+      missed: false,
+    }
+  }
+
+  /// Moves on to the next job of the task, returning the tick it will be released at.
+  pub fn next_period(&mut self) -> usize {
+    self.release = self.release.wrapping_add(self.period);
+    self.absolute = self.release.wrapping_add(self.relative);
+    self.missed = false;
+    self.release
+  }
+
+  /// Returns true if this deadline comes before `other`.
+  pub fn is_before(&self, other: &Deadline) -> bool {
+    before(self.absolute, other.absolute)
+  }
+
+  /// Returns true if the deadline has passed at tick `now`.
+  pub fn is_missed(&self, now: usize) -> bool {
+    before(self.absolute, now)
+  }
+}
+
+/// The details of a missed deadline, passed to the deadline miss hook.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct DeadlineMiss {
+  /// The tid of the task that missed its deadline.
+  pub tid: usize,
+  /// The name of the task that missed its deadline.
+  pub name: &'static str,
+  /// The tick the task had to be finished by.
+  pub deadline: usize,
+  /// The tick the miss was noticed at.
+  pub tick: usize,
+}
+
+/// Sets a function to be called whenever an EDF task misses its deadline.
+///
+/// The hook is called from the tick interrupt, so it should be short and must not block.
+pub fn set_deadline_miss_hook(hook: fn(DeadlineMiss)) {
+  // UNSAFE: Function pointers are written in a single store
+  unsafe { MISS_HOOK = Some(hook) };
+}
+
+/// Reports a deadline miss for `task` if its deadline has passed and it hasn't already been
+/// reported.
+pub fn check_deadline(task: &mut TaskControl, now: usize) {
+  let (tid, name) = (task.tid(), task.name());
+  let miss = match task.deadline {
+    Some(ref mut deadline) if !deadline.missed && deadline.is_missed(now) => {
+      deadline.missed = true;
+      DeadlineMiss {
+        tid: tid,
+        name: name,
+        deadline: deadline.absolute,
+        tick: now,
+      }
+    },
+    _ => return,
+  };
+  // UNSAFE: The hook is only ever written once during initialization
+  if let Some(hook) = unsafe { MISS_HOOK } {
+    hook(miss);
+  }
+}
+
+/// The queue of EDF tasks that are ready to run.
+pub struct EdfQueue {
+  queue: SyncQueue<TaskControl>,
+}
+
+impl EdfQueue {
+  /// Creates an empty queue.
+  pub const fn new() -> Self {
+    EdfQueue { queue: SyncQueue::new() }
+  }
+
+  /// Adds a ready EDF task to the queue.
+  pub fn enqueue<N: Into<NodePtr<TaskControl>>>(&self, task: N) {
+    self.queue.enqueue(task);
+  }
+
+  /// Takes the task with the earliest deadline off of the queue.
+  ///
+  /// Tasks with the same deadline are run in the order they became ready.
+  pub fn dequeue(&self) -> Option<NodePtr<TaskControl>> {
+    let earliest = match self.earliest() {
+      Some(earliest) => earliest,
+      None => return None,
+    };
+    let taken = Cell::new(false);
+    let mut removed = self.queue.remove(|task| {
+      // Only take the first task with the earliest deadline
+      if !taken.get() && task.deadline.map(|deadline| deadline.absolute) == Some(earliest) {
+        taken.set(true);
+        true
+      }
+      else {
+        false
+      }
+    });
+    removed.dequeue()
+  }
+
+  /// Returns the earliest absolute deadline of the tasks in the queue.
+  pub fn earliest(&self) -> Option<usize> {
+    let mut earliest: Option<Deadline> = None;
+    self.queue.modify_all(|task| {
+      if let Some(deadline) = task.deadline {
+        if earliest.map_or(true, |current| deadline.is_before(&current)) {
+          earliest = Some(deadline);
+        }
+      }
+    });
+    earliest.map(|deadline| deadline.absolute)
+  }
+
+  /// Checks every ready task for a missed deadline.
+  pub fn check_deadlines(&self, now: usize) {
+    self.queue.modify_all(|task| check_deadline(task, now));
+  }
+
+  /// Returns true if there are no EDF tasks ready to run.
+  pub fn is_empty(&self) -> bool {
+    self.queue.is_empty()
+  }
+
+  /// Removes all the tasks in the queue.
+  pub fn remove_all(&self) -> Queue<TaskControl> {
+    self.queue.remove_all()
+  }
+}
+
+/// Returns true if tick `a` comes before tick `b`, accounting for the tick count wrapping around.
+pub fn before(a: usize, b: usize) -> bool {
+  (a.wrapping_sub(b) as isize) < 0
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use queue::Node;
+  use alloc::boxed::Box;
+  use task::Priority;
+  use test;
+
+  fn edf_task(period: usize, relative: usize, now: usize) -> Box<Node<TaskControl>> {
+    let mut task = test::create_test_task(512, Priority::Critical, "edf test");
+    task.deadline = Some(Deadline::new(period, relative, now));
+    Box::new(Node::new(task))
+  }
+
+  #[test]
+  fn deadline_wraps() {
+    let late = Deadline::new(10, 5, !0 - 1);
+    let early = Deadline::new(10, 5, !0 - 3);
+
+    assert!(early.is_before(&late));
+    assert_not!(late.is_missed(2));
+    assert!(late.is_missed(4));
+  }
+
+  #[test]
+  fn deadline_next_period() {
+    let mut deadline = Deadline::new(10, 5, 100);
+
+    assert_eq!(deadline.next_period(), 110);
+    assert_eq!(deadline.absolute, 115);
+  }
+
+  #[test]
+  fn queue_dequeues_earliest_deadline() {
+    let queue = EdfQueue::new();
+    queue.enqueue(edf_task(100, 50, 0));
+    queue.enqueue(edf_task(100, 20, 0));
+    queue.enqueue(edf_task(100, 30, 0));
+
+    assert_eq!(queue.earliest(), Some(20));
+    assert_eq!(queue.dequeue().unwrap().deadline.unwrap().absolute, 20);
+    assert_eq!(queue.dequeue().unwrap().deadline.unwrap().absolute, 30);
+    assert_eq!(queue.dequeue().unwrap().deadline.unwrap().absolute, 50);
+    assert!(queue.dequeue().is_none());
+  }
+}
@@ -0,0 +1,115 @@
+// sched/idle.rs
+// AltOSRust
+//
+// Created by Daniel Seitz on 2/21/17
+
+//! The idle task.
+//!
+//! The idle task runs whenever no other task is ready. Each time around its loop it calls the idle
+//! hook, if the application registered one, and then puts the core to sleep until the next
+//! interrupt. Once an interrupt has made another task ready it yields so that task can run.
+
+use task::{TaskControl, Priority};
+use task::args::Args;
+use queue::Node;
+use alloc::boxed::Box;
+use sync::CriticalSection;
+use syscall::sched_yield;
+use super::PRIORITY_QUEUES;
+use arch;
+
+/// The size of the idle task's stack, the idle hook runs on this stack.
+pub const IDLE_TASK_STACK_SIZE: usize = 256;
+
+static mut IDLE_HOOK: Option<fn()> = None;
+
+/// Sets a function for the idle task to call whenever no other task is ready to run.
+///
+/// The hook is called once each time the idle task wakes up, before the core goes back to sleep.
+/// It's a good place for low priority background work like feeding a watchdog or flushing logs.
+/// The hook runs on the idle task's stack, which is only `IDLE_TASK_STACK_SIZE` bytes, and it
+/// must never block.
+///
+/// # Examples
+///
+/// ```rust,no_run
+/// use altos_core::sched;
+///
+/// sched::set_idle_hook(background_work);
+///
+/// fn background_work() {
+///   // Do something that can wait until there's nothing else to do...
+/// }
+/// ```
+pub fn set_idle_hook(hook: fn()) {
+  // UNSAFE: Function pointers are written in a single store
+  unsafe { IDLE_HOOK = Some(hook) };
+}
+
+/// Creates the idle task and puts it in the ready queue.
+pub fn init_idle_task() {
+  let task = TaskControl::new(idle_task_code, Args::empty(), IDLE_TASK_STACK_SIZE, 
+                              Priority::__Idle, "idle");
+
+  PRIORITY_QUEUES.enqueue(Box::new(Node::new(task)));
+}
+
+fn idle_task_code(_args: &mut Args) {
+  loop {
+    idle();
+  }
+}
+
+/// One trip around the idle loop.
+fn idle() {
+  // UNSAFE: The hook is only ever written during initialization
+  if let Some(hook) = unsafe { IDLE_HOOK } {
+    hook();
+  }
+
+  // Interrupts are masked while we check so one can't slip in between the check and the sleep,
+  // the core still wakes up for a pending interrupt and it runs once the section ends
+  let g = CriticalSection::begin();
+  if !PRIORITY_QUEUES.has_ready() {
+    arch::wait_for_interrupt();
+  }
+  drop(g);
+
+  if PRIORITY_QUEUES.has_ready() {
+    sched_yield();
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use atomic::{AtomicUsize, ATOMIC_USIZE_INIT, Ordering};
+  use test;
+
+  static HOOK_CALLS: AtomicUsize = ATOMIC_USIZE_INIT;
+
+  fn count_hook() {
+    HOOK_CALLS.fetch_add(1, Ordering::SeqCst);
+  }
+
+  #[test]
+  fn idle_calls_hook() {
+    let _g = test::set_up();
+    set_idle_hook(count_hook);
+    let calls = HOOK_CALLS.load(Ordering::SeqCst);
+
+    idle();
+    assert_eq!(HOOK_CALLS.load(Ordering::SeqCst), calls + 1);
+  }
+
+  #[test]
+  fn idle_yields_to_ready_task() {
+    let _g = test::set_up();
+    super::super::start_scheduler();
+    assert_eq!(test::current_task().unwrap().name(), "idle");
+
+    let handle = test::create_and_schedule_test_task(512, Priority::Normal, "ready task");
+    idle();
+    assert_eq!(handle.tid(), Ok(test::current_task().unwrap().tid()));
+  }
+}
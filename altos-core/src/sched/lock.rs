@@ -0,0 +1,116 @@
+// sched/lock.rs
+// AltOSRust
+//
+// Created by Daniel Seitz on 2/18/17
+
+//! Scheduler locking.
+//!
+//! Locking the scheduler keeps the current task from being preempted by another task without
+//! masking interrupts the way a `CriticalSection` does. Interrupt handlers still run as usual, but
+//! any context switch they (or the task itself) request is deferred until the scheduler is
+//! unlocked.
+
+use atomic::{AtomicUsize, AtomicBool, ATOMIC_USIZE_INIT, ATOMIC_BOOL_INIT, Ordering};
+use syscall::svc;
+use arch;
+
+static LOCK_COUNT: AtomicUsize = ATOMIC_USIZE_INIT;
+static SWITCH_PENDING: AtomicBool = ATOMIC_BOOL_INIT;
+
+/// Locks the scheduler, returning a `SchedulerGuard` that unlocks it when it falls out of scope.
+///
+/// Locks can be nested, the scheduler is only unlocked once every guard has been dropped. A task
+/// must not block (by sleeping or waiting on a `Mutex`) while it holds the scheduler lock.
+///
+/// # Examples
+///
+/// ```rust,no_run
+/// use altos_core::sched;
+///
+/// let guard = sched::lock();
+///
+/// // Interrupts still get serviced, but no other task can run here...
+///
+/// sched::unlock(guard); // Could also just let it drop out of scope
+/// ```
+pub fn lock() -> SchedulerGuard {
+  svc::call(svc::SYS_SCHED_LOCK, 0, 0, 0);
+  SchedulerGuard
+}
+
+/// Unlocks the scheduler by consuming the guard returned from `lock`.
+///
+/// If a context switch was requested while the scheduler was locked it happens now.
+pub fn unlock(guard: SchedulerGuard) {
+  drop(guard);
+}
+
+/// Returns true if the scheduler is currently locked.
+pub fn is_locked() -> bool {
+  LOCK_COUNT.load(Ordering::SeqCst) != 0
+}
+
+/// Releases every lock on the scheduler without running any deferred context switch.
+///
+/// This is for tearing down a task that died while holding the lock, its guards will never be
+/// dropped.
+#[doc(hidden)]
+pub fn force_unlock() {
+  LOCK_COUNT.store(0, Ordering::SeqCst);
+  SWITCH_PENDING.store(false, Ordering::SeqCst);
+}
+
+/// The handler for `svc::SYS_SCHED_LOCK`.
+#[doc(hidden)]
+pub fn sys_lock(_: usize, _: usize, _: usize) -> usize {
+  LOCK_COUNT.fetch_add(1, Ordering::SeqCst);
+  0
+}
+
+/// The handler for `svc::SYS_SCHED_UNLOCK`.
+#[doc(hidden)]
+pub fn sys_unlock(_: usize, _: usize, _: usize) -> usize {
+  if LOCK_COUNT.fetch_sub(1, Ordering::SeqCst) == 1 {
+    if SWITCH_PENDING.swap(false, Ordering::SeqCst) {
+      arch::yield_cpu();
+    }
+  }
+  0
+}
+
+/// Records that a context switch was requested while the scheduler was locked.
+#[doc(hidden)]
+pub fn defer_switch() {
+  SWITCH_PENDING.store(true, Ordering::SeqCst);
+}
+
+/// Tracks the lifetime of a scheduler lock.
+///
+/// Can only be generated by the `lock()` function, when this falls out of scope the scheduler is
+/// unlocked and any deferred context switch is run.
+#[must_use]
+pub struct SchedulerGuard;
+
+impl Drop for SchedulerGuard {
+  fn drop(&mut self) {
+    svc::call(svc::SYS_SCHED_UNLOCK, 0, 0, 0);
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn lock_nests() {
+    let _g = ::test::set_up();
+    let outer = lock();
+    let inner = lock();
+    assert!(is_locked());
+
+    unlock(inner);
+    assert!(is_locked());
+    unlock(outer);
+    assert_not!(is_locked());
+  }
+}
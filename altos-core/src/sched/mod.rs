@@ -8,34 +8,48 @@
 //! This module contains the code for the scheduler and initialization.
 
 use task::{self, TaskControl, Delay, Priority, State};
-use queue::{SyncQueue, Node};
-use alloc::boxed::Box;
-use core::ops::Index;
+use queue::{SyncQueue, NodePtr};
 use task::NUM_PRIORITIES;
+use sync::{SpinMutex, CriticalSection};
+use syscall::svc;
 use arch;
 
+mod lock;
+mod ready;
+mod idle;
+#[cfg(feature="edf")]
+pub mod edf;
+
+pub use self::lock::{lock, unlock, is_locked, force_unlock, defer_switch, SchedulerGuard};
+pub use self::lock::{sys_lock, sys_unlock};
+pub use self::ready::ReadyQueues;
+pub use self::idle::{set_idle_hook, IDLE_TASK_STACK_SIZE};
+#[cfg(feature="edf")]
+pub use self::edf::{set_deadline_miss_hook, Deadline, DeadlineMiss};
+
 /// The current task.
 ///
 /// This keeps track of the currently running task, this should always be `Some` unless the task is
 /// actively being switched out or the scheduler has not been started.
 #[no_mangle]
 #[doc(hidden)]
-pub static mut CURRENT_TASK: Option<Box<Node<TaskControl>>> = None;
+pub static mut CURRENT_TASK: Option<NodePtr<TaskControl>> = None;
 
-pub static PRIORITY_QUEUES: [SyncQueue<TaskControl>; NUM_PRIORITIES] = [SyncQueue::new(),
-                                                                    SyncQueue::new(), 
-                                                                    SyncQueue::new(), 
-                                                                    SyncQueue::new()];
+#[doc(hidden)]
+pub static PRIORITY_QUEUES: ReadyQueues = ReadyQueues::new(ready::EMPTY);
+#[doc(hidden)]
 pub static SLEEP_QUEUE: SyncQueue<TaskControl> = SyncQueue::new();
+#[doc(hidden)]
 pub static DELAY_QUEUE: SyncQueue<TaskControl> = SyncQueue::new();
+#[doc(hidden)]
 pub static OVERFLOW_DELAY_QUEUE: SyncQueue<TaskControl> = SyncQueue::new();
 
-impl Index<Priority> for [SyncQueue<TaskControl>] {
-  type Output = SyncQueue<TaskControl>;
-  fn index(&self, idx: Priority) -> &Self::Output {
-    &self[idx as usize]
-  }
-}
+/// The number of ticks a task runs for before another task of the same priority gets a turn, if
+/// it hasn't been given a time slice of its own.
+pub const DEFAULT_TIME_SLICE: usize = 1;
+
+static TIME_SLICES: SpinMutex<[usize; NUM_PRIORITIES]> =
+  SpinMutex::new([DEFAULT_TIME_SLICE; NUM_PRIORITIES]);
 
 /// Select a new task to run and switch its context, this function MUST only be called from the
 /// PendSV handler, calling it from elsewhere could lead to undefined behavior. It must be exposed
@@ -51,11 +65,11 @@ pub fn switch_context() {
   // UNSAFE: Accessing CURRENT_TASK
   match unsafe { CURRENT_TASK.take() } {
     Some(mut running) => {
+      trace_event!(SwitchOut(running.tid()));
       if running.destroy {
-        drop(running);
+        reclaim(running);
       }
       else {
-        let queue_index = running.priority;
         if running.is_stack_overflowed() {
           panic!("switch_context - The current task's stack overflowed!");
         }
@@ -70,22 +84,24 @@ pub fn switch_context() {
         else {
           running.state = State::Ready;
           running.delay_type = Delay::Invalid;
-          PRIORITY_QUEUES[queue_index].enqueue(running);
+          PRIORITY_QUEUES.enqueue(running);
         }
       }
 
-      'main: loop {
-        for i in Priority::all() {
-          while let Some(mut new_task) = PRIORITY_QUEUES[i].dequeue() {
-            if new_task.destroy {
-              drop(new_task);
-            }
-            else {
-              new_task.state = State::Running;
-              // UNSAFE: Accessing CURRENT_TASK
-              unsafe { CURRENT_TASK = Some(new_task) };
-              break 'main;
-            }
+      loop {
+        if let Some(mut new_task) = PRIORITY_QUEUES.dequeue() {
+          if new_task.destroy {
+            reclaim(new_task);
+          }
+          else {
+            new_task.state = State::Running;
+            new_task.slice_ticks = 0;
+            trace_event!(SwitchIn(new_task.tid()));
+            #[cfg(test)]
+            ::test::record_switch(new_task.tid());
+            // UNSAFE: Accessing CURRENT_TASK
+            unsafe { CURRENT_TASK = Some(new_task) };
+            break;
           }
         }
       }
@@ -94,19 +110,84 @@ pub fn switch_context() {
   }
 }
 
+/// Free the memory associated with a destroyed task.
+///
+/// Any task-local values are dropped first, then the task's arena is freed along with the rest of
+/// its memory. Statically allocated tasks don't own their memory, dropping their node leaves it
+/// alone, so they only need to be taken out of the task table.
+fn reclaim(mut task: NodePtr<TaskControl>) {
+  trace_event!(TaskDestroy(task.tid()));
+  task::local::destroy_all(&mut task);
+  #[cfg(all(not(test), feature="host"))]
+  ::arch::release_task(task.stack_top());
+  if task.is_static() {
+    task.unregister();
+  }
+  drop(task);
+}
+
+/// Sets the time slice for all tasks of a priority that don't have their own.
+///
+/// The time slice is the number of ticks a task can run before it is preempted by another ready
+/// task of the same priority. A time slice of 0 disables round-robin scheduling for the priority,
+/// tasks will run first in first out until they block or yield. Higher priority tasks always
+/// preempt lower priority ones, regardless of time slices.
+///
+/// # Examples
+///
+/// ```rust,no_run
+/// use altos_core::Priority;
+/// use altos_core::sched;
+///
+/// // Low priority tasks get 10 ticks at a time
+/// sched::set_time_slice(Priority::Low, 10);
+///
+/// // Critical tasks run until they're done
+/// sched::set_time_slice(Priority::Critical, 0);
+/// ```
+pub fn set_time_slice(priority: Priority, ticks: usize) {
+  svc::call(svc::SYS_SET_TIME_SLICE, priority.level(), ticks, 0);
+}
+
+/// The handler for `svc::SYS_SET_TIME_SLICE`.
+#[doc(hidden)]
+pub fn sys_set_time_slice(level: usize, ticks: usize, _: usize) -> usize {
+  let _g = CriticalSection::begin();
+  match TIME_SLICES.lock().get_mut(level) {
+    Some(slice) => {
+      *slice = ticks;
+      0
+    },
+    None => svc::INVALID_ARGUMENT,
+  }
+}
+
+/// Returns the time slice for `task`, the number of ticks it can run before being preempted by a
+/// task of the same priority. 0 means the task is never preempted by tasks of the same priority.
+pub fn time_slice(task: &TaskControl) -> usize {
+  match task.time_slice() {
+    Some(ticks) => ticks,
+    None => {
+      let _g = CriticalSection::begin();
+      TIME_SLICES.lock()[task.priority.level()]
+    },
+  }
+}
+
 /// Start running the first task in the queue
 pub fn start_scheduler() {
-    task::init_idle_task();
-    for i in Priority::all() {
-      if let Some(mut task) = PRIORITY_QUEUES[i].dequeue() {
-        task.state = State::Running;
-        // UNSAFE: Accessing CURRENT_TASK
-        unsafe { CURRENT_TASK = Some(task) };
-        break;
-      }
+    idle::init_idle_task();
+    if let Some(mut task) = PRIORITY_QUEUES.dequeue() {
+      task.state = State::Running;
+      trace_event!(SwitchIn(task.tid()));
+      #[cfg(test)]
+      ::test::record_switch(task.tid());
+      // UNSAFE: Accessing CURRENT_TASK
+      unsafe { CURRENT_TASK = Some(task) };
     }
     // UNSAFE: Accessing CURRENT_TASK
     debug_assert!(unsafe { CURRENT_TASK.is_some() });
+    arch::start_tick_source();
     arch::start_first_task();
 }
 
@@ -115,6 +196,19 @@ mod tests {
   use super::*;
   use test;
 
+  #[test]
+  fn test_time_slice_per_priority() {
+    let _g = test::set_up();
+    let mut task = test::create_test_task(512, Priority::Low, "slice test");
+    assert_eq!(time_slice(&task), DEFAULT_TIME_SLICE);
+
+    set_time_slice(Priority::Low, 10);
+    assert_eq!(time_slice(&task), 10);
+    task.set_time_slice(Some(2));
+    assert_eq!(time_slice(&task), 2);
+    set_time_slice(Priority::Low, DEFAULT_TIME_SLICE);
+  }
+
   #[test]
   fn test_start_scheduler() {
     let _g = test::set_up();
@@ -218,4 +312,33 @@ mod tests {
     assert!(test::current_task().is_some());
     assert_eq!(handle_3.tid(), Ok(test::current_task().unwrap().tid()));
   }
+
+  #[test]
+  fn test_scheduler_runs_static_tasks() {
+    static_task!(static STATIC_TASK: 512);
+
+    let _g = test::set_up();
+    assert!(test::current_task().is_none());
+    let handle_1 = test::create_and_schedule_test_task(512, Priority::Normal, "test task 1");
+    let mut handle_2 = test::create_and_schedule_static_test_task(&STATIC_TASK, Priority::Normal, 
+                                                                  "static task");
+    start_scheduler();
+    assert!(test::current_task().is_some());
+    assert_eq!(handle_1.tid(), Ok(test::current_task().unwrap().tid()));
+
+    switch_context();
+    assert!(test::current_task().is_some());
+    assert_eq!(handle_2.tid(), Ok(test::current_task().unwrap().tid()));
+    assert!(test::current_task().unwrap().is_static());
+    handle_2.destroy();
+
+    // The static task gets reclaimed here, it should not try to free its memory
+    switch_context();
+    assert!(test::current_task().is_some());
+    assert_eq!(handle_1.tid(), Ok(test::current_task().unwrap().tid()));
+
+    switch_context();
+    assert!(test::current_task().is_some());
+    assert_eq!(handle_1.tid(), Ok(test::current_task().unwrap().tid()));
+  }
 }
@@ -0,0 +1,249 @@
+// sched/ready.rs
+// AltOSRust
+//
+// Created by Daniel Seitz on 2/19/17
+
+//! The ready queues.
+//!
+//! There is one queue of ready tasks for each priority level. Alongside the queues we keep a
+//! bitmap with a bit set for every level that has a ready task, so the highest priority ready task
+//! can be found with a single `trailing_zeros` instead of checking each queue in turn.
+//!
+//! With the `edf` feature enabled there is also a queue for tasks in the earliest deadline first
+//! scheduling class, which always runs ahead of the fixed priority queues.
+
+use task::{TaskControl, Priority, NUM_PRIORITIES};
+use queue::{SyncQueue, Queue, NodePtr};
+use sync::CriticalSection;
+use atomic::{AtomicUsize, Ordering};
+#[cfg(feature="edf")]
+use super::edf::{self, EdfQueue};
+
+/// The queues of tasks that are ready to run, one for each priority level.
+pub struct ReadyQueues {
+  queues: [SyncQueue<TaskControl>; NUM_PRIORITIES],
+  ready: AtomicUsize,
+  #[cfg(feature="edf")]
+  edf: EdfQueue,
+}
+
+impl ReadyQueues {
+  /// Creates a new set of ready queues, `queues` must all be empty.
+  pub const fn new(queues: [SyncQueue<TaskControl>; NUM_PRIORITIES]) -> Self {
+    ReadyQueues {
+      queues: queues,
+      ready: AtomicUsize::new(0),
+      #[cfg(feature="edf")]
+      edf: EdfQueue::new(),
+    }
+  }
+
+  /// Puts a task at the back of the queue for its priority.
+  pub fn enqueue<N: Into<NodePtr<TaskControl>>>(&self, task: N) {
+    let task = task.into();
+    let _g = CriticalSection::begin();
+    #[cfg(feature="edf")]
+    {
+      if task.deadline.is_some() {
+        self.edf.enqueue(task);
+        return;
+      }
+    }
+    let level = task.priority.level();
+    self.queues[level].enqueue(task);
+    self.ready.fetch_or(1 << level, Ordering::SeqCst);
+  }
+
+  /// Takes the task at the front of the highest priority non-empty queue.
+  ///
+  /// If there are any ready EDF tasks the one with the earliest deadline is taken instead.
+  pub fn dequeue(&self) -> Option<NodePtr<TaskControl>> {
+    let _g = CriticalSection::begin();
+    #[cfg(feature="edf")]
+    {
+      if let Some(task) = self.edf.dequeue() {
+        return Some(task);
+      }
+    }
+    while let Some(level) = self.highest_level() {
+      let task = self.queues[level].dequeue();
+      if self.queues[level].is_empty() {
+        self.ready.fetch_and(!(1 << level), Ordering::SeqCst);
+      }
+      if task.is_some() {
+        return task;
+      }
+    }
+    None
+  }
+
+  /// Returns the priority of the highest priority task that is ready to run.
+  pub fn highest(&self) -> Option<Priority> {
+    self.highest_level().map(Priority::new_unchecked)
+  }
+
+  /// Returns true if there is a ready task that should run instead of `current`.
+  ///
+  /// A task preempts `current` if it has a higher priority, or the same priority once `current`'s
+  /// time slice has expired. Ready EDF tasks preempt any fixed priority task, and other EDF tasks
+  /// with a later deadline.
+  pub fn preempts(&self, current: &TaskControl, slice_expired: bool) -> bool {
+    #[cfg(feature="edf")]
+    {
+      if let Some(earliest) = self.edf.earliest() {
+        return match current.deadline {
+          Some(deadline) => edf::before(earliest, deadline.absolute),
+          None => true,
+        };
+      }
+      if current.deadline.is_some() {
+        return false;
+      }
+    }
+    match self.highest_level() {
+      Some(level) => {
+        let current_level = current.priority.level();
+        level < current_level || (level == current_level && slice_expired)
+      },
+      None => false,
+    }
+  }
+
+  /// Checks every ready EDF task for a missed deadline.
+  #[cfg(feature="edf")]
+  pub fn check_deadlines(&self, now: usize) {
+    self.edf.check_deadlines(now);
+  }
+
+  /// Returns true if there is any task ready to run.
+  pub fn has_ready(&self) -> bool {
+    #[cfg(feature="edf")]
+    {
+      if !self.edf.is_empty() {
+        return true;
+      }
+    }
+    self.ready.load(Ordering::SeqCst) != 0
+  }
+
+  /// Returns true if there are no tasks ready at `priority`.
+  pub fn is_empty(&self, priority: Priority) -> bool {
+    self.queues[priority.level()].is_empty()
+  }
+
+  /// Removes all the tasks that are ready at `priority`.
+  pub fn remove_all(&self, priority: Priority) -> Queue<TaskControl> {
+    let _g = CriticalSection::begin();
+    let level = priority.level();
+    self.ready.fetch_and(!(1 << level), Ordering::SeqCst);
+    self.queues[level].remove_all()
+  }
+
+  /// Takes the task with id `tid` out of the queue for `priority`, if it's there.
+  pub fn remove(&self, priority: Priority, tid: usize) -> Option<NodePtr<TaskControl>> {
+    let _g = CriticalSection::begin();
+    let level = priority.level();
+    let mut removed = self.queues[level].remove(|task| task.tid() == tid);
+    if self.queues[level].is_empty() {
+      self.ready.fetch_and(!(1 << level), Ordering::SeqCst);
+    }
+    removed.dequeue()
+  }
+
+  /// Removes all the ready tasks at every priority.
+  pub fn clear(&self) {
+    #[cfg(feature="edf")]
+    self.edf.remove_all();
+    for level in Priority::all() {
+      self.remove_all(Priority::new_unchecked(level));
+    }
+  }
+
+  fn highest_level(&self) -> Option<usize> {
+    match self.ready.load(Ordering::SeqCst) {
+      0 => None,
+      ready => Some(ready.trailing_zeros() as usize),
+    }
+  }
+}
+
+/// Creates an array of empty `SyncQueue`s, one for each token passed in.
+macro_rules! empty_queues {
+  ($($level:tt)*) => { [$(empty_queues!(@queue $level)),*] };
+  (@queue $level:tt) => { SyncQueue::new() };
+}
+
+#[cfg(not(any(feature="priorities_8", feature="priorities_16", feature="priorities_32")))]
+pub const EMPTY: [SyncQueue<TaskControl>; NUM_PRIORITIES] = empty_queues!(0 1 2 3);
+
+#[cfg(all(feature="priorities_8", not(any(feature="priorities_16", feature="priorities_32"))))]
+pub const EMPTY: [SyncQueue<TaskControl>; NUM_PRIORITIES] = empty_queues!(0 1 2 3 4 5 6 7);
+
+#[cfg(all(feature="priorities_16", not(feature="priorities_32")))]
+pub const EMPTY: [SyncQueue<TaskControl>; NUM_PRIORITIES] =
+  empty_queues!(0 1 2 3 4 5 6 7 8 9 10 11 12 13 14 15);
+
+#[cfg(feature="priorities_32")]
+pub const EMPTY: [SyncQueue<TaskControl>; NUM_PRIORITIES] =
+  empty_queues!(0 1 2 3 4 5 6 7 8 9 10 11 12 13 14 15
+                16 17 18 19 20 21 22 23 24 25 26 27 28 29 30 31);
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use queue::Node;
+  use alloc::boxed::Box;
+  use test;
+
+  #[test]
+  fn dequeue_highest_first() {
+    let queues = ReadyQueues::new(EMPTY);
+    queues.enqueue(Box::new(Node::new(test::create_test_task(512, Priority::Low, "low"))));
+    queues.enqueue(Box::new(Node::new(test::create_test_task(512, Priority::Critical,
+                                                             "critical"))));
+
+    assert_eq!(queues.highest(), Some(Priority::Critical));
+    assert_eq!(queues.dequeue().unwrap().priority, Priority::Critical);
+    assert_eq!(queues.highest(), Some(Priority::Low));
+    assert_eq!(queues.dequeue().unwrap().priority, Priority::Low);
+    assert_eq!(queues.highest(), None);
+    assert!(queues.dequeue().is_none());
+  }
+
+  #[test]
+  fn remove_all_clears_ready_bit() {
+    let queues = ReadyQueues::new(EMPTY);
+    queues.enqueue(Box::new(Node::new(test::create_test_task(512, Priority::Normal, "normal"))));
+
+    assert_not!(queues.is_empty(Priority::Normal));
+    assert_not!(queues.remove_all(Priority::Normal).is_empty());
+    assert!(queues.is_empty(Priority::Normal));
+    assert_eq!(queues.highest(), None);
+  }
+
+  #[test]
+  fn remove_by_tid() {
+    let queues = ReadyQueues::new(EMPTY);
+    let task = test::create_test_task(512, Priority::Low, "low");
+    let tid = task.tid();
+    queues.enqueue(Box::new(Node::new(task)));
+
+    assert!(queues.remove(Priority::Normal, tid).is_none());
+    assert_eq!(queues.remove(Priority::Low, tid).unwrap().tid(), tid);
+    assert_eq!(queues.highest(), None);
+  }
+
+  #[test]
+  #[cfg(feature="edf")]
+  fn dequeue_edf_before_fixed() {
+    let queues = ReadyQueues::new(EMPTY);
+    let mut edf_task = test::create_test_task(512, Priority::Low, "edf");
+    edf_task.deadline = Some(edf::Deadline::new(10, 10, 0));
+    queues.enqueue(Box::new(Node::new(test::create_test_task(512, Priority::Critical,
+                                                             "critical"))));
+    queues.enqueue(Box::new(Node::new(edf_task)));
+
+    assert_eq!(queues.dequeue().unwrap().name(), "edf");
+    assert_eq!(queues.dequeue().unwrap().name(), "critical");
+  }
+}
@@ -0,0 +1,48 @@
+// sync/held.rs
+// AltOSRust
+//
+// Created by Daniel Seitz on 3/5/17
+
+//! Tracking the locks held by each task.
+//!
+//! A task that dies without unwinding never drops its guards, so any `Mutex` or `SpinMutex` it
+//! was holding stays locked forever. Every lock taken by task code is counted against the task,
+//! so whatever tears the task down can tell whether doing so would leave a lock behind.
+
+use atomic::{AtomicUsize, Ordering};
+use sched::CURRENT_TASK;
+use arch;
+
+/// A lock counted against the task that took it, the count goes back down when this is dropped.
+///
+/// Locks taken by the kernel itself, like in an interrupt handler, aren't counted.
+#[doc(hidden)]
+pub struct Held(Option<*const AtomicUsize>);
+
+impl Held {
+  /// Counts a lock that was just taken against the current task.
+  pub fn take() -> Self {
+    if arch::in_kernel_mode() {
+      return Held(None);
+    }
+    // UNSAFE: Accessing CURRENT_TASK, only the task itself takes and releases the locks it holds
+    match unsafe { CURRENT_TASK.as_ref() } {
+      Some(task) => {
+        let count = task.lock_count();
+        count.fetch_add(1, Ordering::SeqCst);
+        Held(Some(count))
+      },
+      None => Held(None),
+    }
+  }
+}
+
+impl Drop for Held {
+  fn drop(&mut self) {
+    if let Some(count) = self.0 {
+      // UNSAFE: A task can't be destroyed while it's still running code that holds a lock, so the
+      // count is still around
+      unsafe { (*count).fetch_sub(1, Ordering::SeqCst) };
+    }
+  }
+}
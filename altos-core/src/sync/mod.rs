@@ -13,9 +13,11 @@ mod mutex;
 mod spin;
 mod critical;
 mod condvar;
+mod held;
 
 pub use self::mutex::{Mutex, MutexGuard};
 pub use self::mutex::mutex_from_guard;
 pub use self::spin::{SpinMutex, SpinGuard};
 pub use self::critical::CriticalSection;
 pub use self::condvar::CondVar;
+pub use self::held::Held;
@@ -17,6 +17,7 @@
 use atomic::{ATOMIC_BOOL_INIT, AtomicBool, Ordering};
 use core::ops::{Drop, Deref, DerefMut};
 use core::cell::UnsafeCell;
+use super::Held;
 
 /// A mutex lock to synchronize access to some shared resource.
 ///
@@ -36,6 +37,7 @@ pub struct MutexGuard<'mx, T: ?Sized + 'mx> {
   wchan: usize,
   lock: &'mx AtomicBool,
   data: &'mx mut T,
+  _held: Held,
 }
 
 unsafe impl<T: ?Sized + Send> Sync for Mutex<T> {}
@@ -85,11 +87,13 @@ impl<T: ?Sized> Mutex<T> {
   /// ```
   pub fn lock(&self) -> MutexGuard<T> {
     self.obtain_lock();
+    trace_event!(MutexLock(self.wchan()));
     MutexGuard {
       wchan: self.wchan(),
       lock: &self.lock,
       // UNSAFE: lock controls access to data, so only one thread can ever get this &mut
       data: unsafe { &mut *self.data.get() },
+      _held: Held::take(),
     }
   }
 
@@ -115,12 +119,14 @@ impl<T: ?Sized> Mutex<T> {
   /// ```
   pub fn try_lock(&self) -> Option<MutexGuard<T>> {
     if self.lock.compare_and_swap(false, true, Ordering::Acquire) == false {
+      trace_event!(MutexLock(self.wchan()));
       Some(
         MutexGuard {
           wchan: self.wchan(),
           lock: &self.lock,
           // UNSAFE: lock controls access to data, we only execute this branch if we've acquired it
           data: unsafe { &mut *self.data.get() },
+          _held: Held::take(),
         }
       )
     }
@@ -156,6 +162,7 @@ impl<'mx, T: ?Sized> Drop for MutexGuard<'mx, T> {
     // Do we care if we get pre-empted and another thread steals the lock before we wake the
     // sleeping tasks?
     self.lock.store(false, Ordering::SeqCst);
+    trace_event!(MutexUnlock(self.wchan));
     ::syscall::wake(self.wchan);
   }
 }
@@ -166,6 +173,7 @@ mod tests {
   use task::State;
   use sched;
   use syscall;
+  use arch;
   use test;
 
   #[test]
@@ -304,6 +312,26 @@ mod tests {
     assert_eq!(handle_4.tid(), Ok(test::current_task().unwrap().tid()));
   }
 
+  #[test]
+  fn test_mutex_counts_held_locks() {
+    let _g = test::set_up();
+    test::create_two_tasks();
+    sched::start_scheduler();
+    let mutex = Mutex::new(());
+
+    // Locks taken by the kernel aren't the task's
+    drop(mutex.lock());
+    assert_eq!(test::current_task().unwrap().held_locks(), 0);
+
+    arch::set_kernel_mode(false);
+    let guard = mutex.lock();
+    let held = test::current_task().unwrap().held_locks();
+    drop(guard);
+    arch::set_kernel_mode(true);
+    assert_eq!(held, 1);
+    assert_eq!(test::current_task().unwrap().held_locks(), 0);
+  }
+
   #[test]
   fn test_mutex_guard_derefrences_to_owned_data() {
     let mutex = Mutex::new(0);
@@ -16,6 +16,7 @@
 use atomic::{ATOMIC_BOOL_INIT, AtomicBool, Ordering};
 use core::ops::{Drop, Deref, DerefMut};
 use core::cell::UnsafeCell;
+use super::Held;
 
 /// A spin lock used to synchronize access to a shared resource.
 ///
@@ -40,6 +41,7 @@ pub struct SpinMutex<T: ?Sized> {
 pub struct SpinGuard<'mx, T: ?Sized + 'mx> {
   lock: &'mx AtomicBool,
   data: &'mx mut T,
+  _held: Held,
 }
 
 unsafe impl<T: ?Sized + Send> Send for SpinMutex<T> {}
@@ -84,6 +86,7 @@ impl<T: ?Sized> SpinMutex<T> {
       lock: &self.lock,
       // UNSAFE: access to data is controlled by lock
       data: unsafe { &mut *self.data.get() },
+      _held: Held::take(),
     }
   }
 
@@ -114,6 +117,7 @@ impl<T: ?Sized> SpinMutex<T> {
           lock: &self.lock,
           // UNSAFE: executing this branch means we've obtained the lock
           data: unsafe { &mut *self.data.get() },
+          _held: Held::take(),
         }
       )
     }
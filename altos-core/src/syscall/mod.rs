@@ -5,16 +5,22 @@
 
 //! Syscall interface for the AltOS kernel
 
-use sched::{CURRENT_TASK, SLEEP_QUEUE, DELAY_QUEUE, OVERFLOW_DELAY_QUEUE, PRIORITY_QUEUES};
+use sched::{self, CURRENT_TASK, SLEEP_QUEUE, DELAY_QUEUE, OVERFLOW_DELAY_QUEUE, PRIORITY_QUEUES};
 use task::{Delay, State, Priority};
 use task::args::Args;
-use task::{TaskHandle, TaskControl};
-use queue::Node;
+use task::{TaskHandle, TaskControl, StaticTask};
+use task::arena::{self, Arena, ArenaError};
+use queue::{Node, NodePtr};
+use heap;
 use alloc::boxed::Box;
 use tick;
 use sync::CriticalSection;
 use arch;
 
+pub mod svc;
+
+pub use task::MAX_TASKS;
+
 /// An alias for the channel to sleep on that will never be awoken by a wakeup signal, it will
 /// still be woken after a timeout
 pub const FOREVER_CHAN: usize = 0;
@@ -26,7 +32,8 @@ pub const FOREVER_CHAN: usize = 0;
 /// the task, an `Args` argument for the arguments that will be passed to the task, a `usize`
 /// argument for how much space should be allocated for the task's stack, a `Priority` argument for
 /// the priority that the task should run at, and a `&str` argument to give the task a readable
-/// name.
+/// name. A numeric priority level can be given with `Priority::new`, where 0 is the highest
+/// priority.
 ///
 /// # Examples
 ///
@@ -38,6 +45,9 @@ pub const FOREVER_CHAN: usize = 0;
 /// // Create the task and hold onto the handle
 /// let handle = new_task(test_task, Args::empty(), 512, Priority::Normal, "new_task_name");
 ///
+/// // Priorities can also be numeric levels
+/// new_task(test_task, Args::empty(), 512, Priority::new(1), "numeric_priority");
+///
 /// // Start running the task
 /// start_scheduler(); 
 ///
@@ -46,15 +56,333 @@ pub const FOREVER_CHAN: usize = 0;
 ///   loop {}
 /// }
 /// ```
+///
+/// # Panics
+///
+/// This function will panic if `MAX_TASKS` tasks already exist, and aborts if there isn't enough
+/// memory for the task. Use `try_new_task` to handle either case.
 pub fn new_task(code: fn(&mut Args), args: Args, stack_depth: usize, priority: Priority, name: &'static str) -> TaskHandle {
   // Make sure the task is allocated in one fell swoop
   let g = CriticalSection::begin();
-  let task = Box::new(Node::new(TaskControl::new(code, args, stack_depth, priority, name)));
+  let task = arena::with_global_heap(|| {
+    Box::new(Node::new(TaskControl::new(code, args, stack_depth, priority, name)))
+  });
   drop(g);
 
-  let handle = TaskHandle::new(&**task);
-  PRIORITY_QUEUES[task.priority].enqueue(task); 
-  handle
+  schedule_new(task)
+}
+
+/// The reasons creating a task can fail.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum TaskError {
+  /// There wasn't enough memory to allocate the task.
+  OutOfMemory,
+
+  /// The task table is full, no more tasks can be created until one is destroyed.
+  TooManyTasks,
+
+  /// `MAX_ARENAS` arenas already exist, no more can be created until one is freed.
+  TooManyArenas,
+}
+
+/// Tries to create a new task and put it into the task queue for running. It returns a
+/// `TaskHandle` to monitor the task with.
+///
+/// `try_new_task` takes the same arguments as `new_task`, but instead of aborting when there isn't
+/// enough memory for the task it returns a `TaskError::OutOfMemory`. If the kernel's task table is
+/// full then `TaskError::TooManyTasks` is returned.
+///
+/// # Examples
+///
+/// ```rust,no_run
+/// use altos_core::Priority;
+/// use altos_core::syscall::{try_new_task, TaskError};
+/// use altos_core::args::Args;
+///
+/// match try_new_task(test_task, Args::empty(), 4096, Priority::Normal, "big_task") {
+///   Ok(handle) => { /* The task was created */ },
+///   Err(TaskError::OutOfMemory) => { /* Try again with a smaller stack? */ },
+///   Err(TaskError::TooManyTasks) => { /* Wait for another task to finish */ },
+///   Err(TaskError::TooManyArenas) => { /* Only returned by try_new_task_with_arena */ },
+/// }
+///
+/// fn test_task(_args: &mut Args) {
+///   loop {}
+/// }
+/// ```
+pub fn try_new_task(code: fn(&mut Args), args: Args, stack_depth: usize, priority: Priority,
+                    name: &'static str) -> Result<TaskHandle, TaskError> {
+  let g = CriticalSection::begin();
+  let task = arena::with_global_heap(|| {
+    TaskControl::try_new(code, args, stack_depth, priority, name)
+      .and_then(|task| heap::try_box(Node::new(task)).ok())
+  });
+  drop(g);
+
+  match task {
+    Some(task) => try_schedule_new(task),
+    None => Err(TaskError::OutOfMemory),
+  }
+}
+
+/// Creates a new task with its own heap arena and puts it into the task queue for running. It
+/// returns a `TaskHandle` to monitor the task with.
+///
+/// `new_task_with_arena` works the same way as `new_task`, except that it takes an extra `usize`
+/// argument for the size of the task's arena. Any dynamic memory that the task allocates while it
+/// is running will come from the arena, and the whole arena is freed when the task is destroyed.
+/// Memory allocated from the arena must not be shared with other tasks.
+///
+/// # Examples
+///
+/// ```rust,no_run
+/// use altos_core::{start_scheduler, Priority};
+/// use altos_core::syscall::new_task_with_arena;
+/// use altos_core::args::Args;
+///
+/// // Give the task 1K of memory to allocate from
+/// let handle = new_task_with_arena(test_task, Args::empty(), 512, 1024, Priority::Normal, "arena");
+///
+/// start_scheduler(); 
+///
+/// fn test_task(_args: &mut Args) {
+///   // Any allocations done here come from the arena...
+///   loop {}
+/// }
+/// ```
+///
+/// # Panics
+///
+/// This function will panic if `MAX_TASKS` tasks already exist or if `MAX_ARENAS` arenas do, and
+/// aborts if there isn't enough memory for the task. Use `try_new_task_with_arena` to handle any of
+/// these cases.
+pub fn new_task_with_arena(code: fn(&mut Args), args: Args, stack_depth: usize, arena_size: usize,
+                           priority: Priority, name: &'static str) -> TaskHandle {
+  let g = CriticalSection::begin();
+  let task = arena::with_global_heap(|| {
+    let mut task = TaskControl::new(code, args, stack_depth, priority, name);
+    task.set_arena(Arena::new(arena_size));
+    Box::new(Node::new(task))
+  });
+  drop(g);
+
+  schedule_new(task)
+}
+
+/// Tries to create a new task with its own heap arena and put it into the task queue for running.
+/// It returns a `TaskHandle` to monitor the task with.
+///
+/// `try_new_task_with_arena` takes the same arguments as `new_task_with_arena`, but returns a
+/// `TaskError` instead of panicking or aborting. If `MAX_ARENAS` arenas already exist then
+/// `TaskError::TooManyArenas` is returned.
+pub fn try_new_task_with_arena(code: fn(&mut Args), args: Args, stack_depth: usize,
+                               arena_size: usize, priority: Priority, name: &'static str)
+                               -> Result<TaskHandle, TaskError> {
+  let g = CriticalSection::begin();
+  let task = arena::with_global_heap(|| {
+    let mut task = match TaskControl::try_new(code, args, stack_depth, priority, name) {
+      Some(task) => task,
+      None => return Err(TaskError::OutOfMemory),
+    };
+    match Arena::try_new(arena_size) {
+      Ok(arena) => task.set_arena(arena),
+      Err(ArenaError::OutOfMemory) => return Err(TaskError::OutOfMemory),
+      Err(ArenaError::TooManyArenas) => return Err(TaskError::TooManyArenas),
+    }
+    heap::try_box(Node::new(task)).map_err(|_| TaskError::OutOfMemory)
+  });
+  drop(g);
+
+  match task {
+    Ok(task) => try_schedule_new(task),
+    Err(error) => Err(error),
+  }
+}
+
+fn try_schedule_new<N: Into<NodePtr<TaskControl>>>(task: N) -> Result<TaskHandle, TaskError> {
+  let mut task = Some(task.into());
+  let mut handle = None;
+  svc::call(svc::SYS_NEW_TASK, &mut task as *mut _ as usize, &mut handle as *mut _ as usize, 0);
+  match handle {
+    Some(handle) => Ok(handle),
+    // The kernel left the task with us, it gets dropped here
+    None => Err(TaskError::TooManyTasks),
+  }
+}
+
+fn schedule_new<N: Into<NodePtr<TaskControl>>>(task: N) -> TaskHandle {
+  match try_schedule_new(task) {
+    Ok(handle) => handle,
+    Err(_) => panic!("schedule_new - too many tasks, the task table is full!"),
+  }
+}
+
+fn sys_new_task(task: usize, handle: usize, _: usize) -> usize {
+  // UNSAFE: The caller passes pointers to its own slots, and it waits for the syscall to finish
+  let (task, handle) = unsafe {
+    (&mut *(task as *mut Option<NodePtr<TaskControl>>), &mut *(handle as *mut Option<TaskHandle>))
+  };
+  let _g = CriticalSection::begin();
+  if let Some(mut node) = task.take() {
+    match TaskHandle::try_new(&mut **node) {
+      Some(new_handle) => {
+        *handle = Some(new_handle);
+        PRIORITY_QUEUES.enqueue(node);
+      },
+      None => *task = Some(node),
+    }
+  }
+  0
+}
+
+/// Creates a new task in statically allocated memory and puts it into the task queue for running.
+/// It returns a `TaskHandle` to monitor the task with.
+///
+/// `new_static_task` works the same way as `new_task`, except that instead of allocating the
+/// task's stack and control block on the heap it uses the memory reserved by a `StaticTask`. The
+/// depth of the stack is determined by the `StaticTask`. If `args` is `Args::empty()` then no heap
+/// allocation is done at all. Static tasks can be destroyed, but their memory can not be reused.
+///
+/// # Examples
+///
+/// ```rust,no_run
+/// #[macro_use]
+/// extern crate altos_core;
+///
+/// use altos_core::{start_scheduler, Priority};
+/// use altos_core::syscall::new_static_task;
+/// use altos_core::args::Args;
+///
+/// static_task!(static TEST_TASK: 512);
+///
+/// # fn main() {
+/// let handle = new_static_task(&TEST_TASK, test_task, Args::empty(), Priority::Normal, "static");
+///
+/// start_scheduler();
+/// # }
+///
+/// fn test_task(_args: &mut Args) {
+///   loop {}
+/// }
+/// ```
+///
+/// # Panics
+///
+/// This function will panic if `task` has already been used to create a task, or if `MAX_TASKS`
+/// tasks already exist. The table is checked before `task` is claimed, so a static task that
+/// couldn't be created can still be used once another task is destroyed.
+pub fn new_static_task<S>(task: &'static StaticTask<S>, code: fn(&mut Args), args: Args,
+                         priority: Priority, name: &'static str) -> TaskHandle
+  where S: 'static {
+  // Keep another task from taking the last slot between the check and the claim
+  let _g = CriticalSection::begin();
+  if ::task::table_is_full() {
+    panic!("new_static_task - too many tasks, the task table is full!");
+  }
+  let task = task.claim(code, args, priority, name);
+
+  schedule_new(task)
+}
+
+/// Creates a new task in the earliest deadline first scheduling class and puts it into the task
+/// queue for running. It returns a `TaskHandle` to monitor the task with.
+///
+/// `new_edf_task` takes the same arguments as `new_task`, except that instead of a priority it
+/// takes a `usize` argument for the task's period and a `usize` argument for its relative
+/// deadline, both in ticks. The first job of the task is released immediately. Each job should
+/// call `wait_next_period` when it's finished, if it is still running when its deadline passes
+/// the deadline miss hook is called.
+///
+/// Ready EDF tasks always run before fixed priority tasks, the one with the earliest deadline
+/// runs first.
+///
+/// # Examples
+///
+/// ```rust,no_run
+/// use altos_core::syscall::{new_edf_task, wait_next_period};
+/// use altos_core::args::Args;
+///
+/// // Run every 10 ticks, finishing within 5 ticks of each release
+/// new_edf_task(sensor_task, Args::empty(), 512, 10, 5, "sensor");
+///
+/// fn sensor_task(_args: &mut Args) {
+///   loop {
+///     // Read the sensors...
+///     wait_next_period();
+///   }
+/// }
+/// ```
+///
+/// # Panics
+///
+/// This function will panic if `period` is 0 or `deadline` is longer than `period`, or if
+/// `MAX_TASKS` tasks already exist.
+#[cfg(feature="edf")]
+pub fn new_edf_task(code: fn(&mut Args), args: Args, stack_depth: usize, period: usize,
+                    deadline: usize, name: &'static str) -> TaskHandle {
+  if period == 0 || deadline > period {
+    panic!("new_edf_task - deadline must be within a non-zero period!");
+  }
+  let g = CriticalSection::begin();
+  let mut task = arena::with_global_heap(|| {
+    Box::new(Node::new(TaskControl::new(code, args, stack_depth, Priority::Critical, name)))
+  });
+  drop(g);
+  task.deadline = Some(sched::Deadline::new(period, deadline, tick::get_tick()));
+
+  schedule_new(task)
+}
+
+/// Finishes the current job of an EDF task and blocks until its next period starts.
+///
+/// If the next period has already started because the task overran, it only yields so that any
+/// task with an earlier deadline gets to run first.
+///
+/// # Panics
+///
+/// This function will panic if the current task was not created with `new_edf_task`, or if the
+/// scheduler is locked.
+#[cfg(feature="edf")]
+pub fn wait_next_period() {
+  // Check before trapping, so the panic is raised by the task that made the mistake rather than by
+  // the kernel
+  {
+    let _g = CriticalSection::begin();
+    // UNSAFE: Accessing CURRENT_TASK
+    match unsafe { CURRENT_TASK.as_ref() } {
+      Some(current) if current.deadline.is_none() => {
+        panic!("wait_next_period - current task is not an EDF task!");
+      },
+      _ => {},
+    }
+  }
+  if sched::is_locked() {
+    panic!("wait_next_period - can't block while the scheduler is locked!");
+  }
+  svc::call(svc::SYS_WAIT_NEXT_PERIOD, 0, 0, 0);
+}
+
+#[cfg(feature="edf")]
+fn sys_wait_next_period(_: usize, _: usize, _: usize) -> usize {
+  let release = {
+    let _g = CriticalSection::begin();
+    // UNSAFE: Accessing CURRENT_TASK
+    let current = match unsafe { CURRENT_TASK.as_mut() } {
+      Some(current) => current,
+      None => panic!("wait_next_period - current task doesn't exist!"),
+    };
+    match current.deadline.as_mut() {
+      Some(deadline) => deadline.next_period(),
+      None => panic!("wait_next_period - current task is not an EDF task!"),
+    }
+  };
+  let wait = release.wrapping_sub(tick::get_tick()) as isize;
+  if wait > 0 {
+    sys_sleep_for(FOREVER_CHAN, wait as usize, 0)
+  }
+  else {
+    sys_yield(0, 0, 0)
+  }
 }
 
 /// Exits and destroys the currently running task. 
@@ -84,6 +412,11 @@ pub fn new_task(code: fn(&mut Args), args: Args, stack_depth: usize, priority: P
 /// This function will panic if the task is not successfully destroyed (i.e. it gets scheduled
 /// after this function is called), but this should never happen.
 pub fn exit() -> ! {
+  svc::call(svc::SYS_EXIT, 0, 0, 0);
+  panic!("syscall::exit - task returned from exit!");
+}
+
+fn sys_exit(_: usize, _: usize, _: usize) -> usize {
   // UNSAFE: This can only be called from the currently running task, so we know we're the only one
   // with a reference to the task. The destroy method is atomic so we don't have to worry about any
   // threading issues
@@ -91,12 +424,13 @@ pub fn exit() -> ! {
     debug_assert!(CURRENT_TASK.is_some());
     CURRENT_TASK.as_mut().unwrap().destroy();
   }
-  sched_yield();
-  panic!("syscall::exit - task returned from exit!");
+  sys_yield(0, 0, 0)
 }
 
 /// Yield the current task to the scheduler so another task can run.
 ///
+/// If the scheduler is locked the context switch is deferred until it is unlocked.
+///
 /// # Examples
 ///
 /// ```rust,no_run
@@ -114,7 +448,18 @@ pub fn exit() -> ! {
 /// }
 /// ```
 pub fn sched_yield() {
-  arch::yield_cpu();
+  svc::call(svc::SYS_YIELD, 0, 0, 0);
+}
+
+fn sys_yield(_: usize, _: usize, _: usize) -> usize {
+  if sched::is_locked() {
+    // The switch will happen once the scheduler is unlocked
+    sched::defer_switch();
+  }
+  else {
+    arch::yield_cpu();
+  }
+  0
 }
 
 /// Put the current task to sleep, waiting on a channel to be woken up.
@@ -144,6 +489,11 @@ pub fn sleep(wchan: usize) {
 /// `sleep_for` takes a `usize` argument that acts as an identifier to wake up the task. It also
 /// takes a second `usize` argument for the maximum ticks it should sleep before waking.
 ///
+/// # Panics
+///
+/// This function will panic if the scheduler is locked, since the task would never give up the
+/// CPU.
+///
 /// # Examples
 ///
 /// ```no_run
@@ -153,9 +503,22 @@ pub fn sleep(wchan: usize) {
 /// sleep_for(FOREVER_CHAN, 300);
 /// ```
 pub fn sleep_for(wchan: usize, delay: usize) {
+  // Check before trapping, so the panic is raised by the task that made the mistake rather than by
+  // the kernel
+  if sched::is_locked() {
+    panic!("sleep_for - can't block while the scheduler is locked!");
+  }
+  svc::call(svc::SYS_SLEEP_FOR, wchan, delay, 0);
+}
+
+fn sys_sleep_for(wchan: usize, delay: usize, _: usize) -> usize {
+  if sched::is_locked() {
+    panic!("sleep_for - can't block while the scheduler is locked!");
+  }
   // Make the critical section for the whole function, wouldn't want to be rude and make a task
   // give up its time slice for no reason
   let _g = CriticalSection::begin();
+  trace_event!(Sleep(wchan));
   // UNSAFE: Accessing CURRENT_TASK
   unsafe {
     if let Some(current) = CURRENT_TASK.as_mut() {
@@ -177,7 +540,7 @@ pub fn sleep_for(wchan: usize, delay: usize) {
       panic!("sleep_for - current task doesn't exist!");
     }
   }
-  sched_yield();
+  sys_yield(0, 0, 0)
 }
 
 /// Wake up all tasks sleeping on a channel.
@@ -185,17 +548,73 @@ pub fn sleep_for(wchan: usize, delay: usize) {
 /// `wake` takes a `usize` argument that acts as an identifier to only wake up tasks sleeping on
 /// that same identifier. 
 pub fn wake(wchan: usize) {
+  svc::call(svc::SYS_WAKE, wchan, 0, 0);
+}
+
+fn sys_wake(wchan: usize, _: usize, _: usize) -> usize {
   // Since we're messing around with all the task queues, lets make sure everything gets done at 
   // once
   let _g = CriticalSection::begin();
+  trace_event!(Wake(wchan));
+  // A task that went to sleep inside a critical section hasn't been switched out yet, so it isn't
+  // in any of the sleep queues
+  // UNSAFE: Accessing CURRENT_TASK
+  if let Some(current) = unsafe { CURRENT_TASK.as_mut() } {
+    if current.state == State::Blocked && current.wchan == wchan {
+      current.wchan = 0;
+      current.state = State::Ready;
+    }
+  }
   let mut to_wake = SLEEP_QUEUE.remove(|task| task.wchan == wchan);
   to_wake.append(DELAY_QUEUE.remove(|task| task.wchan == wchan));
   to_wake.append(OVERFLOW_DELAY_QUEUE.remove(|task| task.wchan == wchan));
   for mut task in to_wake.into_iter() {
     task.wchan = 0;
     task.state = State::Ready;
-    PRIORITY_QUEUES[task.priority].enqueue(task);
+    PRIORITY_QUEUES.enqueue(task);
+  }
+  0
+}
+
+// The syscalls behind `TaskHandle::set_priority` and `TaskHandle::set_time_slice`
+
+fn sys_set_priority(handle: usize, level: usize, _: usize) -> usize {
+  let priority = match Priority::try_new(level) {
+    Some(priority) => priority,
+    None => return svc::INVALID_ARGUMENT,
+  };
+  let _g = CriticalSection::begin();
+  // UNSAFE: The caller passes a pointer to its handle, and it waits for the syscall to finish
+  let handle = unsafe { &mut *(handle as *mut TaskHandle) };
+  let (old, tid, state) = match handle.task_mut() {
+    Ok(task) => {
+      let old = task.priority;
+      task.priority = priority;
+      (old, task.tid(), task.state)
+    },
+    Err(error) => return svc::from_handle_result(Err(error)),
+  };
+  // A ready task has to move to the queue for its new priority, the current task isn't queued
+  if state == State::Ready {
+    if let Some(task) = PRIORITY_QUEUES.remove(old, tid) {
+      PRIORITY_QUEUES.enqueue(task);
+    }
   }
+  // UNSAFE: Accessing CURRENT_TASK
+  if let Some(current) = unsafe { CURRENT_TASK.as_ref() } {
+    if PRIORITY_QUEUES.preempts(current, false) {
+      sys_yield(0, 0, 0);
+    }
+  }
+  0
+}
+
+fn sys_set_task_time_slice(handle: usize, has_ticks: usize, ticks: usize) -> usize {
+  let ticks = if has_ticks != 0 { Some(ticks) } else { None };
+  let _g = CriticalSection::begin();
+  // UNSAFE: The caller passes a pointer to its handle, and it waits for the syscall to finish
+  let handle = unsafe { &mut *(handle as *mut TaskHandle) };
+  svc::from_handle_result(handle.task_mut().map(|task| task.set_time_slice(ticks)))
 }
 
 /// Update the system tick count and wake up any delayed tasks that need to be woken
@@ -217,7 +636,7 @@ pub fn system_tick() {
     task.wchan = 0;
     task.state = State::Ready;
     task.delay = 0;
-    PRIORITY_QUEUES[task.priority].enqueue(task);
+    PRIORITY_QUEUES.enqueue(task);
   }
 
   if ticks == !0 {
@@ -225,20 +644,46 @@ pub fn system_tick() {
     DELAY_QUEUE.append(overflowed);
   }
 
+  #[cfg(feature="watchdog")]
+  ::watchdog::check(ticks);
+
   // UNSAFE: Accessing CURRENT_TASK
-  let current_priority = unsafe { 
-    match CURRENT_TASK.as_ref() {
-      Some(task) => task.priority,
+  let current = unsafe { 
+    match CURRENT_TASK.as_mut() {
+      Some(task) => task,
       None => panic!("system_tick - current task doesn't exist!"),
     }
   };
+
+  #[cfg(feature="edf")]
+  {
+    // Report any EDF tasks that have run past their deadline
+    sched::edf::check_deadline(current, ticks);
+    PRIORITY_QUEUES.check_deadlines(ticks);
+    SLEEP_QUEUE.modify_all(|task| sched::edf::check_deadline(task, ticks));
+    DELAY_QUEUE.modify_all(|task| sched::edf::check_deadline(task, ticks));
+    OVERFLOW_DELAY_QUEUE.modify_all(|task| sched::edf::check_deadline(task, ticks));
+  }
+
+  // Check if the current task has used up its time slice
+  let slice = sched::time_slice(current);
+  let slice_expired = if slice == 0 {
+    false
+  }
+  else {
+    current.slice_ticks += 1;
+    current.slice_ticks >= slice
+  };
   
-  for i in current_priority.higher() {
-    if !PRIORITY_QUEUES[i].is_empty() {
-      // Only context switch if there's another task at the same or higher priority level
-      sched_yield();
-      break;
-    }
+  // Only context switch if there's a task at a higher priority level, or at the same level
+  // once the current task's time slice is over
+  if PRIORITY_QUEUES.preempts(current, slice_expired) {
+    sched_yield();
+    return;
+  }
+  if slice_expired {
+    // Nobody else wants to run, so start a new time slice
+    current.slice_ticks = 0;
   }
 }
 
@@ -258,7 +703,99 @@ mod tests {
     assert_eq!(handle.state(), Ok(State::Ready));
     assert_eq!(handle.stack_size(), Ok(512));
 
-    assert_not!(PRIORITY_QUEUES[Priority::Normal].remove_all().is_empty());
+    assert_not!(PRIORITY_QUEUES.remove_all(Priority::Normal).is_empty());
+  }
+
+  #[test]
+  fn test_try_new_task() {
+    let _g = test::set_up();
+    let handle = try_new_task(test_task, Args::empty(), 512, Priority::Normal, "test try task");
+    assert_eq!(handle.ok().unwrap().stack_size(), Ok(512));
+
+    assert_not!(PRIORITY_QUEUES.remove_all(Priority::Normal).is_empty());
+  }
+
+  #[test]
+  fn test_try_new_task_out_of_memory() {
+    let _g = test::set_up();
+    let handle = try_new_task(test_task, Args::empty(), usize::max_value() / 2, Priority::Normal,
+                              "test huge task");
+    assert_eq!(handle.err(), Some(TaskError::OutOfMemory));
+
+    assert!(PRIORITY_QUEUES.remove_all(Priority::Normal).is_empty());
+  }
+
+  #[test]
+  fn test_try_new_task_with_arena() {
+    let _g = test::set_up();
+    let handle = try_new_task_with_arena(test_task, Args::empty(), 512, 256, Priority::Normal,
+                                         "test try arena task");
+    assert_eq!(handle.ok().unwrap().stack_size(), Ok(512));
+
+    let mut queue = PRIORITY_QUEUES.remove_all(Priority::Normal);
+    let task = queue.dequeue().unwrap();
+    assert_eq!(task.arena().map(|arena| arena.size()), Some(256));
+  }
+
+  #[test]
+  fn test_try_new_task_with_arena_out_of_memory() {
+    let _g = test::set_up();
+    let handle = try_new_task_with_arena(test_task, Args::empty(), 512, usize::max_value() / 2,
+                                         Priority::Normal, "test huge arena task");
+    assert_eq!(handle.err(), Some(TaskError::OutOfMemory));
+
+    assert!(PRIORITY_QUEUES.remove_all(Priority::Normal).is_empty());
+  }
+
+  #[test]
+  fn test_new_task_numeric_priority() {
+    let _g = test::set_up();
+    let handle = new_task(test_task, Args::empty(), 512, Priority::new(0), "test numeric task");
+    assert_eq!(handle.priority(), Ok(Priority::Critical));
+
+    assert_not!(PRIORITY_QUEUES.remove_all(Priority::Critical).is_empty());
+  }
+
+  #[test]
+  #[should_panic]
+  fn test_new_task_idle_priority() {
+    let _g = test::set_up();
+    new_task(test_task, Args::empty(), 512, Priority::new(Priority::__Idle.level()),
+             "test idle task");
+  }
+
+  #[test]
+  fn test_new_static_task() {
+    static_task!(static STATIC_TASK: 512);
+
+    let _g = test::set_up();
+    let handle = new_static_task(&STATIC_TASK, test_task, Args::empty(), Priority::Normal, 
+                                 "test static task");
+    assert_eq!(handle.name(), Ok("test static task"));
+    assert_eq!(handle.priority(), Ok(Priority::Normal));
+    assert_eq!(handle.state(), Ok(State::Ready));
+    assert_eq!(handle.stack_size(), Ok(512));
+
+    let mut queue = PRIORITY_QUEUES.remove_all(Priority::Normal);
+    let mut task = queue.dequeue().unwrap();
+    task.unregister();
+    // Dropping a static task's node must leave its memory alone
+    drop(task);
+    assert!(queue.is_empty());
+    assert_eq!(STATIC_TASK.depth(), 512);
+  }
+
+  #[test]
+  fn test_new_task_with_arena() {
+    let _g = test::set_up();
+    let handle = new_task_with_arena(test_task, Args::empty(), 512, 256, Priority::Normal,
+                                     "test arena task");
+    assert_eq!(handle.name(), Ok("test arena task"));
+    assert_eq!(handle.stack_size(), Ok(512));
+
+    let mut queue = PRIORITY_QUEUES.remove_all(Priority::Normal);
+    let task = queue.dequeue().unwrap();
+    assert_eq!(task.arena().map(|arena| arena.size()), Some(256));
   }
 
   #[test]
@@ -278,6 +815,132 @@ mod tests {
     assert_eq!(handle_2.tid(), Ok(test::current_task().unwrap().tid()));
   }
 
+  #[test]
+  fn test_sched_yield_deferred_while_locked() {
+    let _g = test::set_up();
+    let (handle_1, handle_2) = test::create_two_tasks();
+
+    start_scheduler();
+    assert_eq!(handle_1.tid(), Ok(test::current_task().unwrap().tid()));
+
+    let guard = sched::lock();
+    sched_yield();
+    assert_eq!(handle_1.tid(), Ok(test::current_task().unwrap().tid()));
+
+    // The deferred switch happens on unlock
+    sched::unlock(guard);
+    assert_eq!(handle_2.tid(), Ok(test::current_task().unwrap().tid()));
+  }
+
+  #[test]
+  fn test_set_priority_preempts() {
+    let _g = test::set_up();
+    let (handle_1, mut handle_2) = test::create_two_tasks();
+
+    start_scheduler();
+    assert_eq!(handle_1.tid(), Ok(test::current_task().unwrap().tid()));
+
+    handle_2.set_priority(Priority::Critical).unwrap();
+    assert_eq!(handle_2.tid(), Ok(test::current_task().unwrap().tid()));
+    assert_eq!(handle_2.priority(), Ok(Priority::Critical));
+  }
+
+  #[test]
+  fn test_set_priority_requeues() {
+    let _g = test::set_up();
+    let (handle_1, mut handle_2) = test::create_two_tasks();
+
+    start_scheduler();
+    handle_2.set_priority(Priority::Low).unwrap();
+    assert!(PRIORITY_QUEUES.is_empty(Priority::Normal));
+    assert_eq!(PRIORITY_QUEUES.highest(), Some(Priority::Low));
+
+    // Nothing of the same priority is left to share the CPU with
+    sched_yield();
+    assert_eq!(handle_1.tid(), Ok(test::current_task().unwrap().tid()));
+  }
+
+  #[test]
+  fn test_system_tick_time_slice() {
+    let _g = test::set_up();
+    let (mut handle_1, handle_2) = test::create_two_tasks();
+    handle_1.set_time_slice(Some(3)).unwrap();
+
+    start_scheduler();
+    system_tick();
+    system_tick();
+    assert_eq!(handle_1.tid(), Ok(test::current_task().unwrap().tid()));
+
+    system_tick();
+    assert_eq!(handle_2.tid(), Ok(test::current_task().unwrap().tid()));
+  }
+
+  #[test]
+  fn test_system_tick_fifo() {
+    let _g = test::set_up();
+    let (mut handle_1, handle_2) = test::create_two_tasks();
+    handle_1.set_time_slice(Some(0)).unwrap();
+
+    start_scheduler();
+    for _ in 0..10 {
+      system_tick();
+    }
+    assert_eq!(handle_1.tid(), Ok(test::current_task().unwrap().tid()));
+
+    // It still gives up the CPU when it yields
+    sched_yield();
+    assert_eq!(handle_2.tid(), Ok(test::current_task().unwrap().tid()));
+  }
+
+  #[test]
+  fn test_system_tick_higher_priority_preempts_slice() {
+    let _g = test::set_up();
+    let mut handle_1 = test::create_and_schedule_test_task(512, Priority::Normal, "test task 1");
+    handle_1.set_time_slice(Some(0)).unwrap();
+
+    start_scheduler();
+    assert_eq!(handle_1.tid(), Ok(test::current_task().unwrap().tid()));
+    let handle_2 = test::create_and_schedule_test_task(512, Priority::Critical, "test task 2");
+    system_tick();
+    assert_eq!(handle_2.tid(), Ok(test::current_task().unwrap().tid()));
+  }
+
+  #[test]
+  fn test_system_tick_deferred_while_locked() {
+    let _g = test::set_up();
+    let (handle_1, handle_2) = test::create_two_tasks();
+
+    start_scheduler();
+    let guard = sched::lock();
+    system_tick();
+    assert_eq!(handle_1.tid(), Ok(test::current_task().unwrap().tid()));
+
+    drop(guard);
+    assert_eq!(handle_2.tid(), Ok(test::current_task().unwrap().tid()));
+  }
+
+  #[test]
+  fn test_unlock_without_pending_switch() {
+    let _g = test::set_up();
+    let (handle_1, _handle_2) = test::create_two_tasks();
+
+    start_scheduler();
+    let guard = sched::lock();
+    sched::unlock(guard);
+    assert_eq!(handle_1.tid(), Ok(test::current_task().unwrap().tid()));
+  }
+
+  #[test]
+  #[should_panic]
+  fn test_sleep_while_locked() {
+    let _g = test::set_up();
+    test::create_and_schedule_test_task(512, Priority::Normal, "locked sleep");
+
+    start_scheduler();
+    let _lock = sched::lock();
+    sleep(FOREVER_CHAN);
+  }
+
   #[test]
   fn test_sleep() {
     let _g = test::set_up();
@@ -334,6 +997,26 @@ mod tests {
     assert_eq!(handle_1.tid(), Ok(test::current_task().unwrap().tid()));
   }
 
+  #[test]
+  fn test_wake_before_switch() {
+    let _g = test::set_up();
+    let (handle_1, _handle_2) = test::create_two_tasks();
+    start_scheduler();
+
+    // Pretend the task went to sleep in a critical section, so the switch hasn't happened yet
+    {
+      let current = test::current_task().unwrap();
+      current.wchan = !FOREVER_CHAN;
+      current.delay_type = Delay::Sleep;
+      current.state = State::Blocked;
+    }
+    wake(!FOREVER_CHAN);
+    assert_eq!(handle_1.state(), Ok(State::Ready));
+
+    sched_yield();
+    assert_ne!(handle_1.state(), Ok(State::Blocked));
+  }
+
   #[test]
   fn test_system_tick() {
     let _g = test::set_up();
@@ -479,5 +1162,132 @@ mod tests {
     assert_eq!(handle_1.tid(), Ok(test::current_task().unwrap().tid()));
   }
 
+  #[test]
+  fn test_scenario_round_robin_timeline() {
+    let mut harness = test::Harness::new();
+    let handle_1 = harness.spawn(Priority::Normal, "test task 1");
+    let handle_2 = harness.spawn(Priority::Normal, "test task 2");
+    let (tid_1, tid_2) = (handle_1.tid().unwrap(), handle_2.tid().unwrap());
+
+    harness.start();
+    harness.run_for(3);
+    assert_eq!(harness.timeline(), vec![test::Switch { tick: 0, tid: tid_1 },
+                                        test::Switch { tick: 1, tid: tid_2 },
+                                        test::Switch { tick: 2, tid: tid_1 },
+                                        test::Switch { tick: 3, tid: tid_2 }]);
+  }
+
+  #[test]
+  fn test_scenario_critical_preempts_within_one_tick() {
+    const WCHAN: usize = 0xC0FFEE;
+    let mut harness = test::Harness::new();
+    let critical = harness.spawn(Priority::Critical, "critical");
+    let normal = harness.spawn(Priority::Normal, "normal");
+
+    harness.start();
+    assert!(harness.is_running(&critical));
+    sleep(WCHAN);
+    assert!(harness.is_running(&normal));
+
+    harness.interrupt_at(3, || wake(WCHAN));
+    harness.run_for(5);
+    assert_eq!(harness.switches_to(&critical), vec![0, 3]);
+    assert_eq!(harness.switches_to(&normal), vec![0]);
+  }
+
+  #[test]
+  fn test_scenario_sleep_for_wraps_at_overflow() {
+    let mut harness = test::Harness::starting_at(!0 - 2);
+    let sleeper = harness.spawn(Priority::Normal, "sleeper");
+    let mut other = harness.spawn(Priority::Normal, "other");
+    other.set_time_slice(Some(0)).unwrap();
+
+    harness.start();
+    assert!(harness.is_running(&sleeper));
+    // The wakeup tick wraps past 0
+    sleep_for(FOREVER_CHAN, 5);
+    assert!(harness.is_running(&other));
+
+    harness.run_for(4);
+    assert_eq!(sleeper.state(), Ok(State::Blocked));
+    harness.run_for(1);
+    assert_eq!(harness.now(), 5);
+    assert_ne!(sleeper.state(), Ok(State::Blocked));
+  }
+
+  #[test]
+  #[cfg(feature="edf")]
+  fn test_edf_earliest_deadline_first() {
+    let _g = test::set_up();
+    let _fixed = test::create_and_schedule_test_task(512, Priority::Critical, "fixed task");
+    let late = new_edf_task(test_task, Args::empty(), 512, 100, 50, "late task");
+    let early = new_edf_task(test_task, Args::empty(), 512, 100, 20, "early task");
+
+    start_scheduler();
+    assert_eq!(early.tid(), Ok(test::current_task().unwrap().tid()));
+
+    // The earlier deadline keeps running until its job is done
+    sched_yield();
+    assert_eq!(early.tid(), Ok(test::current_task().unwrap().tid()));
+
+    wait_next_period();
+    assert_eq!(early.state(), Ok(State::Blocked));
+    assert_eq!(late.tid(), Ok(test::current_task().unwrap().tid()));
+  }
+
+  #[test]
+  #[should_panic]
+  #[cfg(feature="edf")]
+  fn test_wait_next_period_fixed_priority() {
+    let _g = test::set_up();
+    test::create_and_schedule_test_task(512, Priority::Normal, "fixed task");
+
+    start_scheduler();
+    wait_next_period();
+  }
+
+  #[test]
+  #[cfg(feature="edf")]
+  fn test_edf_preempts_fixed_priority() {
+    let _g = test::set_up();
+    let fixed = test::create_and_schedule_test_task(512, Priority::Critical, "fixed task");
+
+    start_scheduler();
+    assert_eq!(fixed.tid(), Ok(test::current_task().unwrap().tid()));
+    let edf = new_edf_task(test_task, Args::empty(), 512, 100, 50, "edf task");
+    system_tick();
+    assert_eq!(edf.tid(), Ok(test::current_task().unwrap().tid()));
+  }
+
+  #[test]
+  #[cfg(feature="edf")]
+  fn test_edf_deadline_miss_hook() {
+    use atomic::{AtomicUsize, ATOMIC_USIZE_INIT, Ordering};
+    static MISSES: AtomicUsize = ATOMIC_USIZE_INIT;
+    fn count_miss(miss: sched::DeadlineMiss) {
+      assert_eq!(miss.name, "edf task");
+      MISSES.fetch_add(1, Ordering::SeqCst);
+    }
+
+    let _g = test::set_up();
+    sched::set_deadline_miss_hook(count_miss);
+    new_edf_task(test_task, Args::empty(), 512, 10, 2, "edf task");
+
+    start_scheduler();
+    for _ in 0..5 {
+      system_tick();
+    }
+    // A missed deadline is only reported once
+    assert_eq!(MISSES.load(Ordering::SeqCst), 1);
+  }
+
+  #[test]
+  #[should_panic]
+  #[cfg(feature="edf")]
+  fn test_new_edf_task_deadline_past_period() {
+    let _g = test::set_up();
+    new_edf_task(test_task, Args::empty(), 512, 10, 20, "bad edf task");
+  }
+
   fn test_task(_args: &mut Args) {}
 }
@@ -0,0 +1,227 @@
+// syscall/svc.rs
+// AltOSRust
+//
+// Created by Daniel Seitz on 2/26/17
+
+//! Supervisor call entry into the kernel.
+//!
+//! The syscalls in the table below trap into the kernel through the architecture's supervisor
+//! call when they're made from a task, so their work is done in handler mode. A syscall is
+//! identified by its number and takes up to three word sized arguments, which are passed in
+//! registers along with the number. The result comes back as a single word.
+//!
+//! The numbers in this module are the kernel's ABI and must never be reused or changed, an
+//! application image that was built separately from the kernel only needs them to make syscalls.
+//!
+//! Syscalls made by the kernel itself, like from an interrupt handler, are dispatched in place
+//! since they're already in handler mode. So are syscalls made by a task inside a critical section
+//! on architectures that can't trap with interrupts masked.
+//!
+//! Arguments that don't fit in a word, like a new task or a task handle, are passed as pointers to
+//! memory owned by the caller, which is blocked until the syscall returns. Syscalls on a task
+//! handle return one of the `HANDLE_*` results.
+//!
+//! Creating a task still allocates it in the calling task's context, only registering it in the
+//! task table and putting it in a ready queue happen in the kernel.
+
+use arch;
+use atomic::{AtomicBool, Ordering};
+use task::HandleError;
+
+/// Yield the CPU to the scheduler. Takes no arguments.
+pub const SYS_YIELD: usize = 0;
+/// Put the current task to sleep. Takes the channel and the maximum number of ticks to sleep for.
+pub const SYS_SLEEP_FOR: usize = 1;
+/// Wake the tasks sleeping on a channel. Takes the channel.
+pub const SYS_WAKE: usize = 2;
+/// Destroy the current task. Takes no arguments and never returns to the task.
+pub const SYS_EXIT: usize = 3;
+/// Schedule a newly created task. Takes a pointer to an `Option<NodePtr<TaskControl>>` holding
+/// the task and a pointer to an `Option<TaskHandle>` that the task's handle is written to. If the
+/// task table is full the handle is left as `None` and the task is left with the caller.
+pub const SYS_NEW_TASK: usize = 4;
+/// Block the current EDF task until its next period. Takes no arguments.
+pub const SYS_WAIT_NEXT_PERIOD: usize = 5;
+/// Change the priority of a task. Takes a pointer to the task's `TaskHandle` and the new priority
+/// level.
+pub const SYS_SET_PRIORITY: usize = 6;
+/// Set the time slice for a priority. Takes the priority level and the number of ticks.
+pub const SYS_SET_TIME_SLICE: usize = 7;
+/// Set the time slice of a single task. Takes a pointer to the task's `TaskHandle`, 1 if the task
+/// gets its own time slice or 0 to use its priority's, and the number of ticks.
+pub const SYS_SET_TASK_TIME_SLICE: usize = 8;
+/// Lock the scheduler. Takes no arguments.
+pub const SYS_SCHED_LOCK: usize = 9;
+/// Release one lock on the scheduler, running any deferred context switch once it's unlocked.
+/// Takes no arguments.
+pub const SYS_SCHED_UNLOCK: usize = 10;
+
+/// The result of a syscall with a number that isn't in the table.
+pub const INVALID_SYSCALL: usize = !0;
+/// The result of a syscall that was passed an argument that's out of range.
+pub const INVALID_ARGUMENT: usize = !0 - 1;
+
+/// The result of a syscall on a task handle whose task has been destroyed.
+pub const HANDLE_DESTROYED: usize = 1;
+/// The result of a syscall on a task handle whose task no longer exists.
+pub const HANDLE_NOT_FOUND: usize = 2;
+
+/// Set while a syscall made by a task is run in place rather than through a trap.
+static IN_PLACE: AtomicBool = AtomicBool::new(false);
+
+type Handler = fn(usize, usize, usize) -> usize;
+
+/// The syscall table, indexed by syscall number.
+static TABLE: [Handler; 11] = [super::sys_yield,                // SYS_YIELD
+                               super::sys_sleep_for,            // SYS_SLEEP_FOR
+                               super::sys_wake,                 // SYS_WAKE
+                               super::sys_exit,                 // SYS_EXIT
+                               super::sys_new_task,             // SYS_NEW_TASK
+                               WAIT_NEXT_PERIOD,                // SYS_WAIT_NEXT_PERIOD
+                               super::sys_set_priority,         // SYS_SET_PRIORITY
+                               ::sched::sys_set_time_slice,     // SYS_SET_TIME_SLICE
+                               super::sys_set_task_time_slice,  // SYS_SET_TASK_TIME_SLICE
+                               ::sched::sys_lock,               // SYS_SCHED_LOCK
+                               ::sched::sys_unlock];            // SYS_SCHED_UNLOCK
+
+// Keep the number reserved when EDF scheduling is compiled out
+#[cfg(feature="edf")]
+const WAIT_NEXT_PERIOD: Handler = super::sys_wait_next_period;
+#[cfg(not(feature="edf"))]
+const WAIT_NEXT_PERIOD: Handler = invalid;
+
+fn invalid(_: usize, _: usize, _: usize) -> usize {
+  INVALID_SYSCALL
+}
+
+/// Makes syscall `number` with the given arguments, returning its result.
+///
+/// If the kernel is already running this dispatches the call directly, otherwise it traps into the
+/// kernel through the architecture's supervisor call.
+pub fn call(number: usize, arg0: usize, arg1: usize, arg2: usize) -> usize {
+  if arch::in_kernel_mode() {
+    dispatch(number, [arg0, arg1, arg2])
+  }
+  else {
+    arch::system_call(number, [arg0, arg1, arg2])
+  }
+}
+
+/// Runs syscall `number` with the arguments that were passed in registers.
+///
+/// This is called by the architecture's supervisor call handler, it must only be called from the
+/// kernel. Returns `INVALID_SYSCALL` if there is no syscall with that number.
+pub fn dispatch(number: usize, args: [usize; 3]) -> usize {
+  debug_assert!(arch::in_kernel_mode() || IN_PLACE.load(Ordering::SeqCst));
+  match TABLE.get(number) {
+    Some(handler) => handler(args[0], args[1], args[2]),
+    None => INVALID_SYSCALL,
+  }
+}
+
+/// Runs syscall `number` for a task without trapping into the kernel.
+///
+/// Some architectures can't take a supervisor call while interrupts are masked, their
+/// `system_call` uses this instead when a task makes a syscall inside a critical section. Nothing
+/// else can run until the critical section ends, so the syscall is still atomic. Returns
+/// `INVALID_SYSCALL` if there is no syscall with that number.
+pub fn dispatch_in_place(number: usize, args: [usize; 3]) -> usize {
+  let old = IN_PLACE.swap(true, Ordering::SeqCst);
+  let result = dispatch(number, args);
+  IN_PLACE.store(old, Ordering::SeqCst);
+  result
+}
+
+/// Packs the result of a syscall on a task handle into a word.
+pub fn from_handle_result(result: Result<(), HandleError>) -> usize {
+  match result {
+    Ok(()) => 0,
+    Err(HandleError::Destroyed) => HANDLE_DESTROYED,
+    Err(HandleError::NotFound) => HANDLE_NOT_FOUND,
+  }
+}
+
+/// Unpacks the result of a syscall on a task handle.
+///
+/// # Panics
+///
+/// This function will panic if `result` isn't a handle syscall's result.
+pub fn to_handle_result(result: usize) -> Result<(), HandleError> {
+  match result {
+    0 => Ok(()),
+    HANDLE_DESTROYED => Err(HandleError::Destroyed),
+    HANDLE_NOT_FOUND => Err(HandleError::NotFound),
+    _ => panic!("to_handle_result - not the result of a handle syscall!"),
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use test;
+  use task::{State, NUM_PRIORITIES};
+  use sched;
+  use sync::CriticalSection;
+
+  #[test]
+  fn unknown_syscall() {
+    let _g = test::set_up();
+    assert_eq!(dispatch(TABLE.len(), [0, 0, 0]), INVALID_SYSCALL);
+    assert_eq!(call(!0, 1, 2, 3), INVALID_SYSCALL);
+  }
+
+  #[test]
+  fn argument_out_of_range() {
+    let _g = test::set_up();
+    assert_eq!(dispatch(SYS_SET_TIME_SLICE, [NUM_PRIORITIES, 1, 0]), INVALID_ARGUMENT);
+  }
+
+  #[test]
+  fn handle_results() {
+    for result in &[Ok(()), Err(HandleError::Destroyed), Err(HandleError::NotFound)] {
+      assert_eq!(to_handle_result(from_handle_result(*result)), *result);
+    }
+  }
+
+  #[test]
+  fn sleep_from_task() {
+    let _g = test::set_up();
+    let (handle_1, handle_2) = test::create_two_tasks();
+    sched::start_scheduler();
+
+    // Pretend the call came from the task, so it goes through the arch's supervisor call
+    arch::set_kernel_mode(false);
+    call(SYS_SLEEP_FOR, 0xdead, 0, 0);
+    arch::set_kernel_mode(true);
+    assert_eq!(handle_1.state(), Ok(State::Blocked));
+    assert_eq!(handle_2.tid(), Ok(test::current_task().unwrap().tid()));
+  }
+
+  #[test]
+  fn sleep_from_task_in_critical_section() {
+    let _g = test::set_up();
+    let (handle_1, handle_2) = test::create_two_tasks();
+    sched::start_scheduler();
+
+    arch::set_kernel_mode(false);
+    {
+      let _cs = CriticalSection::begin();
+      call(SYS_SLEEP_FOR, 0xdead, 0, 0);
+    }
+    arch::set_kernel_mode(true);
+    assert_eq!(handle_1.state(), Ok(State::Blocked));
+    assert_eq!(handle_2.tid(), Ok(test::current_task().unwrap().tid()));
+  }
+
+  #[test]
+  fn wake_through_table() {
+    let _g = test::set_up();
+    let (handle_1, _handle_2) = test::create_two_tasks();
+    sched::start_scheduler();
+
+    call(SYS_SLEEP_FOR, 0xbeef, 0, 0);
+    assert_eq!(handle_1.state(), Ok(State::Blocked));
+    assert_eq!(dispatch(SYS_WAKE, [0xbeef, 0, 0]), 0);
+    assert_eq!(handle_1.state(), Ok(State::Ready));
+  }
+}
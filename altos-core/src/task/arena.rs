@@ -0,0 +1,386 @@
+// task/arena.rs
+// AltOSRust
+//
+// Created by Daniel Seitz on 2/9/17
+
+//! Per-task heap arenas.
+//!
+//! A task can optionally be created with its own arena, a block of memory taken from the global
+//! heap when the task is created. While the task is running, all of its dynamic allocations are
+//! served out of its arena instead of the global heap. When the task is destroyed the whole arena
+//! is freed at once, so any memory the task forgot to free is not leaked.
+//!
+//! Memory allocated out of an arena must never be handed off to another task, it will become
+//! invalid as soon as the owning task is reclaimed. Freeing it from somewhere else is harmless
+//! though, every live arena is registered so its memory is recognized no matter which task or
+//! interrupt handler frees it.
+
+use alloc::heap;
+use sched::CURRENT_TASK;
+use sync::{SpinMutex, CriticalSection};
+use atomic::{AtomicUsize, ATOMIC_USIZE_INIT, Ordering};
+use arch;
+
+/// The most arenas that can exist at one time.
+pub const MAX_ARENAS: usize = 16;
+
+// The [start, end) range of every live arena
+static ARENAS: SpinMutex<[Option<(usize, usize)>; MAX_ARENAS]> = SpinMutex::new([None; MAX_ARENAS]);
+// The number of ranges in ARENAS, so frees can skip the search when there are no arenas
+static LIVE_ARENAS: AtomicUsize = ATOMIC_USIZE_INIT;
+
+/// The reasons creating an arena can fail.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum ArenaError {
+  /// There wasn't enough memory in the global heap for the arena.
+  OutOfMemory,
+
+  /// `MAX_ARENAS` arenas already exist.
+  TooManyArenas,
+}
+
+/// A block of memory that a single task does all of its allocations from.
+///
+/// Allocation is done by bumping a pointer through the block, freeing individual allocations does
+/// nothing. The memory is returned to the global heap when the `Arena` is dropped.
+#[derive(Debug)]
+pub struct Arena {
+  base: *mut u8,
+  size: usize,
+  next: usize,
+  suspended: usize,
+}
+
+impl Arena {
+  /// Creates a new arena, allocating `size` bytes from the global heap.
+  ///
+  /// # Panics
+  ///
+  /// This function will panic if `MAX_ARENAS` arenas already exist, and aborts if there isn't
+  /// enough memory for the arena. Use `try_new` to handle either case.
+  pub fn new(size: usize) -> Self {
+    match Self::try_new(size) {
+      Ok(arena) => arena,
+      Err(ArenaError::OutOfMemory) => {
+        ::heap::out_of_memory(size, ::core::mem::align_of::<usize>())
+      },
+      Err(ArenaError::TooManyArenas) => panic!("Arena::new - too many arenas exist!"),
+    }
+  }
+
+  /// Creates a new arena like `new`, but returns an error instead of panicking or aborting.
+  pub fn try_new(size: usize) -> Result<Self, ArenaError> {
+    let align = ::core::mem::align_of::<usize>();
+    // UNSAFE: We're touching the allocation interface, the arena keeps track of the memory and
+    // frees it when it is dropped.
+    let base = unsafe { heap::allocate(size, align) };
+    if base.is_null() {
+      return Err(ArenaError::OutOfMemory);
+    }
+    if !register(base as usize, size) {
+      // UNSAFE: We just allocated this block, nothing else knows about it
+      unsafe { heap::deallocate(base, size, align) };
+      return Err(ArenaError::TooManyArenas);
+    }
+
+    Ok(Arena {
+      base: base,
+      size: size,
+      next: base as usize,
+      suspended: 0,
+    })
+  }
+
+  /// Allocates a block of memory with the given size and alignment out of the arena.
+  ///
+  /// Returns `None` if there isn't enough space left in the arena.
+  pub fn allocate(&mut self, size: usize, align: usize) -> Option<*mut u8> {
+    let start = align_up(self.next, align);
+    let end = start.saturating_add(size);
+
+    if end <= self.base as usize + self.size {
+      self.next = end;
+      Some(start as *mut u8)
+    }
+    else {
+      None
+    }
+  }
+
+  /// Returns true if `ptr` points into the memory owned by this arena.
+  pub fn contains(&self, ptr: *mut u8) -> bool {
+    let addr = ptr as usize;
+    addr >= self.base as usize && addr < self.base as usize + self.size
+  }
+
+  /// Returns the total size of the arena in bytes.
+  pub fn size(&self) -> usize { self.size }
+
+  /// Returns how many bytes of the arena have been allocated.
+  pub fn used(&self) -> usize { self.next - self.base as usize }
+
+  fn is_active(&self) -> bool {
+    self.suspended == 0
+  }
+}
+
+impl Drop for Arena {
+  fn drop(&mut self) {
+    let align = ::core::mem::align_of::<usize>();
+    unregister(self.base as usize);
+    // UNSAFE: This is the exact size and location of the block of memory we allocated
+    unsafe {
+      heap::deallocate(self.base, self.size, align);
+    }
+  }
+}
+
+/// Runs `block` with the current task's arena disabled, so any allocations are made from the global
+/// heap.
+///
+/// The kernel uses this for any allocations it makes on behalf of a task that must outlive it, like
+/// the memory for a newly created task.
+pub fn with_global_heap<F, R>(block: F) -> R where F: FnOnce() -> R {
+  set_suspended(true);
+  let result = block();
+  set_suspended(false);
+  result
+}
+
+/// Allocates memory from the current task's arena.
+///
+/// Returns `None` if the allocation should be served by the global heap instead. That is the case
+/// if the current task doesn't have an arena, if we're running in the kernel, or if the arena has
+/// been disabled by `with_global_heap`. If the arena is out of space then the allocation fails, it
+/// does NOT fall back to the global heap.
+#[doc(hidden)]
+pub fn allocate(size: usize, align: usize) -> Option<*mut u8> {
+  if arch::in_kernel_mode() {
+    return None;
+  }
+  let _g = CriticalSection::begin();
+  // UNSAFE: Accessing CURRENT_TASK
+  match unsafe { CURRENT_TASK.as_mut() } {
+    Some(task) => match task.arena_mut() {
+      Some(arena) if arena.is_active() => {
+        Some(arena.allocate(size, align).unwrap_or(::core::ptr::null_mut()))
+      },
+      _ => None,
+    },
+    None => None,
+  }
+}
+
+/// Frees memory allocated from any task's arena.
+///
+/// Returns true if `ptr` belongs to an arena, in which case there is nothing to do since the
+/// memory will be freed with the arena. Returns false if the global heap should free it instead.
+#[doc(hidden)]
+pub fn deallocate(ptr: *mut u8, _size: usize, _align: usize) -> bool {
+  // An arena is registered before any of its memory is handed out, so with none registered this
+  // can't be arena memory
+  if LIVE_ARENAS.load(Ordering::SeqCst) == 0 {
+    return false;
+  }
+  let addr = ptr as usize;
+  let _g = CriticalSection::begin();
+  ARENAS.lock().iter().any(|range| match *range {
+    Some((start, end)) => addr >= start && addr < end,
+    None => false,
+  })
+}
+
+fn register(base: usize, size: usize) -> bool {
+  let _g = CriticalSection::begin();
+  let mut arenas = ARENAS.lock();
+  match arenas.iter_mut().find(|range| range.is_none()) {
+    Some(range) => {
+      *range = Some((base, base + size));
+      LIVE_ARENAS.fetch_add(1, Ordering::SeqCst);
+      true
+    },
+    None => false,
+  }
+}
+
+fn unregister(base: usize) {
+  let _g = CriticalSection::begin();
+  let mut arenas = ARENAS.lock();
+  for range in arenas.iter_mut() {
+    if range.map_or(false, |(start, _)| start == base) {
+      *range = None;
+      LIVE_ARENAS.fetch_sub(1, Ordering::SeqCst);
+      break;
+    }
+  }
+}
+
+fn set_suspended(suspend: bool) {
+  let _g = CriticalSection::begin();
+  // UNSAFE: Accessing CURRENT_TASK
+  if let Some(task) = unsafe { CURRENT_TASK.as_mut() } {
+    if let Some(arena) = task.arena_mut() {
+      if suspend {
+        arena.suspended += 1;
+      }
+      else {
+        arena.suspended -= 1;
+      }
+    }
+  }
+}
+
+fn align_up(addr: usize, align: usize) -> usize {
+  (addr + align - 1) & !(align - 1)
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use task::Priority;
+  use task::args::Args;
+  use collections::Vec;
+  use sched;
+  use syscall;
+  use arch;
+  use test;
+
+  #[test]
+  fn arena_allocate_smoke() {
+    let _g = test::set_up();
+    let mut arena = Arena::new(64);
+    let first = arena.allocate(8, 4).unwrap();
+    let second = arena.allocate(8, 4).unwrap();
+
+    assert!(arena.contains(first));
+    assert!(arena.contains(second));
+    assert_eq!(second as usize - first as usize, 8);
+    assert_eq!(arena.used(), 16);
+  }
+
+  #[test]
+  fn arena_allocate_aligns() {
+    let _g = test::set_up();
+    let mut arena = Arena::new(64);
+    arena.allocate(1, 1).unwrap();
+    let aligned = arena.allocate(4, 4).unwrap();
+
+    assert_eq!(aligned as usize % 4, 0);
+  }
+
+  #[test]
+  fn arena_allocate_out_of_space() {
+    let _g = test::set_up();
+    let mut arena = Arena::new(16);
+
+    assert!(arena.allocate(16, 1).is_some());
+    assert!(arena.allocate(1, 1).is_none());
+  }
+
+  #[test]
+  fn arena_does_not_contain_outside_pointers() {
+    let _g = test::set_up();
+    let arena = Arena::new(16);
+    let mut other = 0u8;
+
+    assert_not!(arena.contains(&mut other));
+  }
+
+  #[test]
+  fn arena_try_new_too_many() {
+    let _g = test::set_up();
+    let mut arenas = Vec::new();
+    while let Ok(arena) = Arena::try_new(16) {
+      arenas.push(arena);
+    }
+
+    assert!(arenas.len() <= MAX_ARENAS);
+    assert_eq!(Arena::try_new(16).err(), Some(ArenaError::TooManyArenas));
+    drop(arenas);
+    assert!(Arena::try_new(16).is_ok());
+  }
+
+  #[test]
+  fn deallocate_without_arenas() {
+    let _g = test::set_up();
+    let mut other = 0u8;
+
+    assert_not!(deallocate(&mut other, 1, 1));
+  }
+
+  #[test]
+  fn allocate_routes_to_current_task_arena() {
+    let _g = test::set_up();
+    syscall::new_task_with_arena(test_task, Args::empty(), 512, 128, Priority::Normal, "arena");
+    sched::start_scheduler();
+
+    arch::set_kernel_mode(false);
+    let ptr = allocate(16, 4);
+    let in_arena = deallocate(ptr.unwrap(), 16, 4);
+    let global = with_global_heap(|| allocate(16, 4));
+    arch::set_kernel_mode(true);
+
+    assert!(ptr.is_some());
+    assert!(in_arena);
+    assert!(global.is_none());
+    assert_eq!(test::current_task().unwrap().arena().unwrap().used(), 16);
+  }
+
+  #[test]
+  fn deallocate_recognizes_live_arenas() {
+    let _g = test::set_up();
+    let mut arena = Arena::new(64);
+    let ptr = arena.allocate(8, 4).unwrap();
+
+    assert!(deallocate(ptr, 8, 4));
+    drop(arena);
+    assert_not!(deallocate(ptr, 8, 4));
+  }
+
+  #[test]
+  fn deallocate_from_other_task() {
+    let _g = test::set_up();
+    let owner = syscall::new_task_with_arena(test_task, Args::empty(), 512, 128, Priority::Normal,
+                                             "arena");
+    let other = test::create_and_schedule_test_task(512, Priority::Normal, "no arena");
+    sched::start_scheduler();
+    assert_eq!(owner.tid(), Ok(test::current_task().unwrap().tid()));
+
+    arch::set_kernel_mode(false);
+    let ptr = allocate(16, 4).unwrap();
+    arch::set_kernel_mode(true);
+
+    sched::switch_context();
+    assert_eq!(other.tid(), Ok(test::current_task().unwrap().tid()));
+    assert!(deallocate(ptr, 16, 4));
+
+    // Freed from an interrupt handler, with no task running
+    // UNSAFE: The scheduler isn't used again in this test
+    unsafe { CURRENT_TASK = None };
+    assert!(deallocate(ptr, 16, 4));
+  }
+
+  #[test]
+  fn allocate_uses_global_heap_without_arena() {
+    let _g = test::set_up();
+    test::create_and_schedule_test_task(512, Priority::Normal, "no arena");
+    sched::start_scheduler();
+
+    arch::set_kernel_mode(false);
+    let ptr = allocate(16, 4);
+    arch::set_kernel_mode(true);
+
+    assert!(ptr.is_none());
+  }
+
+  #[test]
+  fn allocate_uses_global_heap_in_kernel() {
+    let _g = test::set_up();
+    syscall::new_task_with_arena(test_task, Args::empty(), 512, 128, Priority::Normal, "arena");
+    sched::start_scheduler();
+
+    assert!(allocate(16, 4).is_none());
+  }
+
+  fn test_task(_args: &mut Args) {}
+}
@@ -5,15 +5,22 @@
 
 use super::stack::Stack;
 use super::args::Args;
+use super::arena::Arena;
+use super::table;
 use alloc::boxed::Box;
 use sync::CriticalSection;
+use syscall::svc;
+use atomic::{AtomicUsize, Ordering};
+#[cfg(feature="edf")]
+use sched::edf::Deadline;
 
-pub const NUM_PRIORITIES: usize = 4;
+/// The number of task-local storage slots each task has for `LocalKey`s.
+pub const NUM_TASK_LOCALS: usize = 4;
 
-pub const VALID_TASK: usize = 0xBADB0100;
-pub const INVALID_TASK: usize = 0x0;
+/// The number of raw task-local slots each task has, these are separate from the `LocalKey` slots.
+pub const NUM_RAW_LOCALS: usize = 2;
 
-type HandleResult<T> = Result<T, ()>;
+type HandleResult<T> = Result<T, HandleError>;
 
 mod tid {
   use atomic::{ATOMIC_USIZE_INIT, AtomicUsize, Ordering};
@@ -34,36 +41,87 @@ pub enum Delay {
   Invalid,
 }
 
+/// The number of priority levels, including the level reserved for the idle task.
+///
+/// This is 4 by default, the `priorities_8`, `priorities_16` and `priorities_32` features raise it
+/// for designs that need more levels, like rate-monotonic scheduling.
+#[cfg(not(any(feature="priorities_8", feature="priorities_16", feature="priorities_32")))]
+pub const NUM_PRIORITIES: usize = 4;
+#[cfg(all(feature="priorities_8", not(any(feature="priorities_16", feature="priorities_32"))))]
+pub const NUM_PRIORITIES: usize = 8;
+#[cfg(all(feature="priorities_16", not(feature="priorities_32")))]
+pub const NUM_PRIORITIES: usize = 16;
+#[cfg(feature="priorities_32")]
+pub const NUM_PRIORITIES: usize = 32;
+
 /// Priorities that a task can have.
 ///
 /// Priorities declare which tasks should be run before others. A higher priority task will always
 /// be run before a lower priority task if it is ready to be run.
-#[derive(Debug, Copy, Clone, PartialEq)]
-pub enum Priority {
+///
+/// A priority is a numeric level, where level 0 is the highest priority. The lowest level,
+/// `NUM_PRIORITIES - 1`, is reserved for the idle task. The named priorities `Critical`, `Normal`,
+/// and `Low` are provided for systems that don't need fine grained control.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct Priority(usize);
+
+#[allow(non_upper_case_globals)]
+impl Priority {
   /// The highest priority.
   ///
   /// Tasks with this priority will always be run before any other task. This priority should be
   /// reserved for short lived, time critical tasks that do work important to the functioning of
   /// the system.
-  Critical = 0,
+  pub const Critical: Priority = Priority(0);
 
   /// The standard task priority.
   ///
   /// Most tasks should be given this priority. The task can be preempted at any time so should not
   /// rely on any operation to be atomic unless specifically marked in a critical section.
-  Normal = 1,
+  pub const Normal: Priority = Priority((NUM_PRIORITIES - 1) / 2);
 
   /// The minimal task priority.
   ///
   /// These tasks should be purely optional for the system to run. They will only be run if there
   /// are no other tasks to run, so on some systems they may never run at all.
-  Low = 2,
+  pub const Low: Priority = Priority(NUM_PRIORITIES - 2);
 
   #[doc(hidden)]
-  __Idle = 3,
-}
+  pub const __Idle: Priority = Priority(NUM_PRIORITIES - 1);
+
+  /// Creates a priority from a numeric level, where 0 is the highest priority.
+  ///
+  /// # Panics
+  ///
+  /// This function will panic if `level` is not less than `NUM_PRIORITIES - 1`, the lowest level
+  /// is reserved for the idle task. Use `try_new` for levels that aren't known to be valid.
+  pub fn new(level: usize) -> Self {
+    match Priority::try_new(level) {
+      Some(priority) => priority,
+      None => panic!("Priority::new - priority level is out of range!"),
+    }
+  }
+
+  /// Creates a priority from a numeric level, returning `None` if the level is out of range.
+  pub fn try_new(level: usize) -> Option<Self> {
+    if level < NUM_PRIORITIES - 1 {
+      Some(Priority(level))
+    }
+    else {
+      None
+    }
+  }
+
+  #[doc(hidden)]
+  pub fn new_unchecked(level: usize) -> Self {
+    Priority(level)
+  }
+
+  /// Returns the numeric level of this priority, 0 is the highest priority.
+  pub fn level(&self) -> usize {
+    self.0
+  }
 
-impl Priority {
   /// Returns a range of values corresponding to each priority, starting from the highest.
   pub fn all() -> ::core::ops::Range<usize> {
     (0..NUM_PRIORITIES)
@@ -74,7 +132,7 @@ impl Priority {
   /// Use this to iterate over priority queues starting from the highest priority queue down to the
   /// current priority.
   pub fn higher(&self) -> ::core::ops::Range<usize> {
-    0..(*self as usize + 1)
+    0..(self.0 + 1)
   }
 }
 
@@ -114,13 +172,22 @@ pub struct TaskControl {
   args: Box<Args>,
   tid: usize,
   name: &'static str,
-  valid: usize,
+  handle: Option<TaskHandle>,
   pub wchan: usize,
   pub delay: usize,
   pub delay_type: Delay,
   pub destroy: bool,
   pub priority: Priority,
+  pub slice_ticks: usize,
+  time_slice: Option<usize>,
+  #[cfg(feature="edf")]
+  pub deadline: Option<Deadline>,
   pub state: State,
+  locals: [usize; NUM_TASK_LOCALS],
+  raw_locals: [usize; NUM_RAW_LOCALS],
+  arena: Option<Arena>,
+  is_static: bool,
+  locks: AtomicUsize,
 }
 
 unsafe impl Send for TaskControl {}
@@ -136,20 +203,60 @@ impl TaskControl {
     // Arguments struct stored right above the stack
     let args_mem: Box<Args> = Box::new(args);
 
+    Self::from_parts(code, args_mem, stack, priority, name, false)
+  }
+
+  /// Creates a new `TaskControl` like `new`, but returns `None` instead of aborting if there isn't
+  /// enough memory for the task's stack.
+  pub fn try_new(code: fn(&mut Args), args: Args, depth: usize, priority: Priority, 
+                 name: &'static str) -> Option<Self> {
+    let stack = match Stack::try_new(depth) {
+      Some(stack) => stack,
+      None => return None,
+    };
+    let args_mem = match ::heap::try_box(args) {
+      Ok(args_mem) => args_mem,
+      Err(_) => return None,
+    };
+
+    Some(Self::from_parts(code, args_mem, stack, priority, name, false))
+  }
+
+  /// Creates a new `TaskControl` whose stack and arguments live in statically allocated memory.
+  ///
+  /// Neither `args` nor `stack` are owned by the heap, so the returned task must live in static
+  /// memory and never be dropped. See `StaticTask` for the safe interface to this.
+  pub unsafe fn new_static(code: fn(&mut Args), args: Box<Args>, stack: Stack, priority: Priority, 
+                           name: &'static str) -> Self {
+    Self::from_parts(code, args, stack, priority, name, true)
+  }
+
+  fn from_parts(code: fn(&mut Args), args: Box<Args>, stack: Stack, priority: Priority, 
+                name: &'static str, is_static: bool) -> Self {
     let tid = tid::fetch_next_tid();
+    trace_event!(TaskCreate(tid));
 
     let mut task = TaskControl {
       stack: stack,
-      args: args_mem,
+      args: args,
       tid: tid,
       name: name,
-      valid: VALID_TASK + (tid & 0xFF),
+      handle: None,
       wchan: 0,
       delay: 0,
       delay_type: Delay::Invalid,
       destroy: false,
       priority: priority,
+      slice_ticks: 0,
+      time_slice: None,
+      #[cfg(feature="edf")]
+      deadline: None,
       state: State::Embryo,
+      locals: [0; NUM_TASK_LOCALS],
+      raw_locals: [0; NUM_RAW_LOCALS],
+      arena: None,
+      is_static: is_static,
+      locks: AtomicUsize::new(0),
     };
     task.initialize(code);
     task
@@ -166,7 +273,16 @@ impl TaskControl {
     // TODO: Check if task is INIT task? So at least we always have a safe task to run...
     let _g = CriticalSection::begin();
     self.destroy = true;
-    self.valid = INVALID_TASK;
+  }
+
+  /// Removes the task from the task table, any handles to it will return
+  /// `Err(HandleError::NotFound)` from now on.
+  ///
+  /// This is done automatically when the task is dropped.
+  pub fn unregister(&mut self) {
+    if let Some(handle) = self.handle.take() {
+      table::remove(handle.index, handle.generation);
+    }
   }
 
   /// Checks if the stack has gone past its bounds, returns true if it has.
@@ -180,7 +296,109 @@ impl TaskControl {
     self.stack.check_overflow()
   }
 
+  /// Returns the value stored in one of the task's task-local slots.
+  ///
+  /// # Panics
+  ///
+  /// This method will panic if `slot` is not less than `NUM_TASK_LOCALS`.
+  pub fn local(&self, slot: usize) -> usize {
+    self.locals[slot]
+  }
+
+  /// Stores a value in one of the task's task-local slots.
+  ///
+  /// # Panics
+  ///
+  /// This method will panic if `slot` is not less than `NUM_TASK_LOCALS`.
+  pub fn set_local(&mut self, slot: usize, value: usize) {
+    self.locals[slot] = value;
+  }
+
+  /// Returns the value stored in one of the task's raw task-local slots.
+  ///
+  /// # Panics
+  ///
+  /// This method will panic if `slot` is not less than `NUM_RAW_LOCALS`.
+  pub fn raw_local(&self, slot: usize) -> usize {
+    self.raw_locals[slot]
+  }
+
+  /// Stores a value in one of the task's raw task-local slots.
+  ///
+  /// # Panics
+  ///
+  /// This method will panic if `slot` is not less than `NUM_RAW_LOCALS`.
+  pub fn set_raw_local(&mut self, slot: usize, value: usize) {
+    self.raw_locals[slot] = value;
+  }
+
+  /// Gives the task its own arena to allocate dynamic memory from.
+  ///
+  /// This should only be done before the task has been scheduled, otherwise some of its memory may
+  /// already have been allocated from the global heap.
+  pub fn set_arena(&mut self, arena: Arena) {
+    self.arena = Some(arena);
+  }
+
+  /// Returns a reference to the task's arena, if it has one.
+  pub fn arena(&self) -> Option<&Arena> {
+    self.arena.as_ref()
+  }
+
+  /// Returns a mutable reference to the task's arena, if it has one.
+  pub fn arena_mut(&mut self) -> Option<&mut Arena> {
+    self.arena.as_mut()
+  }
+
+  /// Returns the number of `Mutex` and `SpinMutex` locks the task is holding.
+  pub fn held_locks(&self) -> usize {
+    self.locks.load(Ordering::SeqCst)
+  }
+
+  /// Returns the count of locks the task is holding, for `sync::Held` to keep up to date.
+  pub fn lock_count(&self) -> &AtomicUsize {
+    &self.locks
+  }
+
+  /// Returns true if the memory for this task was statically allocated.
+  ///
+  /// Statically allocated tasks must never be dropped, their memory is not owned by the heap.
+  pub fn is_static(&self) -> bool { self.is_static }
+
+  /// Sets how many ticks the task can run before it is preempted by another task of the same
+  /// priority.
+  ///
+  /// A value of 0 disables round-robin scheduling for the task, it will run until it blocks or
+  /// yields. `None` uses the time slice configured for the task's priority.
+  pub fn set_time_slice(&mut self, ticks: Option<usize>) {
+    self.time_slice = ticks;
+  }
+
+  /// Returns the task's time slice override, if it has one.
+  pub fn time_slice(&self) -> Option<usize> { self.time_slice }
+
   pub fn tid(&self) -> usize { self.tid }
+
+  pub fn name(&self) -> &'static str { self.name }
+
+  /// Returns the top of the task's stack as of the last time it was switched out.
+  pub fn stack_top(&self) -> *const usize { self.stack.top() }
+}
+
+impl Drop for TaskControl {
+  fn drop(&mut self) {
+    self.unregister();
+  }
+}
+
+/// The errors that can be returned when accessing a task through a `TaskHandle`.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum HandleError {
+  /// The task has been marked for destruction, it will be reclaimed by the kernel soon.
+  Destroyed,
+
+  /// The task the handle referred to no longer exists, its memory has already been reclaimed.
+  NotFound,
 }
 
 /// A `TaskHandle` references a `TaskControl` and provides access to some state about it.
@@ -189,19 +407,46 @@ impl TaskControl {
 /// provides a way to examine the state of the task at run time as well as perform some operations
 /// on it like marking it for destruction. 
 ///
+/// A handle doesn't point at the task directly, it refers to a slot in the kernel's task table.
+/// Each slot keeps a generation count that changes when its task is reclaimed, so a handle to a
+/// task that no longer exists is detected without ever touching the freed memory.
+///
 /// This struct is thread safe, as all accesses to the internal `TaskControl` are checked for
 /// validity. If a task has been destroyed by one thread, then any other thread trying to access it
 /// will be returned an `Err`.
-#[derive(Copy, Clone, Debug)]
-pub struct TaskHandle(*const TaskControl);
-
-unsafe impl Send for TaskHandle {}
-unsafe impl Sync for TaskHandle {}
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct TaskHandle {
+  index: usize,
+  generation: usize,
+}
 
 impl TaskHandle {
   /// Creates a new `TaskHandle` referencing a `TaskControl`.
-  pub fn new(task: &TaskControl) -> Self {
-    TaskHandle(task)
+  ///
+  /// The first time a handle is created for a task the task is registered in the task table, it
+  /// must not be moved after that.
+  ///
+  /// # Panics
+  ///
+  /// This method will panic if the task table is full.
+  pub fn new(task: &mut TaskControl) -> Self {
+    match Self::try_new(task) {
+      Some(handle) => handle,
+      None => panic!("TaskHandle::new - too many tasks, the task table is full!"),
+    }
+  }
+
+  /// Creates a new `TaskHandle` referencing a `TaskControl`, returning `None` if the task table
+  /// is full.
+  pub fn try_new(task: &mut TaskControl) -> Option<Self> {
+    if let Some(handle) = task.handle {
+      return Some(handle);
+    }
+    table::insert(task).map(|(index, generation)| {
+      let handle = TaskHandle { index: index, generation: generation };
+      task.handle = Some(handle);
+      handle
+    })
   }
 
   /// Marks a task for destruction by the OS, returns true if it was in a valid state before the
@@ -211,7 +456,7 @@ impl TaskHandle {
   /// memory associated with that task will be reclaimed at the operating system's convenience.
   /// There is no guarantee about when this will happen, and in some circumstances it may in fact
   /// never happen, but once a task has been marked for destruction all attempts to access its data
-  /// through a `TaskHandle` will return `Err(())`.
+  /// through a `TaskHandle` will return `Err(HandleError::Destroyed)`.
   ///
   /// # Examples
   ///
@@ -234,22 +479,16 @@ impl TaskHandle {
   /// # }
   /// ```
   pub fn destroy(&mut self) -> bool {
-    // FIXME: If the task has allocated any dynamic memory on it own, this will be leaked when the
-    //  task is destroyed.
-    //    A possible solution... allocate a heap space for each task. Pass a heap allocation
-    //    interface to the task implicitly and do all dynamic memory allocation through this
-    //    interface. When the task is destroyed we can just free the whole task-specific heap so we
-    //    wont have to worry about leaking memory. This means we would likely have to disallow core 
-    //    library `Box` allocations within the task. Or... we just don't allow dynamic allocation 
-    //    within tasks. - Daniel Seitz
+    // NOTE: If the task has allocated any dynamic memory on its own, this will be leaked when the
+    //  task is destroyed unless the task was created with its own arena (see
+    //  `syscall::new_task_with_arena`), in which case the whole arena is freed with the task.
     let _g = CriticalSection::begin();
-    if self.is_valid() {
-      let task = self.task_ref_mut();
-      task.destroy();
-      true
-    }
-    else {
-      false
+    match self.task_mut() {
+      Ok(task) => {
+        task.destroy();
+        true
+      },
+      Err(_) => false,
     }
   }
 
@@ -261,6 +500,7 @@ impl TaskHandle {
   ///
   /// ```rust,no_run
   /// # use altos_core::{TaskHandle, Priority};
+  /// # use altos_core::HandleError;
   /// # use altos_core::syscall::new_task;
   /// # use altos_core::args::Args;
   ///
@@ -268,7 +508,8 @@ impl TaskHandle {
   ///
   /// match handle.priority() {
   ///   Ok(priority) => { /* Task was valid */ },
-  ///   Err(()) => { /* Task was destroyed */ },
+  ///   Err(HandleError::Destroyed) => { /* Task was destroyed */ },
+  ///   Err(_) => { /* Task doesn't exist anymore */ },
   /// }
   ///
   /// # fn test_task(_args: &mut Args) {
@@ -278,16 +519,11 @@ impl TaskHandle {
   ///
   /// # Errors
   ///
-  /// If the task has been destroyed then this method will return an `Err(())`.
+  /// If the task has been destroyed then this method will return an `Err(HandleError::Destroyed)`,
+  /// see `HandleError` for the other errors that can be returned.
   pub fn priority(&self) -> HandleResult<Priority> {
     let _g = CriticalSection::begin();
-    if self.is_valid() {
-      let task = self.task_ref();
-      Ok(task.priority)
-    }
-    else {
-      Err(())
-    }
+    self.task_ref().map(|task| task.priority)
   }
 
   /// Returns a task's current state.
@@ -298,6 +534,7 @@ impl TaskHandle {
   ///
   /// ```rust,no_run
   /// # use altos_core::{TaskHandle, Priority};
+  /// # use altos_core::HandleError;
   /// # use altos_core::syscall::new_task;
   /// # use altos_core::args::Args;
   ///
@@ -305,7 +542,8 @@ impl TaskHandle {
   ///
   /// match handle.state() {
   ///   Ok(state) => { /* Task was valid */ },
-  ///   Err(()) => { /* Task was destroyed */ },
+  ///   Err(HandleError::Destroyed) => { /* Task was destroyed */ },
+  ///   Err(_) => { /* Task doesn't exist anymore */ },
   /// }
   ///
   /// # fn test_task(_args: &mut Args) {
@@ -315,16 +553,11 @@ impl TaskHandle {
   ///
   /// # Errors
   ///
-  /// If the task has been destroyed then this method will return an `Err(())`.
+  /// If the task has been destroyed then this method will return an `Err(HandleError::Destroyed)`,
+  /// see `HandleError` for the other errors that can be returned.
   pub fn state(&self) -> HandleResult<State> {
     let _g = CriticalSection::begin();
-    if self.is_valid() {
-      let task = self.task_ref();
-      Ok(task.state)
-    }
-    else {
-      Err(())
-    }
+    self.task_ref().map(|task| task.state)
   }
 
   /// Returns a task's tid (task identifier).
@@ -336,6 +569,7 @@ impl TaskHandle {
   ///
   /// ```rust,no_run
   /// # use altos_core::{TaskHandle, Priority};
+  /// # use altos_core::HandleError;
   /// # use altos_core::syscall::new_task;
   /// # use altos_core::args::Args;
   ///
@@ -343,7 +577,8 @@ impl TaskHandle {
   ///
   /// match handle.tid() {
   ///   Ok(tid) => { /* Task was valid */ },
-  ///   Err(()) => { /* Task was destroyed */ },
+  ///   Err(HandleError::Destroyed) => { /* Task was destroyed */ },
+  ///   Err(_) => { /* Task doesn't exist anymore */ },
   /// }
   ///
   /// # fn test_task(_args: &mut Args) {
@@ -353,30 +588,29 @@ impl TaskHandle {
   ///
   /// # Errors
   ///
-  /// If the task has been destroyed then this method will return an `Err(())`.
+  /// If the task has been destroyed then this method will return an `Err(HandleError::Destroyed)`,
+  /// see `HandleError` for the other errors that can be returned.
   pub fn tid(&self) -> HandleResult<usize> {
     let _g = CriticalSection::begin();
-    if self.is_valid() {
-      let task = self.task_ref();
-      Ok(task.tid)
-    }
-    else {
-      Err(())
-    }
+    self.task_ref().map(|task| task.tid)
   }
 
   /// Returns the task's name.
   ///
+  /// # Examples
+  ///
   /// ```rust,no_run
   /// # use altos_core::{TaskHandle, Priority};
+  /// # use altos_core::HandleError;
   /// # use altos_core::syscall::new_task;
   /// # use altos_core::args::Args;
   ///
   /// let handle = new_task(test_task, Args::empty(), 512, Priority::Normal, "new_task_name");
-  /// 
+  ///
   /// match handle.name() {
   ///   Ok(name) => { /* Task was valid */ },
-  ///   Err(()) => { /* Task was destroyed */ },
+  ///   Err(HandleError::Destroyed) => { /* Task was destroyed */ },
+  ///   Err(_) => { /* Task doesn't exist anymore */ },
   /// }
   ///
   /// # fn test_task(_args: &mut Args) {
@@ -386,16 +620,11 @@ impl TaskHandle {
   ///
   /// # Errors
   ///
-  /// If the task has been destroyed then this method will return an `Err(())`.
+  /// If the task has been destroyed then this method will return an `Err(HandleError::Destroyed)`,
+  /// see `HandleError` for the other errors that can be returned.
   pub fn name(&self) -> HandleResult<&'static str> {
     let _g = CriticalSection::begin();
-    if self.is_valid() {
-      let task = self.task_ref();
-      Ok(task.name)
-    }
-    else {
-      Err(())
-    }
+    self.task_ref().map(|task| task.name)
   }
 
   /// Returns the task's stack size.
@@ -404,6 +633,7 @@ impl TaskHandle {
   ///
   /// ```rust,no_run
   /// # use altos_core::{TaskHandle, Priority};
+  /// # use altos_core::HandleError;
   /// # use altos_core::syscall::new_task;
   /// # use altos_core::args::Args;
   ///
@@ -411,7 +641,8 @@ impl TaskHandle {
   ///
   /// match handle.stack_size() {
   ///   Ok(size) => { /* Task was valid */ },
-  ///   Err(()) => { /* Task was destroyed */ },
+  ///   Err(HandleError::Destroyed) => { /* Task was destroyed */ },
+  ///   Err(_) => { /* Task doesn't exist anymore */ },
   /// }
   ///
   /// # fn test_task(_args: &mut Args) {
@@ -421,19 +652,66 @@ impl TaskHandle {
   ///
   /// # Errors
   ///
-  /// If the task has been destroyed then this method will return an `Err(())`.
+  /// If the task has been destroyed then this method will return an `Err(HandleError::Destroyed)`,
+  /// see `HandleError` for the other errors that can be returned.
   pub fn stack_size(&self) -> HandleResult<usize> {
     let _g = CriticalSection::begin();
-    if self.is_valid() {
-      let task = self.task_ref();
-      Ok(task.stack.depth())
-    }
-    else {
-      Err(())
-    }
+    self.task_ref().map(|task| task.stack.depth())
   }
 
-  /// Check if the task pointed to by this handle is valid
+  /// Sets the task's time slice, the number of ticks it can run before another task of the same
+  /// priority gets a turn.
+  ///
+  /// A value of `Some(0)` disables round-robin scheduling for the task, it will run until it blocks
+  /// or yields. `None` uses the time slice configured for the task's priority with
+  /// `sched::set_time_slice`.
+  ///
+  /// # Errors
+  ///
+  /// If the task has been destroyed then this method will return an `Err(HandleError::Destroyed)`,
+  /// see `HandleError` for the other errors that can be returned.
+  pub fn set_time_slice(&mut self, ticks: Option<usize>) -> HandleResult<()> {
+    let (has_ticks, ticks) = match ticks {
+      Some(ticks) => (1, ticks),
+      None => (0, 0),
+    };
+    svc::to_handle_result(svc::call(svc::SYS_SET_TASK_TIME_SLICE, self as *mut _ as usize,
+                                    has_ticks, ticks))
+  }
+
+  /// Changes the task's priority.
+  ///
+  /// If the task is ready to run it moves to the back of the queue for its new priority. If
+  /// another task should now run instead of the current one it is switched in right away. EDF
+  /// tasks are scheduled by their deadlines, so their priority doesn't affect them.
+  ///
+  /// # Examples
+  ///
+  /// ```rust,no_run
+  /// # use altos_core::{TaskHandle, Priority};
+  /// # use altos_core::syscall::new_task;
+  /// # use altos_core::args::Args;
+  ///
+  /// let mut handle = new_task(test_task, Args::empty(), 512, Priority::Low, "new_task_name");
+  ///
+  /// // The task has something urgent to do
+  /// handle.set_priority(Priority::Critical).unwrap();
+  ///
+  /// # fn test_task(_args: &mut Args) {
+  /// #   loop {}
+  /// # }
+  /// ```
+  ///
+  /// # Errors
+  ///
+  /// If the task has been destroyed then this method will return an `Err(HandleError::Destroyed)`,
+  /// see `HandleError` for the other errors that can be returned.
+  pub fn set_priority(&mut self, priority: Priority) -> HandleResult<()> {
+    svc::to_handle_result(svc::call(svc::SYS_SET_PRIORITY, self as *mut _ as usize,
+                                    priority.level(), 0))
+  }
+
+  /// Check if the task referenced by this handle is valid
   /// 
   /// # Examples
   /// 
@@ -451,26 +729,32 @@ impl TaskHandle {
   /// 
   /// ```
   pub fn is_valid(&self) -> bool {
-    // UNSAFE: Yes, potentially we're reading from a dangling pointer (if the task has been freed,
-    // for instance), but there should be specific values at these locations, and if they aren't
-    // there then at least we'll know not to do anymore reads.
-    let (tid, valid) = unsafe { ((*self.0).tid, (*self.0).valid) };
-    let tid_mask = tid & 0xFF;
-    valid == VALID_TASK + tid_mask
+    let _g = CriticalSection::begin();
+    self.task_ref().is_ok()
   }
 
-  fn task_ref(&self) -> &TaskControl {
-    // UNSAFE: This is used internally to the TaskHandle, and is only ever called after checking if
-    // the handle is still valid. All operations are within critical sections and so can be
-    // considered atomic
-    unsafe { &*self.0 }
+  fn task_ref(&self) -> HandleResult<&TaskControl> {
+    // UNSAFE: The task table only hands out tasks that haven't been reclaimed yet. All operations
+    // are within critical sections and so can be considered atomic
+    let task = match table::lookup(self.index, self.generation) {
+      Some(task) => unsafe { &*task },
+      None => return Err(HandleError::NotFound),
+    };
+    if task.destroy {
+      Err(HandleError::Destroyed)
+    }
+    else {
+      Ok(task)
+    }
   }
 
-  fn task_ref_mut(&mut self) -> &mut TaskControl {
-    // UNSAFE: This is used internally to the TaskHandle, and is only ever called after checking if
-    // the handle is still valid. All operations are within critical sections and so can be
-    // considered atomic
-    unsafe { &mut *(self.0 as *mut TaskControl) }
+  /// Returns the task this handle references, for the kernel's syscall handlers.
+  ///
+  /// This must only be called inside a critical section.
+  #[doc(hidden)]
+  pub fn task_mut(&mut self) -> HandleResult<&mut TaskControl> {
+    // UNSAFE: We've checked the task is valid, and the table gives out mutable pointers
+    self.task_ref().map(|task| unsafe { &mut *(task as *const TaskControl as *mut TaskControl) })
   }
 }
 
@@ -488,24 +772,32 @@ mod tests {
 
   fn get_invalid_task() -> TaskControl {
     let mut task = test::create_test_task(512, Priority::Normal, "invalid test");
-    task.valid = INVALID_TASK;
+    task.destroy = true;
     task
   }
 
+  #[test]
+  fn priority_try_new() {
+    assert_eq!(Priority::try_new(0), Some(Priority::Critical));
+    assert_eq!(Priority::try_new(Priority::Low.level()), Some(Priority::Low));
+    assert_eq!(Priority::try_new(Priority::__Idle.level()), None);
+    assert_eq!(Priority::try_new(99), None);
+  }
+
   #[test]
   fn task_handle_valid() {
     let mut task = get_task();
-    let handle = TaskHandle::new(&task);
+    let handle = TaskHandle::new(&mut task);
 
     assert!(handle.is_valid());
-    task.valid = INVALID_TASK;
+    task.destroy = true;
     assert!(!handle.is_valid());
   }
 
   #[test]
   fn task_handle_destroy() {
-    let task = get_task();
-    let mut handle = TaskHandle::new(&task);
+    let mut task = get_task();
+    let mut handle = TaskHandle::new(&mut task);
 
     assert!(handle.is_valid());
     assert!(handle.destroy());
@@ -516,8 +808,8 @@ mod tests {
 
   #[test]
   fn invalid_task_handle_destroy() {
-    let task = get_invalid_task();
-    let mut handle = TaskHandle::new(&task);
+    let mut task = get_invalid_task();
+    let mut handle = TaskHandle::new(&mut task);
 
     assert!(!handle.is_valid());
     assert!(!handle.destroy());
@@ -526,72 +818,72 @@ mod tests {
 
   #[test]
   fn task_handle_stack_size() {
-    let task = get_task();
-    let handle = TaskHandle::new(&task);
+    let mut task = get_task();
+    let handle = TaskHandle::new(&mut task);
 
     assert_eq!(handle.stack_size(), Ok(512));
   }
 
   #[test]
   fn invalid_task_handle_stack_size() {
-    let task = get_invalid_task();
-    let handle = TaskHandle::new(&task);
+    let mut task = get_invalid_task();
+    let handle = TaskHandle::new(&mut task);
 
     assert!(handle.stack_size().is_err());
   }
 
   #[test]
   fn task_handle_priority() {
-    let task = get_task();
-    let handle = TaskHandle::new(&task);
+    let mut task = get_task();
+    let handle = TaskHandle::new(&mut task);
 
     assert_eq!(handle.priority(), Ok(Priority::Normal));
   }
 
   #[test]
   fn invalid_task_handle_priority() {
-    let task = get_invalid_task();
-    let handle = TaskHandle::new(&task);
+    let mut task = get_invalid_task();
+    let handle = TaskHandle::new(&mut task);
 
     assert!(handle.priority().is_err());
   }
 
   #[test]
   fn task_handle_state() {
-    let task = get_task();
-    let handle = TaskHandle::new(&task);
+    let mut task = get_task();
+    let handle = TaskHandle::new(&mut task);
 
     assert_eq!(handle.state(), Ok(State::Ready));
   }
 
   #[test]
   fn invalid_task_handle_state() {
-    let task = get_invalid_task();
-    let handle = TaskHandle::new(&task);
+    let mut task = get_invalid_task();
+    let handle = TaskHandle::new(&mut task);
 
     assert!(handle.state().is_err());
   }
 
   #[test]
   fn task_handle_name() {
-    let task = get_task();
-    let handle = TaskHandle::new(&task);
+    let mut task = get_task();
+    let handle = TaskHandle::new(&mut task);
 
     assert_eq!(handle.name(), Ok("task test"));
   }
 
   #[test]
   fn invalid_task_handle_name() {
-    let task = get_invalid_task();
-    let handle = TaskHandle::new(&task);
+    let mut task = get_invalid_task();
+    let handle = TaskHandle::new(&mut task);
 
     assert!(handle.name().is_err());
   }
 
   #[test]
   fn task_handle_tid() {
-    let task = get_task();
-    let handle = TaskHandle::new(&task);
+    let mut task = get_task();
+    let handle = TaskHandle::new(&mut task);
 
     assert_eq!(handle.tid(), Ok(task.tid));
 
@@ -599,9 +891,59 @@ mod tests {
 
   #[test]
   fn invalid_task_handle_tid() {
-    let task = get_invalid_task();
-    let handle = TaskHandle::new(&task);
+    let mut task = get_invalid_task();
+    let handle = TaskHandle::new(&mut task);
 
     assert!(handle.tid().is_err());
   }
+
+  #[test]
+  fn invalid_task_handle_destroyed_error() {
+    let mut task = get_invalid_task();
+    let handle = TaskHandle::new(&mut task);
+
+    assert_eq!(handle.name(), Err(HandleError::Destroyed));
+  }
+
+  #[test]
+  fn task_handle_reuses_slot() {
+    let mut task = get_task();
+    let handle_1 = TaskHandle::new(&mut task);
+    let handle_2 = TaskHandle::new(&mut task);
+
+    assert_eq!(handle_1, handle_2);
+  }
+
+  #[test]
+  fn stale_task_handle_not_found() {
+    let mut task = get_task();
+    let handle = TaskHandle::new(&mut task);
+    drop(task);
+
+    // A new task may now be registered in the same slot, but the old handle can't see it
+    let mut task = get_task();
+    let _new_handle = TaskHandle::new(&mut task);
+    assert_eq!(handle.name(), Err(HandleError::NotFound));
+    assert_not!(handle.is_valid());
+  }
+
+  #[test]
+  fn task_handle_set_time_slice() {
+    let _g = test::set_up();
+    let mut task = get_task();
+    let mut handle = TaskHandle::new(&mut task);
+
+    assert_eq!(handle.set_time_slice(Some(5)), Ok(()));
+    assert_eq!(task.time_slice(), Some(5));
+  }
+
+  #[test]
+  fn invalid_task_handle_set_priority() {
+    let _g = test::set_up();
+    let mut task = get_invalid_task();
+    let mut handle = TaskHandle::new(&mut task);
+
+    assert_eq!(handle.set_priority(Priority::Low), Err(HandleError::Destroyed));
+    assert_eq!(task.priority, Priority::Normal);
+  }
 }
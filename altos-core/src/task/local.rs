@@ -0,0 +1,288 @@
+// task/local.rs
+// AltOSRust
+//
+// Created by Daniel Seitz on 2/6/17
+
+//! Task-local storage.
+//!
+//! Every task has a small, fixed number of pointer sized slots that it can use to store its own
+//! state. The `task_local!` macro declares a `LocalKey` that claims one of these slots, each task
+//! that accesses the key gets its own lazily initialized copy of the value. When a task is
+//! destroyed the values stored in its slots are dropped.
+//!
+//! Each task also has `NUM_RAW_LOCALS` raw slots that are accessed with the `get` and `set`
+//! functions. They're kept apart from the slots that keys claim, so a raw value can never be
+//! mistaken for a key's value.
+//!
+//! ```rust,no_run
+//! #[macro_use]
+//! extern crate altos_core;
+//!
+//! use core::cell::Cell;
+//!
+//! task_local!(static ERROR_COUNT: Cell<usize> = Cell::new(0));
+//!
+//! # fn main() {
+//! // Only affects the count of the currently running task
+//! ERROR_COUNT.with(|count| count.set(count.get() + 1));
+//! # }
+//! ```
+
+use super::control::{TaskControl, NUM_TASK_LOCALS};
+pub use super::control::NUM_RAW_LOCALS;
+use sched::CURRENT_TASK;
+use sync::{SpinMutex, CriticalSection};
+use atomic::{AtomicUsize, ATOMIC_USIZE_INIT, Ordering};
+use alloc::boxed::Box;
+use core::marker::PhantomData;
+
+/// Marks a slot that has not been assigned to any key yet.
+const UNASSIGNED: usize = !0;
+
+static CLAIMED_SLOTS: AtomicUsize = ATOMIC_USIZE_INIT;
+static DESTRUCTORS: SpinMutex<[Option<fn(usize)>; NUM_TASK_LOCALS]> =
+  SpinMutex::new([None; NUM_TASK_LOCALS]);
+
+/// A key to a task-local value.
+///
+/// Declare these with the `task_local!` macro. Each key is assigned one of the task-local slots the
+/// first time it is accessed, and every task accessing it will get its own copy of the value.
+pub struct LocalKey<T: 'static> {
+  slot: AtomicUsize,
+  init: fn() -> T,
+  _marker: PhantomData<T>,
+}
+
+unsafe impl<T: 'static> Sync for LocalKey<T> {}
+
+impl<T: 'static> LocalKey<T> {
+  #[doc(hidden)]
+  pub const fn new(init: fn() -> T) -> Self {
+    LocalKey {
+      slot: AtomicUsize::new(UNASSIGNED),
+      init: init,
+      _marker: PhantomData,
+    }
+  }
+
+  /// Acquires a reference to the value of this key for the currently running task.
+  ///
+  /// If this is the first time the task has accessed the key the value will be initialized.
+  ///
+  /// # Panics
+  ///
+  /// This method will panic if there is no task currently running or if more keys have been
+  /// declared than there are task-local slots available.
+  pub fn with<F, R>(&'static self, f: F) -> R where F: FnOnce(&T) -> R {
+    let slot = self.slot();
+    let ptr = {
+      let _g = CriticalSection::begin();
+      let task = current_task("LocalKey::with");
+      match task.local(slot) {
+        0 => {
+          let value = Box::into_raw(Box::new((self.init)())) as usize;
+          task.set_local(slot, value);
+          value
+        },
+        value => value,
+      }
+    };
+    // UNSAFE: The slot is only ever filled with a pointer to a `T` by this key, and it is only
+    // freed once the task is destroyed, at which point it can't be running this code.
+    f(unsafe { &*(ptr as *const T) })
+  }
+
+  fn slot(&self) -> usize {
+    match self.slot.load(Ordering::SeqCst) {
+      UNASSIGNED => {
+        let _g = CriticalSection::begin();
+        // Check again, we could have been preempted by someone else assigning the slot
+        let slot = self.slot.load(Ordering::SeqCst);
+        if slot != UNASSIGNED {
+          return slot;
+        }
+        let claimed = CLAIMED_SLOTS.fetch_add(1, Ordering::SeqCst);
+        if claimed >= NUM_TASK_LOCALS {
+          panic!("LocalKey::slot - declared more task locals than there are slots available!");
+        }
+        let slot = claimed;
+        DESTRUCTORS.lock()[slot] = Some(drop_value::<T>);
+        self.slot.store(slot, Ordering::SeqCst);
+        slot
+      },
+      slot => slot,
+    }
+  }
+}
+
+/// Returns the value stored in raw slot `slot` for the currently running task.
+///
+/// This is the low level interface to task-local storage. The raw slots are separate from the
+/// slots claimed by `LocalKey`s, there are `NUM_RAW_LOCALS` of them.
+///
+/// # Panics
+///
+/// This function will panic if there is no task currently running or if `slot` is not less than
+/// `NUM_RAW_LOCALS`.
+pub fn get(slot: usize) -> usize {
+  let _g = CriticalSection::begin();
+  current_task("local::get").raw_local(slot)
+}
+
+/// Sets the value stored in raw slot `slot` for the currently running task.
+///
+/// Values set through this function are never dropped when the task is destroyed.
+///
+/// # Panics
+///
+/// This function will panic if there is no task currently running or if `slot` is not less than
+/// `NUM_RAW_LOCALS`.
+pub fn set(slot: usize, value: usize) {
+  let _g = CriticalSection::begin();
+  current_task("local::set").set_raw_local(slot, value);
+}
+
+/// Runs the destructors for all the task-local values owned by `task`.
+#[doc(hidden)]
+pub fn destroy_all(task: &mut TaskControl) {
+  let destructors = *DESTRUCTORS.lock();
+  for (slot, destructor) in destructors.iter().enumerate() {
+    let value = task.local(slot);
+    if value != 0 {
+      task.set_local(slot, 0);
+      if let Some(destructor) = *destructor {
+        destructor(value);
+      }
+    }
+  }
+}
+
+fn drop_value<T>(ptr: usize) {
+  // UNSAFE: This is only registered as the destructor for slots filled in by a `LocalKey<T>`
+  unsafe { drop(Box::from_raw(ptr as *mut T)) };
+}
+
+fn current_task(caller: &'static str) -> &'static mut TaskControl {
+  // UNSAFE: Accessing CURRENT_TASK, callers must be in a critical section
+  unsafe {
+    match CURRENT_TASK.as_mut() {
+      Some(task) => &mut ***task,
+      None => panic!("{} - current task doesn't exist!", caller),
+    }
+  }
+}
+
+/// Declares a new task-local storage key of type `LocalKey`.
+///
+/// Each task that accesses the key gets its own copy of the value, initialized with the supplied
+/// expression the first time it is accessed. The value is dropped when the task is destroyed.
+#[macro_export]
+macro_rules! task_local {
+  ($(#[$attr:meta])* static $name:ident: $t:ty = $init:expr) => {
+    $(#[$attr])*
+    static $name: $crate::local::LocalKey<$t> = {
+      fn __init() -> $t { $init }
+      $crate::local::LocalKey::new(__init)
+    };
+  };
+  ($(#[$attr:meta])* pub static $name:ident: $t:ty = $init:expr) => {
+    $(#[$attr])*
+    pub static $name: $crate::local::LocalKey<$t> = {
+      fn __init() -> $t { $init }
+      $crate::local::LocalKey::new(__init)
+    };
+  };
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use task::Priority;
+  use sched;
+  use test;
+  use core::cell::Cell;
+
+  static DROPPED: AtomicUsize = ATOMIC_USIZE_INIT;
+
+  struct DropCounter(Cell<usize>);
+
+  impl Drop for DropCounter {
+    fn drop(&mut self) {
+      DROPPED.fetch_add(1, Ordering::SeqCst);
+    }
+  }
+
+  task_local!(static COUNTER: DropCounter = DropCounter(Cell::new(0)));
+
+  #[test]
+  fn test_task_local_per_task() {
+    let _g = test::set_up();
+    let (handle_1, handle_2) = test::create_two_tasks();
+
+    sched::start_scheduler();
+    assert_eq!(handle_1.tid(), Ok(test::current_task().unwrap().tid()));
+    COUNTER.with(|c| c.0.set(c.0.get() + 1));
+    COUNTER.with(|c| assert_eq!(c.0.get(), 1));
+
+    sched::switch_context();
+    assert_eq!(handle_2.tid(), Ok(test::current_task().unwrap().tid()));
+    COUNTER.with(|c| assert_eq!(c.0.get(), 0));
+    COUNTER.with(|c| c.0.set(10));
+
+    sched::switch_context();
+    assert_eq!(handle_1.tid(), Ok(test::current_task().unwrap().tid()));
+    COUNTER.with(|c| assert_eq!(c.0.get(), 1));
+  }
+
+  #[test]
+  fn test_task_local_dropped_on_reclaim() {
+    let _g = test::set_up();
+    let (mut handle_1, handle_2) = test::create_two_tasks();
+
+    sched::start_scheduler();
+    assert_eq!(handle_1.tid(), Ok(test::current_task().unwrap().tid()));
+    COUNTER.with(|c| c.0.set(5));
+    let dropped = DROPPED.load(Ordering::SeqCst);
+
+    handle_1.destroy();
+    sched::switch_context();
+    assert_eq!(handle_2.tid(), Ok(test::current_task().unwrap().tid()));
+    assert_eq!(DROPPED.load(Ordering::SeqCst), dropped + 1);
+  }
+
+  #[test]
+  fn test_raw_slots() {
+    let _g = test::set_up();
+    test::create_and_schedule_test_task(512, Priority::Normal, "local test");
+
+    sched::start_scheduler();
+    assert_eq!(get(0), 0);
+    set(0, 0xDEAD);
+    assert_eq!(get(0), 0xDEAD);
+  }
+
+  #[test]
+  fn test_raw_slots_separate_from_keys() {
+    let _g = test::set_up();
+    test::create_and_schedule_test_task(512, Priority::Normal, "local test");
+
+    sched::start_scheduler();
+    for slot in 0..NUM_RAW_LOCALS {
+      set(slot, 0xDEAD);
+    }
+    COUNTER.with(|c| assert_eq!(c.0.get(), 0));
+    for slot in 0..NUM_RAW_LOCALS {
+      assert_eq!(get(slot), 0xDEAD);
+    }
+  }
+
+  #[test]
+  #[should_panic]
+  fn test_raw_slot_out_of_bounds() {
+    let _g = test::set_up();
+    test::create_and_schedule_test_task(512, Priority::Normal, "local test");
+
+    sched::start_scheduler();
+    set(NUM_RAW_LOCALS, 0xDEAD);
+  }
+}
@@ -8,30 +8,18 @@
 //! This module contains the functions used to create tasks and modify them within the kernel.
 
 pub mod args;
+#[macro_use]
+pub mod local;
+pub mod arena;
 mod stack;
 mod control;
-
-pub use self::control::{TaskHandle, TaskControl, Delay, State, Priority};
-pub use self::control::NUM_PRIORITIES;
-
-use args::Args;
-
+mod table;
+#[macro_use]
+mod static_task;
+
+pub use self::control::{TaskHandle, HandleError, TaskControl, Delay, State, Priority};
+pub use self::static_task::StaticTask;
+pub use self::control::{NUM_PRIORITIES, NUM_TASK_LOCALS, NUM_RAW_LOCALS};
+pub use self::table::MAX_TASKS;
 #[doc(hidden)]
-pub fn init_idle_task() {
-  use sched::PRIORITY_QUEUES;
-  use queue::Node;
-  use alloc::boxed::Box;
-  const INIT_TASK_STACK_SIZE: usize = 256;
-
-  let task = TaskControl::new(idle_task_code, Args::empty(), INIT_TASK_STACK_SIZE, Priority::__Idle, "idle");
-
-  PRIORITY_QUEUES[task.priority].enqueue(Box::new(Node::new(task)));
-}
-
-fn idle_task_code(_args: &mut Args) {
-  use syscall::sched_yield;
-
-  loop {
-    sched_yield();
-  }
-}
+pub use self::table::is_full as table_is_full;
@@ -5,7 +5,7 @@
 
 use volatile::Volatile;
 use super::args::Args;
-use alloc::{self, heap};
+use alloc::heap;
 use alloc::boxed::Box;
 use arch;
 
@@ -19,20 +19,43 @@ pub struct Stack {
 
 impl Stack {
   pub fn new(depth: usize) -> Self {
+    match Self::try_new(depth) {
+      Some(stack) => stack,
+      None => ::heap::out_of_memory(depth, ::core::mem::align_of::<u8>()),
+    }
+  }
+
+  /// Creates a new stack, returning `None` if there isn't enough memory for it.
+  pub fn try_new(depth: usize) -> Option<Self> {
     let align = ::core::mem::align_of::<u8>();
     // UNSAFE: We're touching the allocation interface, but the stack keeps track of any memory
     // that gets allocated, when the stack is dropped it will free the memory.
     let ptr = unsafe { heap::allocate(depth, align) };
     if ptr.is_null() {
-      alloc::oom();
+      return None;
     }
 
-    Stack {
+    Some(Stack {
       // UNSAFE: We've allocated 'depth' size already successfuly, so this offset must be within
       // bounds
       ptr: unsafe { ptr.offset(depth as isize) } as *const usize,
       base: ptr as *const usize,
       depth: depth,
+    })
+  }
+
+  /// Creates a stack on top of memory that was not allocated from the heap.
+  ///
+  /// Dropping a `Stack` frees its memory, so the returned stack must never be dropped. It's only
+  /// used for static tasks, whose control blocks are never dropped.
+  pub unsafe fn from_static(mem: &'static mut [u8]) -> Self {
+    let depth = mem.len();
+    let ptr = mem.as_mut_ptr();
+    Stack {
+      // UNSAFE: The slice is exactly 'depth' bytes long, so this offset is one past the end of it
+      ptr: ptr.offset(depth as isize) as *const usize,
+      base: ptr as *const usize,
+      depth: depth,
     }
   }
 
@@ -51,6 +74,9 @@ impl Stack {
 
   pub fn depth(&self) -> usize { self.depth }
 
+  /// Returns the saved top of the stack.
+  pub fn top(&self) -> *const usize { self.ptr }
+
   unsafe fn ptr(&self) -> Volatile<usize> {
     Volatile::new(self.ptr)
   }
@@ -115,6 +141,23 @@ mod tests {
     assert_eq!(size, stack.depth);
   }
 
+  #[test]
+  fn try_new_out_of_memory() {
+    assert!(Stack::try_new(usize::max_value() / 2).is_none());
+  }
+
+  #[test]
+  fn static_stack_uses_provided_memory() {
+    static mut MEMORY: [u8; 256] = [0; 256];
+    let stack = unsafe { Stack::from_static(&mut MEMORY) };
+    let size = stack.ptr as usize - stack.base as usize;
+
+    assert_eq!(size, 256);
+    assert_eq!(stack.base as usize, unsafe { MEMORY.as_ptr() } as usize);
+    // The memory isn't from the heap
+    ::core::mem::forget(stack);
+  }
+
   #[test]
   fn check_stack_overflow_no_overflow() {
     let stack = Stack::new(1024);
@@ -0,0 +1,167 @@
+// task/static_task.rs
+// AltOSRust
+//
+// Created by Daniel Seitz on 2/4/17
+
+//! Statically allocated tasks.
+//!
+//! Tasks created through `new_task` allocate their control block, stack and arguments on the heap.
+//! A `StaticTask` reserves all of that memory at compile time instead, so a task can be created
+//! without touching the heap at all. Use the `static_task!` macro to declare one.
+
+use super::control::{TaskControl, Priority};
+use super::stack::Stack;
+use super::args::Args;
+use queue::{Node, NodePtr};
+use alloc::boxed::Box;
+use atomic::{AtomicBool, ATOMIC_BOOL_INIT, Ordering};
+use core::cell::UnsafeCell;
+use core::{mem, slice};
+
+/// Memory reserved for a single task at compile time.
+///
+/// A `StaticTask` holds the task's control block, its arguments and its stack. The type parameter
+/// `S` is the type of the stack memory, its size in bytes is the depth of the stack. Each
+/// `StaticTask` can only be used to create a single task, and its memory is never reclaimed even if
+/// the task is destroyed.
+///
+/// Declare these with the `static_task!` macro rather than constructing them directly.
+pub struct StaticTask<S: 'static> {
+  claimed: AtomicBool,
+  node: UnsafeCell<Option<Node<TaskControl>>>,
+  args: UnsafeCell<Option<Args>>,
+  stack: UnsafeCell<S>,
+}
+
+unsafe impl<S: 'static> Sync for StaticTask<S> {}
+
+impl<S: 'static> StaticTask<S> {
+  /// Creates a new `StaticTask` that will use `stack` as its stack memory.
+  pub const fn new(stack: S) -> Self {
+    StaticTask {
+      claimed: ATOMIC_BOOL_INIT,
+      node: UnsafeCell::new(None),
+      args: UnsafeCell::new(None),
+      stack: UnsafeCell::new(stack),
+    }
+  }
+
+  /// Returns the size of the stack in bytes.
+  pub fn depth(&self) -> usize {
+    mem::size_of::<S>()
+  }
+
+  /// Returns true if a task has already been created from this memory.
+  pub fn is_claimed(&self) -> bool {
+    self.claimed.load(Ordering::SeqCst)
+  }
+
+  /// Initializes the task in this static memory and returns the node that should be scheduled.
+  ///
+  /// The returned `NodePtr` refers to the static node, dropping it never drops the task or frees
+  /// its memory.
+  ///
+  /// # Panics
+  ///
+  /// This method will panic if a task has already been created from this memory.
+  #[doc(hidden)]
+  pub fn claim(&'static self, code: fn(&mut Args), args: Args, priority: Priority,
+               name: &'static str) -> NodePtr<TaskControl> {
+    if self.claimed.compare_and_swap(false, true, Ordering::SeqCst) != false {
+      panic!("StaticTask::claim - static task memory has already been used!");
+    }
+
+    // UNSAFE: We've just claimed this memory, so we're the only ones with access to it. The memory
+    // is 'static so the pointers we hand out will always be valid. The task control block is only
+    // ever reached through a static `NodePtr`, so it is never dropped and neither are the stack and
+    // arguments inside it.
+    unsafe {
+      let stack_mem = slice::from_raw_parts_mut(self.stack.get() as *mut u8, self.depth());
+      let stack = Stack::from_static(stack_mem);
+
+      let args_slot = &mut *self.args.get();
+      *args_slot = Some(args);
+      let args = Box::from_raw(args_slot.as_mut().unwrap() as *mut Args);
+
+      let node_slot: &'static mut Option<Node<TaskControl>> = &mut *self.node.get();
+      *node_slot = Some(Node::new(TaskControl::new_static(code, args, stack, priority, name)));
+      NodePtr::from(node_slot.as_mut().unwrap())
+    }
+  }
+}
+
+/// Declares statically allocated memory for a task.
+///
+/// This creates a `static` `StaticTask` with a stack of the given depth in bytes. Pass it to
+/// `syscall::new_static_task` to create a task without doing any heap allocation.
+///
+/// # Examples
+///
+/// ```rust,no_run
+/// #[macro_use]
+/// extern crate altos_core;
+///
+/// use altos_core::Priority;
+/// use altos_core::syscall::new_static_task;
+/// use altos_core::args::Args;
+///
+/// static_task!(static BLINK_TASK: 512);
+///
+/// # fn main() {
+/// new_static_task(&BLINK_TASK, blink, Args::empty(), Priority::Normal, "blink");
+/// # }
+///
+/// fn blink(_args: &mut Args) {
+///   loop {}
+/// }
+/// ```
+#[macro_export]
+macro_rules! static_task {
+  ($(#[$attr:meta])* static $name:ident: $depth:expr) => {
+    $(#[$attr])*
+    static $name: $crate::StaticTask<[usize; $depth / ::core::mem::size_of::<usize>()]> =
+      $crate::StaticTask::new([0; $depth / ::core::mem::size_of::<usize>()]);
+  };
+  ($(#[$attr:meta])* pub static $name:ident: $depth:expr) => {
+    $(#[$attr])*
+    pub static $name: $crate::StaticTask<[usize; $depth / ::core::mem::size_of::<usize>()]> =
+      $crate::StaticTask::new([0; $depth / ::core::mem::size_of::<usize>()]);
+  };
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  fn test_task(_args: &mut Args) {}
+
+  #[test]
+  fn static_task_claim_smoke() {
+    static TASK: StaticTask<[usize; 64]> = StaticTask::new([0; 64]);
+
+    assert_not!(TASK.is_claimed());
+    let node = TASK.claim(test_task, Args::empty(), Priority::Normal, "static test");
+    assert!(TASK.is_claimed());
+    assert!(node.is_static());
+    assert_eq!(TASK.depth(), 64 * mem::size_of::<usize>());
+
+    // Dropping the node leaves the static memory alone
+    drop(node);
+  }
+
+  #[test]
+  #[should_panic]
+  fn static_task_claim_twice_panics() {
+    static TASK: StaticTask<[usize; 64]> = StaticTask::new([0; 64]);
+
+    TASK.claim(test_task, Args::empty(), Priority::Normal, "static test");
+    TASK.claim(test_task, Args::empty(), Priority::Normal, "static test");
+  }
+
+  #[test]
+  fn static_task_macro_depth() {
+    static_task!(static TASK: 256);
+
+    assert_eq!(TASK.depth(), 256);
+  }
+}
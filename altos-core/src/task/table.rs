@@ -0,0 +1,113 @@
+// task/table.rs
+// AltOSRust
+//
+// Created by Daniel Seitz on 2/17/17
+
+//! The task table.
+//!
+//! Every task that has a `TaskHandle` is registered in a slot in this table. Each slot has a
+//! generation count that is bumped whenever the task in it goes away, a handle remembers the
+//! generation of the slot it was given, so a stale handle is detected without ever dereferencing
+//! the memory of a task that has already been freed.
+
+use super::control::TaskControl;
+use sync::{SpinMutex, CriticalSection};
+use core::ptr;
+
+/// The maximum number of tasks that can exist at one time.
+///
+/// This is 32 by default, the `tasks_64` and `tasks_128` features raise it for systems that need
+/// more tasks. Each slot costs two words of RAM.
+#[cfg(not(any(feature="tasks_64", feature="tasks_128")))]
+pub const MAX_TASKS: usize = 32;
+#[cfg(all(feature="tasks_64", not(feature="tasks_128")))]
+pub const MAX_TASKS: usize = 64;
+#[cfg(feature="tasks_128")]
+pub const MAX_TASKS: usize = 128;
+
+#[derive(Copy, Clone)]
+struct Slot {
+  generation: usize,
+  task: *mut TaskControl,
+}
+
+struct Table([Slot; MAX_TASKS]);
+
+unsafe impl Send for Table {}
+
+static TABLE: SpinMutex<Table> =
+  SpinMutex::new(Table([Slot { generation: 0, task: ptr::null_mut() }; MAX_TASKS]));
+
+/// Registers `task` in a free slot of the table, returning the index and generation of the slot.
+///
+/// The task must not be moved for as long as it is registered. Returns `None` if the table is full.
+pub fn insert(task: &mut TaskControl) -> Option<(usize, usize)> {
+  let _g = CriticalSection::begin();
+  let mut table = TABLE.lock();
+  for (index, slot) in table.0.iter_mut().enumerate() {
+    if slot.task.is_null() {
+      slot.task = task;
+      return Some((index, slot.generation));
+    }
+  }
+  None
+}
+
+/// Returns true if there are no free slots left in the table.
+pub fn is_full() -> bool {
+  let _g = CriticalSection::begin();
+  let table = TABLE.lock();
+  table.0.iter().all(|slot| !slot.task.is_null())
+}
+
+/// Frees the slot at `index`, invalidating any handles that refer to it.
+pub fn remove(index: usize, generation: usize) {
+  let _g = CriticalSection::begin();
+  let mut table = TABLE.lock();
+  if let Some(slot) = table.0.get_mut(index) {
+    if slot.generation == generation {
+      slot.task = ptr::null_mut();
+      slot.generation = slot.generation.wrapping_add(1);
+    }
+  }
+}
+
+/// Returns the task in the slot at `index` if the slot still has the same generation.
+pub fn lookup(index: usize, generation: usize) -> Option<*mut TaskControl> {
+  let _g = CriticalSection::begin();
+  let table = TABLE.lock();
+  match table.0.get(index) {
+    Some(slot) if slot.generation == generation && !slot.task.is_null() => Some(slot.task),
+    _ => None,
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use task::Priority;
+  use test;
+
+  #[test]
+  fn insert_and_lookup() {
+    let mut task = test::create_test_task(512, Priority::Normal, "table test");
+    let (index, generation) = insert(&mut task).unwrap();
+
+    assert_eq!(lookup(index, generation), Some(&mut task as *mut _));
+    remove(index, generation);
+  }
+
+  #[test]
+  fn stale_generation_not_found() {
+    let mut task = test::create_test_task(512, Priority::Normal, "table test");
+    let (index, generation) = insert(&mut task).unwrap();
+    remove(index, generation);
+
+    assert_eq!(lookup(index, generation), None);
+  }
+
+  #[test]
+  fn out_of_bounds_not_found() {
+    assert_eq!(lookup(MAX_TASKS, 0), None);
+  }
+}
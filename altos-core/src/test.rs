@@ -13,8 +13,11 @@ macro_rules! assert_not {
 
 use sched::{CURRENT_TASK, SLEEP_QUEUE, DELAY_QUEUE, OVERFLOW_DELAY_QUEUE, PRIORITY_QUEUES};
 use sync::{SpinMutex, SpinGuard};
-use task::{Priority, TaskControl, TaskHandle};
+use task::{Priority, TaskControl, TaskHandle, StaticTask};
 use task::args::Args;
+use std::boxed::Box;
+use std::vec::Vec;
+use tick;
 
 static TEST_LOCK: SpinMutex<()> = SpinMutex::new(());
 
@@ -23,9 +26,7 @@ pub fn set_up() -> SpinGuard<'static, ()> {
   SLEEP_QUEUE.remove_all();
   DELAY_QUEUE.remove_all();
   OVERFLOW_DELAY_QUEUE.remove_all();
-  for queue in PRIORITY_QUEUES.iter() {
-    queue.remove_all();
-  }
+  PRIORITY_QUEUES.clear();
   unsafe { CURRENT_TASK = None };
   guard
 }
@@ -40,8 +41,9 @@ pub fn create_and_schedule_test_task(stack_size: usize, priority: Priority, name
     ::syscall::new_task(test_task, Args::empty(), stack_size, priority, name)
 }
 
-pub fn convert_handle_to_task_control(handle: TaskHandle) -> &'static TaskControl {
-  unsafe { ::std::mem::transmute::<TaskHandle, &TaskControl>(handle) }
+pub fn create_and_schedule_static_test_task<S: 'static>(task: &'static StaticTask<S>, priority: Priority,
+                                               name: &'static str) -> TaskHandle {
+  ::syscall::new_static_task(task, test_task, Args::empty(), priority, name)
 }
 
 pub fn current_task() -> Option<&'static mut TaskControl> {
@@ -55,3 +57,134 @@ pub fn create_two_tasks() -> (TaskHandle, TaskHandle) {
 }
 
 fn test_task(_args: &mut Args) {}
+
+/// A task being picked to run by `switch_context`.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct Switch {
+  /// The tick the switch happened at, counted from the start of the scenario.
+  pub tick: usize,
+  /// The tid of the task that was switched to.
+  pub tid: usize,
+}
+
+static SWITCHES: SpinMutex<Option<(usize, Vec<Switch>)>> = SpinMutex::new(None);
+
+/// Called by `switch_context` with the task it picked, this is a no-op unless a `Harness` is
+/// recording.
+pub fn record_switch(tid: usize) {
+  let mut recording = SWITCHES.lock();
+  if let Some((start, ref mut switches)) = *recording {
+    switches.push(Switch { tick: tick::get_tick().wrapping_sub(start), tid: tid });
+  }
+}
+
+/// Drives the scheduler with a virtual clock to test scenarios tick by tick.
+///
+/// The harness records every task that `switch_context` picks along with the tick it was picked
+/// at, so a test can make assertions on the timeline. Interrupts can be injected at chosen ticks,
+/// they run just before the tick handler for that tick. All ticks are counted from when the
+/// harness was created.
+///
+/// # Examples
+///
+/// ```rust,ignore
+/// let mut harness = Harness::new();
+/// let normal = harness.spawn(Priority::Normal, "normal");
+/// harness.start();
+/// harness.interrupt_at(3, || { /* wake something up */ });
+/// harness.run_for(5);
+/// assert_eq!(harness.switches_to(&normal), vec![0]);
+/// ```
+pub struct Harness {
+  _guard: SpinGuard<'static, ()>,
+  start: usize,
+  saved_tick: usize,
+  interrupts: Vec<(usize, Box<FnMut()>)>,
+}
+
+impl Harness {
+  /// Sets up a fresh scenario starting at the current tick.
+  pub fn new() -> Self {
+    let now = tick::get_tick();
+    Self::starting_at(now)
+  }
+
+  /// Sets up a fresh scenario with the clock starting at `start`, the clock is put back when the
+  /// harness is dropped.
+  pub fn starting_at(start: usize) -> Self {
+    let guard = set_up();
+    let saved_tick = tick::get_tick();
+    tick::set_tick(start);
+    *SWITCHES.lock() = Some((start, Vec::new()));
+    Harness {
+      _guard: guard,
+      start: start,
+      saved_tick: saved_tick,
+      interrupts: Vec::new(),
+    }
+  }
+
+  /// Creates a task and puts it in the ready queue.
+  pub fn spawn(&mut self, priority: Priority, name: &'static str) -> TaskHandle {
+    create_and_schedule_test_task(512, priority, name)
+  }
+
+  /// Starts the scheduler, the first task it picks is switched to at tick 0.
+  pub fn start(&mut self) {
+    ::sched::start_scheduler();
+  }
+
+  /// Runs `interrupt` just before the tick handler for tick `at`.
+  pub fn interrupt_at<F: FnMut() + 'static>(&mut self, at: usize, interrupt: F) {
+    self.interrupts.push((at, Box::new(interrupt)));
+  }
+
+  /// Advances the clock by `ticks`, firing any interrupts that are due along the way.
+  pub fn run_for(&mut self, ticks: usize) {
+    for _ in 0..ticks {
+      let next = self.now().wrapping_add(1);
+      let mut i = 0;
+      while i < self.interrupts.len() {
+        if self.interrupts[i].0 == next {
+          let (_, mut interrupt) = self.interrupts.remove(i);
+          interrupt();
+        }
+        else {
+          i += 1;
+        }
+      }
+      ::syscall::system_tick();
+    }
+  }
+
+  /// Returns the current tick, counted from the start of the scenario.
+  pub fn now(&self) -> usize {
+    tick::get_tick().wrapping_sub(self.start)
+  }
+
+  /// Returns every switch that has happened so far.
+  pub fn timeline(&self) -> Vec<Switch> {
+    match *SWITCHES.lock() {
+      Some((_, ref switches)) => switches.clone(),
+      None => Vec::new(),
+    }
+  }
+
+  /// Returns the ticks that the task behind `handle` was switched to at.
+  pub fn switches_to(&self, handle: &TaskHandle) -> Vec<usize> {
+    let tid = handle.tid().unwrap();
+    self.timeline().iter().filter(|switch| switch.tid == tid).map(|switch| switch.tick).collect()
+  }
+
+  /// Returns true if the task behind `handle` is the one running.
+  pub fn is_running(&self, handle: &TaskHandle) -> bool {
+    current_task().map(|task| task.tid()) == handle.tid().ok()
+  }
+}
+
+impl Drop for Harness {
+  fn drop(&mut self) {
+    *SWITCHES.lock() = None;
+    tick::set_tick(self.saved_tick);
+  }
+}
@@ -26,3 +26,9 @@ pub fn get_tick() -> usize {
   SYSTEM_TICKS.load(Ordering::Relaxed)
 }
 
+
+/// Sets the tick counter, so tests can start the clock anywhere they like.
+#[cfg(test)]
+pub fn set_tick(ticks: usize) {
+  SYSTEM_TICKS.store(ticks, Ordering::Relaxed);
+}
@@ -0,0 +1,286 @@
+// trace.rs
+// AltOSRust
+//
+// Created by Daniel Seitz on 2/22/17
+
+//! Kernel event tracing.
+//!
+//! With the `trace` feature enabled the kernel records an event whenever it does something
+//! interesting, like switching tasks or waking up a sleeping channel. Events are timestamped and
+//! stored in a fixed size ring buffer in RAM, once the buffer is full the oldest events are
+//! overwritten. The application can drain the buffer and send the records off the device, where the
+//! `trace_decode` tool in the `tools` directory turns them back into a readable timeline.
+//!
+//! Each record is dumped as three little-endian 32-bit words: the timestamp, the event kind and
+//! the event's argument. The kind numbers are part of the dump format and must not be changed.
+
+use sync::{SpinMutex, CriticalSection};
+use tick;
+
+/// The number of events the trace buffer can hold.
+pub const TRACE_BUFFER_LEN: usize = 64;
+
+/// The size in bytes of a record once it has been converted by `Record::to_bytes`.
+pub const RECORD_SIZE: usize = 12;
+
+/// An event recorded by the kernel.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum Event {
+  /// An empty slot in the buffer, never recorded.
+  None,
+  /// The task with this tid started running.
+  SwitchIn(usize),
+  /// The task with this tid stopped running.
+  SwitchOut(usize),
+  /// A task was created with this tid.
+  TaskCreate(usize),
+  /// The task with this tid was destroyed.
+  TaskDestroy(usize),
+  /// The running task went to sleep on this channel.
+  Sleep(usize),
+  /// The tasks sleeping on this channel were woken.
+  Wake(usize),
+  /// The mutex at this address was locked.
+  MutexLock(usize),
+  /// The mutex at this address was unlocked.
+  MutexUnlock(usize),
+  /// The handler for this exception number started.
+  IsrEnter(usize),
+  /// The handler for this exception number finished.
+  IsrExit(usize),
+}
+
+impl Event {
+  /// Returns the kind number and argument that the event is dumped as.
+  pub fn encode(&self) -> (usize, usize) {
+    match *self {
+      Event::None => (0, 0),
+      Event::SwitchIn(tid) => (1, tid),
+      Event::SwitchOut(tid) => (2, tid),
+      Event::TaskCreate(tid) => (3, tid),
+      Event::TaskDestroy(tid) => (4, tid),
+      Event::Sleep(wchan) => (5, wchan),
+      Event::Wake(wchan) => (6, wchan),
+      Event::MutexLock(addr) => (7, addr),
+      Event::MutexUnlock(addr) => (8, addr),
+      Event::IsrEnter(number) => (9, number),
+      Event::IsrExit(number) => (10, number),
+    }
+  }
+}
+
+/// A timestamped event in the trace buffer.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct Record {
+  /// The time the event happened at, from the timestamp source.
+  pub timestamp: usize,
+  /// What happened.
+  pub event: Event,
+}
+
+impl Record {
+  const EMPTY: Record = Record { timestamp: 0, event: Event::None };
+
+  /// Converts the record into the format read by the host decoder.
+  pub fn to_bytes(&self) -> [u8; RECORD_SIZE] {
+    let (kind, arg) = self.event.encode();
+    let mut bytes = [0; RECORD_SIZE];
+    for (i, word) in [self.timestamp, kind, arg].iter().enumerate() {
+      let word = *word as u32;
+      bytes[i * 4] = word as u8;
+      bytes[i * 4 + 1] = (word >> 8) as u8;
+      bytes[i * 4 + 2] = (word >> 16) as u8;
+      bytes[i * 4 + 3] = (word >> 24) as u8;
+    }
+    bytes
+  }
+}
+
+struct Buffer {
+  records: [Record; TRACE_BUFFER_LEN],
+  head: usize,
+  len: usize,
+  lost: usize,
+}
+
+static BUFFER: SpinMutex<Buffer> = SpinMutex::new(Buffer {
+  records: [Record::EMPTY; TRACE_BUFFER_LEN],
+  head: 0,
+  len: 0,
+  lost: 0,
+});
+
+static mut TIMESTAMP: fn() -> usize = tick::get_tick;
+
+/// Sets the function used to timestamp events.
+///
+/// By default events are timestamped with the system tick count, which can't tell apart events
+/// that happen within the same tick. A port can supply a finer grained clock, like a cycle counter.
+pub fn set_timestamp_source(source: fn() -> usize) {
+  // UNSAFE: Function pointers are written in a single store
+  unsafe { TIMESTAMP = source };
+}
+
+/// Records an event in the trace buffer, overwriting the oldest event if the buffer is full.
+///
+/// The kernel records its own events, this is public so ports and applications can record
+/// interrupts and other events of their own.
+pub fn record(event: Event) {
+  let _g = CriticalSection::begin();
+  // UNSAFE: The timestamp source is only ever written during initialization
+  let timestamp = unsafe { TIMESTAMP() };
+  let mut buffer = BUFFER.lock();
+  let tail = (buffer.head + buffer.len) % TRACE_BUFFER_LEN;
+  buffer.records[tail] = Record { timestamp: timestamp, event: event };
+  if buffer.len == TRACE_BUFFER_LEN {
+    buffer.head = (buffer.head + 1) % TRACE_BUFFER_LEN;
+    buffer.lost += 1;
+  }
+  else {
+    buffer.len += 1;
+  }
+}
+
+/// Records that the handler for exception `number` started.
+pub fn isr_enter(number: usize) {
+  record(Event::IsrEnter(number));
+}
+
+/// Records that the handler for exception `number` finished.
+pub fn isr_exit(number: usize) {
+  record(Event::IsrExit(number));
+}
+
+/// Moves the oldest records in the trace buffer into `out`, returning how many were moved.
+///
+/// # Examples
+///
+/// ```rust,no_run
+/// use altos_core::trace::{self, Record, TRACE_BUFFER_LEN};
+///
+/// let mut records = [Record { timestamp: 0, event: trace::Event::None }; TRACE_BUFFER_LEN];
+/// let count = trace::drain(&mut records);
+/// for record in &records[..count] {
+///   // Send record.to_bytes() to the host...
+/// }
+/// ```
+pub fn drain(out: &mut [Record]) -> usize {
+  let _g = CriticalSection::begin();
+  let mut buffer = BUFFER.lock();
+  let count = ::core::cmp::min(out.len(), buffer.len);
+  for slot in out[..count].iter_mut() {
+    *slot = buffer.records[buffer.head];
+    buffer.head = (buffer.head + 1) % TRACE_BUFFER_LEN;
+  }
+  buffer.len -= count;
+  count
+}
+
+/// Copies the newest records in the trace buffer into `out` without removing them, oldest first,
+/// returning how many were copied.
+///
+/// This doesn't wait for the buffer, it's meant for crash handlers that might have interrupted
+/// something in the middle of recording. If the buffer is in use nothing is copied.
+pub fn latest(out: &mut [Record]) -> usize {
+  let buffer = match BUFFER.try_lock() {
+    Some(buffer) => buffer,
+    None => return 0,
+  };
+  let count = ::core::cmp::min(out.len(), buffer.len);
+  let start = buffer.head + buffer.len - count;
+  for (i, slot) in out[..count].iter_mut().enumerate() {
+    *slot = buffer.records[(start + i) % TRACE_BUFFER_LEN];
+  }
+  count
+}
+
+/// Returns how many records have been overwritten since the last call, then resets the count.
+pub fn take_lost() -> usize {
+  let _g = CriticalSection::begin();
+  let mut buffer = BUFFER.lock();
+  ::core::mem::replace(&mut buffer.lost, 0)
+}
+
+/// Throws away every record in the trace buffer.
+pub fn clear() {
+  let _g = CriticalSection::begin();
+  let mut buffer = BUFFER.lock();
+  buffer.head = 0;
+  buffer.len = 0;
+  buffer.lost = 0;
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use test;
+
+  fn drain_all() -> ::std::vec::Vec<Record> {
+    let mut records = [Record::EMPTY; TRACE_BUFFER_LEN];
+    let count = drain(&mut records);
+    records[..count].to_vec()
+  }
+
+  #[test]
+  fn drain_in_order() {
+    let _g = test::set_up();
+    clear();
+    record(Event::Sleep(1));
+    record(Event::Wake(2));
+
+    let records = drain_all();
+    assert_eq!(records.len(), 2);
+    assert_eq!(records[0].event, Event::Sleep(1));
+    assert_eq!(records[1].event, Event::Wake(2));
+    assert!(drain_all().is_empty());
+  }
+
+  #[test]
+  fn overwrites_oldest() {
+    let _g = test::set_up();
+    clear();
+    for i in 0..TRACE_BUFFER_LEN + 2 {
+      record(Event::Wake(i));
+    }
+
+    let records = drain_all();
+    assert_eq!(records.len(), TRACE_BUFFER_LEN);
+    assert_eq!(records[0].event, Event::Wake(2));
+    assert_eq!(take_lost(), 2);
+    assert_eq!(take_lost(), 0);
+  }
+
+  #[test]
+  fn latest_keeps_records() {
+    let _g = test::set_up();
+    clear();
+    for i in 0..TRACE_BUFFER_LEN + 3 {
+      record(Event::Wake(i));
+    }
+
+    let mut records = [Record::EMPTY; 2];
+    assert_eq!(latest(&mut records), 2);
+    assert_eq!(records[0].event, Event::Wake(TRACE_BUFFER_LEN + 1));
+    assert_eq!(records[1].event, Event::Wake(TRACE_BUFFER_LEN + 2));
+    assert_eq!(drain_all().len(), TRACE_BUFFER_LEN);
+  }
+
+  #[test]
+  fn record_to_bytes() {
+    let record = Record { timestamp: 0x0102_0304, event: Event::SwitchIn(5) };
+    assert_eq!(record.to_bytes(), [4, 3, 2, 1, 1, 0, 0, 0, 5, 0, 0, 0]);
+  }
+
+  #[test]
+  fn context_switch_is_traced() {
+    let _g = test::set_up();
+    let (handle_1, handle_2) = test::create_two_tasks();
+    ::sched::start_scheduler();
+    clear();
+
+    ::syscall::sched_yield();
+    let records = drain_all();
+    assert_eq!(records[0].event, Event::SwitchOut(handle_1.tid().unwrap()));
+    assert_eq!(records[1].event, Event::SwitchIn(handle_2.tid().unwrap()));
+  }
+}
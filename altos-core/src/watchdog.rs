@@ -0,0 +1,280 @@
+// watchdog.rs
+// AltOSRust
+//
+// Created by Daniel Seitz on 2/23/17
+
+//! Task liveness monitoring.
+//!
+//! A task that should never get stuck can register itself with the software watchdog, giving a
+//! timeout in ticks. From then on it must call `Watch::check_in` at least once every timeout. The
+//! watchdog is checked on every system tick, if a task goes longer than its timeout without
+//! checking in it is reported as stalled through the stall hook. The hook gets the stalled task's
+//! name, tid and a `TaskHandle`, so it can reset the system or destroy the task and start a new
+//! one.
+//!
+//! A feed hook can also be set, which is called on every tick as long as no watched task is
+//! stalled. Feeding the hardware watchdog from it means the system is only reset by the hardware
+//! if some task stops making progress, instead of only if the tick interrupt stops.
+
+use task::TaskHandle;
+use sched::CURRENT_TASK;
+use sync::{SpinMutex, CriticalSection};
+use tick;
+
+/// The maximum number of tasks that can be watched at one time.
+pub const MAX_WATCHED: usize = 8;
+
+/// The details of a stalled task, passed to the stall hook.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct Stall {
+  /// The tid of the stalled task.
+  pub tid: usize,
+  /// The name of the stalled task.
+  pub name: &'static str,
+  /// A handle to the stalled task.
+  pub handle: TaskHandle,
+  /// The tick the task last checked in at.
+  pub last_check_in: usize,
+  /// The tick the stall was noticed at.
+  pub tick: usize,
+}
+
+#[derive(Copy, Clone)]
+struct Entry {
+  handle: Option<TaskHandle>,
+  tid: usize,
+  name: &'static str,
+  timeout: usize,
+  last_check_in: usize,
+  stalled: bool,
+}
+
+impl Entry {
+  const EMPTY: Entry = Entry {
+    handle: None,
+    tid: 0,
+    name: "",
+    timeout: 0,
+    last_check_in: 0,
+    stalled: false,
+  };
+}
+
+static WATCHED: SpinMutex<[Entry; MAX_WATCHED]> = SpinMutex::new([Entry::EMPTY; MAX_WATCHED]);
+
+static mut STALL_HOOK: Option<fn(Stall)> = None;
+static mut FEED_HOOK: Option<fn()> = None;
+
+/// A registration with the software watchdog, the task that created it must check in through it
+/// before its timeout runs out.
+///
+/// Dropping the `Watch` stops watching the task.
+pub struct Watch {
+  index: usize,
+  handle: TaskHandle,
+}
+
+impl Watch {
+  /// Tells the watchdog that the task is still making progress.
+  ///
+  /// If the task had been reported as stalled it is considered healthy again.
+  pub fn check_in(&self) {
+    let _g = CriticalSection::begin();
+    let mut watched = WATCHED.lock();
+    let entry = &mut watched[self.index];
+    if entry.handle == Some(self.handle) {
+      entry.last_check_in = tick::get_tick();
+      entry.stalled = false;
+    }
+  }
+}
+
+impl Drop for Watch {
+  fn drop(&mut self) {
+    let _g = CriticalSection::begin();
+    let mut watched = WATCHED.lock();
+    if watched[self.index].handle == Some(self.handle) {
+      watched[self.index] = Entry::EMPTY;
+    }
+  }
+}
+
+/// Starts watching the current task, it must check in at least once every `timeout` ticks.
+///
+/// Returns `None` if `MAX_WATCHED` tasks are already being watched, or if the kernel's task table
+/// is full.
+///
+/// # Examples
+///
+/// ```rust,no_run
+/// use altos_core::watchdog;
+/// use altos_core::args::Args;
+///
+/// fn worker_task(_args: &mut Args) {
+///   let watch = watchdog::register(100).expect("too many watched tasks");
+///   loop {
+///     // Do some work that should never take longer than 100 ticks...
+///     watch.check_in();
+///   }
+/// }
+/// ```
+///
+/// # Panics
+///
+/// This function will panic if there is no current task.
+pub fn register(timeout: usize) -> Option<Watch> {
+  let _g = CriticalSection::begin();
+  // UNSAFE: Accessing CURRENT_TASK
+  let current = match unsafe { CURRENT_TASK.as_mut() } {
+    Some(current) => current,
+    None => panic!("watchdog::register - current task doesn't exist!"),
+  };
+  let handle = match TaskHandle::try_new(&mut **current) {
+    Some(handle) => handle,
+    None => return None,
+  };
+  let mut watched = WATCHED.lock();
+  for (index, entry) in watched.iter_mut().enumerate() {
+    if entry.handle.is_none() {
+      *entry = Entry {
+        handle: Some(handle),
+        tid: current.tid(),
+        name: current.name(),
+        timeout: timeout,
+        last_check_in: tick::get_tick(),
+        stalled: false,
+      };
+      return Some(Watch { index: index, handle: handle });
+    }
+  }
+  None
+}
+
+/// Sets a function to be called when a watched task doesn't check in before its timeout.
+///
+/// The hook is called once per stall from the tick interrupt, so it must not block. If the task
+/// checks in again it can be reported again the next time it stalls.
+pub fn set_stall_hook(hook: fn(Stall)) {
+  // UNSAFE: Function pointers are written in a single store
+  unsafe { STALL_HOOK = Some(hook) };
+}
+
+/// Sets a function to be called on every tick while none of the watched tasks are stalled.
+///
+/// This is meant to feed the hardware watchdog. It's called from the tick interrupt, so it must
+/// not block.
+pub fn set_feed_hook(hook: fn()) {
+  // UNSAFE: Function pointers are written in a single store
+  unsafe { FEED_HOOK = Some(hook) };
+}
+
+/// Checks the watched tasks for stalls, this is called by the kernel on every system tick.
+#[doc(hidden)]
+pub fn check(now: usize) {
+  let mut healthy = true;
+  for index in 0..MAX_WATCHED {
+    // Don't hold the lock while the hook runs, it might want to stop watching the task
+    let stall = {
+      let mut watched = WATCHED.lock();
+      let entry = &mut watched[index];
+      let handle = match entry.handle {
+        Some(handle) => handle,
+        None => continue,
+      };
+      if !handle.is_valid() {
+        // The task went away without dropping its Watch
+        *entry = Entry::EMPTY;
+        continue;
+      }
+      if entry.stalled {
+        healthy = false;
+        continue;
+      }
+      if now.wrapping_sub(entry.last_check_in) <= entry.timeout {
+        continue;
+      }
+      entry.stalled = true;
+      healthy = false;
+      Stall {
+        tid: entry.tid,
+        name: entry.name,
+        handle: handle,
+        last_check_in: entry.last_check_in,
+        tick: now,
+      }
+    };
+    // UNSAFE: The hook is only ever written during initialization
+    if let Some(hook) = unsafe { STALL_HOOK } {
+      hook(stall);
+    }
+  }
+
+  if healthy {
+    // UNSAFE: The hook is only ever written during initialization
+    if let Some(hook) = unsafe { FEED_HOOK } {
+      hook();
+    }
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use atomic::{AtomicUsize, ATOMIC_USIZE_INIT, Ordering};
+  use task::Priority;
+  use sched;
+  use test;
+
+  static STALLS: AtomicUsize = ATOMIC_USIZE_INIT;
+  static FEEDS: AtomicUsize = ATOMIC_USIZE_INIT;
+
+  fn count_stall(stall: Stall) {
+    assert_eq!(stall.name, "watched task");
+    STALLS.fetch_add(1, Ordering::SeqCst);
+  }
+
+  fn count_feed() {
+    FEEDS.fetch_add(1, Ordering::SeqCst);
+  }
+
+  #[test]
+  fn stall_reported_once() {
+    let _g = test::set_up();
+    set_stall_hook(count_stall);
+    set_feed_hook(count_feed);
+    test::create_and_schedule_test_task(512, Priority::Normal, "watched task");
+    sched::start_scheduler();
+
+    let now = tick::get_tick();
+    let watch = register(5).unwrap();
+    let stalls = STALLS.load(Ordering::SeqCst);
+    let feeds = FEEDS.load(Ordering::SeqCst);
+
+    check(now + 5);
+    assert_eq!(STALLS.load(Ordering::SeqCst), stalls);
+    assert_eq!(FEEDS.load(Ordering::SeqCst), feeds + 1);
+
+    check(now + 6);
+    check(now + 7);
+    assert_eq!(STALLS.load(Ordering::SeqCst), stalls + 1);
+    // The hardware watchdog isn't fed while a task is stalled
+    assert_eq!(FEEDS.load(Ordering::SeqCst), feeds + 1);
+
+    watch.check_in();
+    check(tick::get_tick() + 1);
+    assert_eq!(FEEDS.load(Ordering::SeqCst), feeds + 2);
+  }
+
+  #[test]
+  fn drop_stops_watching() {
+    let _g = test::set_up();
+    test::create_and_schedule_test_task(512, Priority::Normal, "watched task");
+    sched::start_scheduler();
+
+    let watch = register(5).unwrap();
+    let index = watch.index;
+    assert!(WATCHED.lock()[index].handle.is_some());
+    drop(watch);
+    assert!(WATCHED.lock()[index].handle.is_none());
+  }
+}
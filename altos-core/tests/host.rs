@@ -0,0 +1,64 @@
+// tests/host.rs
+// AltOSRust
+//
+// Created by Daniel Seitz on 2/24/17
+
+//! Runs the kernel on the host and checks that tasks really execute.
+//!
+//! The kernel is global, so everything is checked from a single test. The scheduler runs on its own
+//! thread since it never returns.
+
+extern crate altos_core;
+
+use altos_core::{start_scheduler, Priority};
+use altos_core::args::Args;
+use altos_core::atomic::{AtomicUsize, AtomicBool, ATOMIC_USIZE_INIT, ATOMIC_BOOL_INIT, Ordering};
+use altos_core::syscall::{new_task, sleep_for, FOREVER_CHAN};
+use altos_core::tick;
+use std::thread;
+use std::time::{Duration, Instant};
+
+static SLEPT_TICKS: AtomicUsize = ATOMIC_USIZE_INIT;
+static SHORT_TASKS_RUN: AtomicUsize = ATOMIC_USIZE_INIT;
+static DONE: AtomicBool = ATOMIC_BOOL_INIT;
+
+const SHORT_TASKS: usize = 3;
+
+#[test]
+fn tasks_run_on_host() {
+  thread::spawn(|| {
+    new_task(sleeper_task, Args::empty(), 512, Priority::Normal, "sleeper");
+    new_task(spawner_task, Args::empty(), 512, Priority::Normal, "spawner");
+    start_scheduler();
+  });
+
+  let timeout = Instant::now() + Duration::from_secs(10);
+  while !DONE.load(Ordering::SeqCst) {
+    assert!(Instant::now() < timeout, "the simulated tasks didn't finish in time!");
+    thread::sleep(Duration::from_millis(10));
+  }
+
+  assert!(SLEPT_TICKS.load(Ordering::SeqCst) >= 20);
+  assert_eq!(SHORT_TASKS_RUN.load(Ordering::SeqCst), SHORT_TASKS);
+}
+
+fn sleeper_task(_args: &mut Args) {
+  let start = tick::get_tick();
+  sleep_for(FOREVER_CHAN, 20);
+  SLEPT_TICKS.store(tick::get_tick() - start, Ordering::SeqCst);
+}
+
+/// Creates tasks that exit right away, so their threads have to be torn down.
+fn spawner_task(_args: &mut Args) {
+  for _ in 0..SHORT_TASKS {
+    new_task(short_task, Args::empty(), 512, Priority::Normal, "short");
+    sleep_for(FOREVER_CHAN, 5);
+  }
+  // Give the sleeper time to finish
+  sleep_for(FOREVER_CHAN, 30);
+  DONE.store(true, Ordering::SeqCst);
+}
+
+fn short_task(_args: &mut Args) {
+  SHORT_TASKS_RUN.fetch_add(1, Ordering::SeqCst);
+}
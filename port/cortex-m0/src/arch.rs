@@ -0,0 +1,174 @@
+// arch.rs
+// AltOS Rust
+//
+// Created by Daniel Seitz on 1/7/17
+
+//! The Cortex-M0 implementation of the kernel's architecture layer.
+
+use altos_core::volatile::Volatile;
+use altos_core::args::Args;
+use altos_core::alloc::boxed::Box;
+use altos_core::arch::Arch;
+use altos_core::syscall::{self, svc};
+use peripheral::systick;
+
+/// The Cortex-M0 processor, registered as the kernel's architecture.
+pub struct Cm0;
+
+impl Arch for Cm0 {
+  fn yield_cpu() {
+    const ICSR_ADDR: usize = 0xE000_ED04;
+    const PEND_SV_SET: usize = 0b1 << 28;
+
+    unsafe {
+      let mut reg = Volatile::new(ICSR_ADDR as *const usize);
+      *reg |= PEND_SV_SET;
+    }
+  }
+
+  fn initialize_stack(stack_ptr: Volatile<usize>, code: fn(&mut Args), args: &Box<Args>) -> usize {
+    const INITIAL_XPSR: usize = 0x0100_0000;
+    unsafe {
+      // Initial offset added to account for way MCU uses stack on entry/exit of interrupts
+      stack_ptr.offset(-1).store(INITIAL_XPSR); /* xPSR */
+      stack_ptr.offset(-2).store(code as usize); /* PC */
+      stack_ptr.offset(-3).store(exit_error as usize); /* LR */
+      stack_ptr.offset(-8).store(&**args as *const _ as usize); /* R0 */
+      stack_ptr.offset(-16).as_ptr() as usize
+    }
+  }
+
+  #[inline(never)]
+  fn start_first_task() {
+    unsafe {
+      #[cfg(target_arch="arm")]
+      asm!(
+        concat!(
+            "ldr r2, current_task_const_2\n", /* get location of current_task */
+            "ldr r3, [r2]\n",
+            "ldr r0, [r3]\n",
+
+            "adds r0, #32\n", /* discard everything up to r0 */
+            "msr psp, r0\n", /* this is the new top of stack to use for the task */
+
+            "movs r0, #2\n", /* switch to the psp stack */
+            "msr CONTROL, r0\n", /* we're using psp instead of msp now */
+
+            "isb\n", /* instruction barrier */
+
+            "pop {r0-r5}\n", /* pop the registers that are saved automatically */
+            "mov lr, r5\n", /* lr is now in r5, so put it back where it belongs */
+            "pop {r3}\n", /* pop return address (old pc) into r3 */
+            "pop {r2}\n", /* pop and discard xPSR */
+            "cpsie i\n", /* first task has its context, so interrupts can be enabled */
+            "bx r3\n", /* start executing user code */
+
+             ".align 4\n",
+            "current_task_const_2: .word CURRENT_TASK\n")
+        : /* no outputs */
+        : /* no inputs */
+        : /* no clobbers */
+        : "volatile");
+    }
+  }
+
+  fn wait_for_interrupt() {
+    unsafe {
+      #[cfg(target_arch="arm")]
+      asm!(
+        concat!(
+          "dsb\n", /* make sure any outstanding memory accesses are done */
+          "wfi\n") /* sleep until an interrupt is pending */
+        : /* no outputs */
+        : /* no inputs */
+        : /* no clobbers */
+        : "volatile");
+    }
+  }
+
+  fn in_kernel_mode() -> bool {
+    const MAIN_STACK: usize = 0b00;
+    const _PROGRAM_STACK: usize = 0b10;
+    unsafe {
+      let stack_mask: usize;
+      asm!("mrs $0, CONTROL\n" /* get the stack control mask */
+        : "=r"(stack_mask)
+        : /* no inputs */
+        : /* no clobbers */
+        : "volatile");
+      stack_mask == MAIN_STACK
+    }
+  }
+
+  fn begin_critical() -> usize {
+    let primask: usize;
+    unsafe {
+      asm!(
+        concat!(
+          "mrs $0, PRIMASK\n",
+          "cpsid i\n")
+        : "=r"(primask)
+        : /* no inputs */
+        : /* no clobbers */
+        : "volatile");
+    }
+    primask
+  }
+
+  fn end_critical(primask: usize) {
+    unsafe {
+      #[cfg(target_arch="arm")]
+      asm!("msr PRIMASK, $0"
+        : /* no outputs */
+        : "r"(primask)
+        : /* no clobbers */
+        : "volatile");
+    }
+  }
+
+  fn start_tick_source() {
+    let systick = systick::systick();
+
+    systick.use_processor_clock();
+    systick.clear_current_value();
+    systick.enable_counter();
+    systick.enable_interrupts();
+  }
+
+  fn system_call(number: usize, args: [usize; 3]) -> usize {
+    // An SVC taken while PRIMASK is set escalates to a HardFault, so a task that's in a critical
+    // section makes the call in place instead
+    let primask: usize;
+    unsafe {
+      asm!("mrs $0, PRIMASK\n"
+        : "=r"(primask)
+        : /* no inputs */
+        : /* no clobbers */
+        : "volatile");
+    }
+    if primask != 0 {
+      return svc::dispatch_in_place(number, args);
+    }
+
+    let result: usize;
+    unsafe {
+      // The handler stores the result over the stacked r0
+      asm!("svc #0\n"
+        : "={r0}"(result)
+        : "{r0}"(number), "{r1}"(args[0]), "{r2}"(args[1]), "{r3}"(args[2])
+        : "memory"
+        : "volatile");
+    }
+    result
+  }
+}
+
+arch_port!(Cm0);
+
+fn exit_error() -> ! {
+  unsafe {
+    #[cfg(target_arch="arm")]
+    asm!("bkpt");
+    syscall::exit();
+  }
+}
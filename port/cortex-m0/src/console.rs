@@ -0,0 +1,146 @@
+// console.rs
+// AltOSRust
+//
+// Created by Daniel Seitz on 3/3/17
+
+//! A text console over one of the serial ports.
+//!
+//! Once the console has been set up with `init` any task can write to it with the `print!` and
+//! `println!` macros. Output is serialized through a `Mutex`, so each call's output comes out in
+//! one piece rather than interleaved with another task's.
+//!
+//! The panic and fault handlers can't wait on a `Mutex`, they write through the emergency path
+//! instead. It polls the serial port directly without taking any locks, so it works even if the
+//! system is in a bad state, but it can cut into the middle of another task's output.
+//!
+//! # Examples
+//!
+//! ```rust,ignore
+//! #[macro_use]
+//! extern crate cortex_m0;
+//!
+//! use cortex_m0::kernel::console;
+//! use cortex_m0::peripheral::serial::{Usart, Config};
+//!
+//! console::init(Usart::Usart1, Config::new(115_200));
+//! println!("Hello from task {}", "main");
+//! ```
+
+use core::fmt::{self, Write};
+use altos_core::sync::{Mutex, MutexGuard};
+use altos_core::atomic::{AtomicUsize, ATOMIC_USIZE_INIT, Ordering};
+use altos_core::log::Record;
+use peripheral::serial::{Serial, Usart, Config};
+
+/// The serial port the console writes to, or `None` before the console is set up.
+pub struct Console {
+  serial: Option<Serial>,
+}
+
+impl Write for Console {
+  fn write_str(&mut self, s: &str) -> fmt::Result {
+    if let Some(serial) = self.serial {
+      serial.write(s.as_bytes());
+    }
+    Ok(())
+  }
+}
+
+static CONSOLE: Mutex<Console> = Mutex::new(Console { serial: None });
+
+/// The USART that the emergency path writes to, 0 means the console hasn't been set up.
+static EMERGENCY_USART: AtomicUsize = ATOMIC_USIZE_INIT;
+
+/// Opens `usart` and starts using it for the console.
+pub fn init(usart: Usart, config: Config) {
+  let serial = Serial::open(usart, config);
+  CONSOLE.lock().serial = Some(serial);
+  let number = match usart {
+    Usart::Usart1 => 1,
+    Usart::Usart2 => 2,
+  };
+  EMERGENCY_USART.store(number, Ordering::SeqCst);
+}
+
+/// Locks the console, so several writes can be made without another task's output getting in
+/// between them.
+///
+/// Writes are thrown away if the console hasn't been set up.
+///
+/// # Examples
+///
+/// ```rust,ignore
+/// use core::fmt::Write;
+/// use cortex_m0::kernel::console;
+///
+/// let mut console = console::lock();
+/// for i in 0..10 {
+///   write!(console, "{} ", i).unwrap();
+/// }
+/// ```
+pub fn lock() -> MutexGuard<'static, Console> {
+  CONSOLE.lock()
+}
+
+/// Writes to the console, this is used by the `print!` and `println!` macros.
+#[doc(hidden)]
+pub fn _print(args: fmt::Arguments) {
+  // The console never fails, writes just get dropped when it isn't set up
+  let _ = lock().write_fmt(args);
+}
+
+/// Writes to the console's serial port directly, without waiting for the console's lock.
+///
+/// This is only meant for when the system is going down, like in the panic and fault handlers,
+/// since it can cut into the middle of another task's output.
+pub fn emergency_write(args: fmt::Arguments) {
+  let usart = match EMERGENCY_USART.load(Ordering::SeqCst) {
+    1 => Usart::Usart1,
+    2 => Usart::Usart2,
+    _ => return,
+  };
+  let mut writer = EmergencyWriter(Serial::get(usart));
+  let _ = writer.write_fmt(args);
+}
+
+/// A log sink that writes each record to the console.
+///
+/// # Examples
+///
+/// ```rust,ignore
+/// use cortex_m0::kernel::{console, log};
+/// use cortex_m0::peripheral::serial::{Usart, Config};
+///
+/// console::init(Usart::Usart1, Config::new(115_200));
+/// log::start_logger(console::log_sink, 512);
+/// ```
+pub fn log_sink(record: &Record) {
+  let _ = write!(lock(), "{}\r\n", record);
+}
+
+struct EmergencyWriter(Serial);
+
+impl Write for EmergencyWriter {
+  fn write_str(&mut self, s: &str) -> fmt::Result {
+    self.0.write_polled(s.as_bytes());
+    Ok(())
+  }
+}
+
+/// Prints to the console.
+///
+/// This blocks if another task is printing, so it can only be used from a task.
+#[macro_export]
+macro_rules! print {
+  ($($arg:tt)*) => ($crate::kernel::console::_print(format_args!($($arg)*)));
+}
+
+/// Prints to the console, with a newline.
+///
+/// This blocks if another task is printing, so it can only be used from a task.
+#[macro_export]
+macro_rules! println {
+  () => (print!("\r\n"));
+  ($fmt:expr) => (print!(concat!($fmt, "\r\n")));
+  ($fmt:expr, $($arg:tt)*) => (print!(concat!($fmt, "\r\n"), $($arg)*));
+}
@@ -0,0 +1,434 @@
+// crash.rs
+// AltOSRust
+//
+// Created by Daniel Seitz on 3/1/17
+
+//! A crash log that survives a reset.
+//!
+//! When the system panics or faults the details are saved to a record in the `.noinit` section,
+//! which startup doesn't initialize, so after the reset the application can find out what went
+//! wrong and report it. The record holds why the system went down, the task that was running, the
+//! registers of a fault, the panic message and, with the `trace` feature, the last few trace
+//! events.
+//!
+//! The record is checksummed, RAM that was just powered on or a record that was only partly
+//! written when the system went down is never mistaken for a crash.
+//!
+//! Resets the software never saw coming, like the watchdog or the reset pin, can't leave a record.
+//! Those are found from the reset flags the RCC keeps, which are read once per boot and reported
+//! with the crash.
+//!
+//! # Examples
+//!
+//! ```rust,ignore
+//! use cortex_m0::crash;
+//!
+//! if let Some(crash) = crash::last_crash() {
+//!   // Report crash.reason, crash.message(), ...
+//!   crash::clear();
+//! }
+//! ```
+
+use core::fmt::{self, Write};
+use core::{mem, slice, str};
+use altos_core::CURRENT_TASK;
+#[cfg(feature="trace")]
+use altos_core::trace;
+use exceptions::fault::{ExceptionFrame, FaultReport};
+use peripheral::rcc::{self, ResetFlag};
+
+/// The number of bytes of the panic message that are kept, anything longer is cut off.
+pub const CRASH_MESSAGE_LEN: usize = 128;
+
+/// The number of bytes of the running task's name that are kept.
+pub const CRASH_TASK_NAME_LEN: usize = 16;
+
+/// The number of trace events that are kept.
+pub const CRASH_TRACE_LEN: usize = 16;
+
+/// The size of a trace event in the record, see `trace::Record::to_bytes`.
+pub const CRASH_TRACE_RECORD_SIZE: usize = 12;
+
+const CRASH_MAGIC: usize = 0x4352_5348;
+
+/// Why the system went down.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum CrashReason {
+  /// Something panicked.
+  Panic,
+  /// A HardFault happened.
+  HardFault,
+  /// The hardware reset the system without a panic or fault being saved, `Crash::reset` says why.
+  Reset,
+}
+
+impl CrashReason {
+  fn code(&self) -> usize {
+    match *self {
+      CrashReason::Panic => 1,
+      CrashReason::HardFault => 2,
+      CrashReason::Reset => 3,
+    }
+  }
+
+  fn from_code(code: usize) -> Option<Self> {
+    match code {
+      1 => Some(CrashReason::Panic),
+      2 => Some(CrashReason::HardFault),
+      _ => None,
+    }
+  }
+}
+
+/// The registers saved for a fault.
+#[derive(Debug, Copy, Clone)]
+pub struct CrashRegisters {
+  /// The registers the hardware stacked on entry to the HardFault.
+  pub frame: ExceptionFrame,
+  /// The EXC_RETURN value the handler was entered with.
+  pub exc_return: usize,
+}
+
+/// The sources that caused the last reset, as recorded by the RCC.
+///
+/// The reset pin is driven by every other source of reset too, so `pin` is usually set along with
+/// them.
+#[derive(Debug, Copy, Clone, PartialEq, Default)]
+pub struct ResetFlags {
+  /// The reset pin was pulled low.
+  pub pin: bool,
+  /// Power was turned on, or dropped too low. A brown out can't be told apart from power on.
+  pub power: bool,
+  /// The software asked for a reset, like the `Reset` panic and fault policies do.
+  pub software: bool,
+  /// The independent watchdog ran out.
+  pub watchdog: bool,
+  /// The window watchdog ran out.
+  pub window_watchdog: bool,
+  /// The processor tried to enter a low power mode that reset is configured for.
+  pub low_power: bool,
+}
+
+impl ResetFlags {
+  /// Returns true if the reset came from outside the software and wasn't just power coming on.
+  fn unexpected(&self) -> bool {
+    self.watchdog || self.window_watchdog || self.low_power ||
+      (self.pin && !self.power && !self.software)
+  }
+}
+
+/// A crash read back out of the crash log.
+///
+/// The crash is a copy of the record, it isn't changed if another crash is saved after it was
+/// read.
+#[derive(Copy)]
+pub struct Crash {
+  /// Why the system went down.
+  pub reason: CrashReason,
+  /// The registers at the time of a fault.
+  pub registers: Option<CrashRegisters>,
+  /// The sources of the reset that followed the crash.
+  pub reset: ResetFlags,
+  tid: Option<usize>,
+  task_name: [u8; CRASH_TASK_NAME_LEN],
+  task_name_len: usize,
+  message: [u8; CRASH_MESSAGE_LEN],
+  message_len: usize,
+  trace: [[u8; CRASH_TRACE_RECORD_SIZE]; CRASH_TRACE_LEN],
+  trace_len: usize,
+}
+
+impl Crash {
+  fn new(reason: CrashReason, reset: ResetFlags) -> Self {
+    Crash {
+      reason: reason,
+      registers: None,
+      reset: reset,
+      tid: None,
+      task_name: [0; CRASH_TASK_NAME_LEN],
+      task_name_len: 0,
+      message: [0; CRASH_MESSAGE_LEN],
+      message_len: 0,
+      trace: [[0; CRASH_TRACE_RECORD_SIZE]; CRASH_TRACE_LEN],
+      trace_len: 0,
+    }
+  }
+
+  /// Returns the tid and name of the task that crashed, if the crash happened in a task.
+  pub fn task(&self) -> Option<(usize, &str)> {
+    self.tid.map(|tid| (tid, as_str(&self.task_name, self.task_name_len)))
+  }
+
+  /// Returns the panic message, empty for a fault.
+  pub fn message(&self) -> &str {
+    as_str(&self.message, self.message_len)
+  }
+
+  /// Returns the last trace events before the crash, oldest first. Each is in the format read by
+  /// the `trace_decode` tool.
+  pub fn trace(&self) -> &[[u8; CRASH_TRACE_RECORD_SIZE]] {
+    &self.trace[..self.trace_len]
+  }
+}
+
+impl Clone for Crash {
+  fn clone(&self) -> Self {
+    *self
+  }
+}
+
+impl fmt::Debug for Crash {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    f.debug_struct("Crash")
+      .field("reason", &self.reason)
+      .field("task", &self.task())
+      .field("registers", &self.registers)
+      .field("message", &self.message())
+      .field("trace", &self.trace())
+      .field("reset", &self.reset)
+      .finish()
+  }
+}
+
+#[repr(C)]
+struct CrashRecord {
+  magic: usize,
+  checksum: usize,
+  reason: usize,
+  has_task: usize,
+  tid: usize,
+  task_name_len: usize,
+  task_name: [u8; CRASH_TASK_NAME_LEN],
+  has_registers: usize,
+  registers: [usize; 9],
+  message_len: usize,
+  message: [u8; CRASH_MESSAGE_LEN],
+  trace_len: usize,
+  trace: [[u8; CRASH_TRACE_RECORD_SIZE]; CRASH_TRACE_LEN],
+}
+
+// Kept in .noinit so it survives a reset, it's only valid if the magic and checksum match
+#[link_section = ".noinit"]
+static mut CRASH_RECORD: CrashRecord = CrashRecord {
+  magic: 0,
+  checksum: 0,
+  reason: 0,
+  has_task: 0,
+  tid: 0,
+  task_name_len: 0,
+  task_name: [0; CRASH_TASK_NAME_LEN],
+  has_registers: 0,
+  registers: [0; 9],
+  message_len: 0,
+  message: [0; CRASH_MESSAGE_LEN],
+  trace_len: 0,
+  trace: [[0; CRASH_TRACE_RECORD_SIZE]; CRASH_TRACE_LEN],
+};
+
+// Read from the RCC the first time they're needed, this is in .bss so it's reset every boot
+static mut RESET_FLAGS: Option<ResetFlags> = None;
+
+/// Returns the sources that caused the last reset.
+///
+/// The flags are read the first time this is called and kept for the rest of the boot, `clear`
+/// clears them.
+pub fn reset_flags() -> ResetFlags {
+  // UNSAFE: The flags are only written here and in `clear`, which are called during start up
+  unsafe {
+    if let Some(flags) = RESET_FLAGS {
+      return flags;
+    }
+    let rcc = rcc::rcc();
+    let flags = ResetFlags {
+      pin: rcc.reset_flag_is_set(ResetFlag::Pin),
+      power: rcc.reset_flag_is_set(ResetFlag::Power),
+      software: rcc.reset_flag_is_set(ResetFlag::Software),
+      watchdog: rcc.reset_flag_is_set(ResetFlag::IndependentWatchdog),
+      window_watchdog: rcc.reset_flag_is_set(ResetFlag::WindowWatchdog),
+      low_power: rcc.reset_flag_is_set(ResetFlag::LowPower),
+    };
+    RESET_FLAGS = Some(flags);
+    flags
+  }
+}
+
+/// Returns the crash that was saved before the last reset, if there is one.
+///
+/// If nothing was saved but the reset came from a watchdog, the reset pin or a low power reset the
+/// crash's reason is `Reset`. A plain power on isn't a crash.
+///
+/// The crash is kept until `clear` is called, so it will still be here after another reset.
+pub fn last_crash() -> Option<Crash> {
+  let reset = reset_flags();
+  // UNSAFE: The record is only written while crashing
+  unsafe {
+    let record = &CRASH_RECORD;
+    let reason = if record.magic == CRASH_MAGIC && record.checksum == checksum(record) {
+      CrashReason::from_code(record.reason)
+    }
+    else {
+      None
+    };
+    let reason = match reason {
+      Some(reason) => reason,
+      None if reset.unexpected() => return Some(Crash::new(CrashReason::Reset, reset)),
+      None => return None,
+    };
+    let mut crash = Crash::new(reason, reset);
+    if record.has_task != 0 {
+      crash.tid = Some(record.tid);
+      crash.task_name = record.task_name;
+      crash.task_name_len = ::core::cmp::min(record.task_name_len, CRASH_TASK_NAME_LEN);
+    }
+    if record.has_registers != 0 {
+      let registers = &record.registers;
+      crash.registers = Some(CrashRegisters {
+        frame: ExceptionFrame {
+          r0: registers[0],
+          r1: registers[1],
+          r2: registers[2],
+          r3: registers[3],
+          r12: registers[4],
+          lr: registers[5],
+          pc: registers[6],
+          xpsr: registers[7],
+        },
+        exc_return: registers[8],
+      });
+    }
+    crash.message = record.message;
+    crash.message_len = ::core::cmp::min(record.message_len, CRASH_MESSAGE_LEN);
+    crash.trace = record.trace;
+    crash.trace_len = ::core::cmp::min(record.trace_len, CRASH_TRACE_LEN);
+    Some(crash)
+  }
+}
+
+/// Throws away the saved crash and clears the reset flags.
+pub fn clear() {
+  rcc::rcc().clear_reset_flags();
+  // UNSAFE: The record is only written while crashing, and the flags during start up
+  unsafe {
+    CRASH_RECORD.magic = 0;
+    RESET_FLAGS = Some(ResetFlags::default());
+  }
+}
+
+/// Saves a panic to the crash log. This is called by the panic handler.
+#[doc(hidden)]
+pub fn save_panic(msg: fmt::Arguments, file: &'static str, line: usize, in_task: bool) {
+  // UNSAFE: Only called while crashing, with interrupts disabled
+  unsafe {
+    let record = begin(CrashReason::Panic, in_task);
+    let message_len = {
+      let mut writer = BufWriter { buf: &mut record.message, len: 0 };
+      // Running out of room isn't an error worth reporting, the message is just cut off
+      let _ = write!(writer, "{}:{}: {}", file, line, msg);
+      writer.len
+    };
+    record.message_len = message_len;
+    finish(record);
+  }
+}
+
+/// Saves a fault to the crash log. This is called by the HardFault handler.
+#[doc(hidden)]
+pub fn save_fault(report: &FaultReport) {
+  // UNSAFE: Only called while crashing, with interrupts disabled
+  unsafe {
+    let record = begin(CrashReason::HardFault, report.in_task());
+    let frame = &report.frame;
+    record.has_registers = 1;
+    record.registers = [frame.r0, frame.r1, frame.r2, frame.r3, frame.r12, frame.lr, frame.pc,
+                        frame.xpsr, report.exc_return];
+    finish(record);
+  }
+}
+
+/// Starts a new record, saving the running task if the crash happened in one.
+unsafe fn begin(reason: CrashReason, in_task: bool) -> &'static mut CrashRecord {
+  let record = &mut CRASH_RECORD;
+  // Invalidate the old record first, so a crash while saving doesn't leave a mix of the two
+  record.magic = 0;
+  record.reason = reason.code();
+  record.has_registers = 0;
+  record.message_len = 0;
+  record.has_task = 0;
+  record.task_name_len = 0;
+  if let (true, Some(task)) = (in_task, CURRENT_TASK.as_ref()) {
+    let name = task.name();
+    let len = truncate(name, CRASH_TASK_NAME_LEN);
+    record.task_name[..len].copy_from_slice(&name.as_bytes()[..len]);
+    record.task_name_len = len;
+    record.tid = task.tid();
+    record.has_task = 1;
+  }
+  record
+}
+
+/// Saves the last trace events and seals the record.
+unsafe fn finish(record: &mut CrashRecord) {
+  record.trace_len = 0;
+  #[cfg(feature="trace")]
+  {
+    let mut events = [trace::Record { timestamp: 0, event: trace::Event::None }; CRASH_TRACE_LEN];
+    let count = trace::latest(&mut events);
+    for (slot, event) in record.trace.iter_mut().zip(events[..count].iter()) {
+      *slot = event.to_bytes();
+    }
+    record.trace_len = count;
+  }
+  record.checksum = checksum(record);
+  record.magic = CRASH_MAGIC;
+}
+
+/// An FNV-1a hash of everything in the record after the checksum.
+fn checksum(record: &CrashRecord) -> usize {
+  let header_size = 2 * mem::size_of::<usize>();
+  // UNSAFE: The record is plain old data, so it can be looked at as bytes
+  let bytes = unsafe {
+    slice::from_raw_parts(record as *const _ as *const u8, mem::size_of::<CrashRecord>())
+  };
+  let mut hash: u32 = 0x811C_9DC5;
+  for byte in &bytes[header_size..] {
+    hash ^= *byte as u32;
+    hash = hash.wrapping_mul(0x0100_0193);
+  }
+  hash as usize
+}
+
+/// Returns the longest prefix of `s` that fits in `max` bytes without splitting a character.
+fn truncate(s: &str, max: usize) -> usize {
+  if s.len() <= max {
+    return s.len();
+  }
+  let mut len = max;
+  while !s.is_char_boundary(len) {
+    len -= 1;
+  }
+  len
+}
+
+fn as_str(buf: &[u8], len: usize) -> &str {
+  str::from_utf8(&buf[..len]).unwrap_or("<invalid crash record>")
+}
+
+/// Formats into a fixed buffer, cutting off anything that doesn't fit.
+struct BufWriter<'a> {
+  buf: &'a mut [u8],
+  len: usize,
+}
+
+impl<'a> Write for BufWriter<'a> {
+  fn write_str(&mut self, s: &str) -> fmt::Result {
+    let len = truncate(s, self.buf.len() - self.len);
+    self.buf[self.len..self.len + len].copy_from_slice(&s.as_bytes()[..len]);
+    self.len += len;
+    if len < s.len() {
+      Err(fmt::Error)
+    }
+    else {
+      Ok(())
+    }
+  }
+}
@@ -0,0 +1,163 @@
+// exceptions/fault.rs
+// AltOSRust
+//
+// Created by Daniel Seitz on 2/27/17
+
+//! HardFault reporting.
+//!
+//! When a HardFault happens the handler recovers the registers that the hardware stacked on entry
+//! to the exception and works out which task was running. The report is saved to the crash log,
+//! written to the console if there is one and handed to the fault sink, if one is set. Then the
+//! system is halted or reset depending on the fault policy.
+//!
+//! The sink runs inside the HardFault handler, so it can't block or use the kernel, and if it
+//! faults itself the processor locks up.
+
+use core::fmt;
+use arm::asm::bkpt;
+use altos_core::CURRENT_TASK;
+use crash;
+use console;
+use system_control;
+
+/// The registers the hardware pushes onto the stack on entry to an exception.
+#[repr(C)]
+#[derive(Debug, Copy, Clone)]
+pub struct ExceptionFrame {
+  pub r0: usize,
+  pub r1: usize,
+  pub r2: usize,
+  pub r3: usize,
+  pub r12: usize,
+  pub lr: usize,
+  pub pc: usize,
+  pub xpsr: usize,
+}
+
+/// The task that was running when a fault happened.
+#[derive(Debug, Copy, Clone)]
+pub struct FaultedTask {
+  pub tid: usize,
+  pub name: &'static str,
+}
+
+/// Everything known about a HardFault.
+#[derive(Debug, Copy, Clone)]
+pub struct FaultReport {
+  /// The registers at the time of the fault, `pc` is the faulting instruction.
+  pub frame: ExceptionFrame,
+  /// The EXC_RETURN value the handler was entered with.
+  pub exc_return: usize,
+  /// The task that was running, if the scheduler had been started.
+  pub task: Option<FaultedTask>,
+}
+
+impl FaultReport {
+  /// Returns true if the fault happened in a task, rather than in the kernel or an interrupt
+  /// handler.
+  pub fn in_task(&self) -> bool {
+    const THREAD_PSP: usize = 0b1 << 2;
+    self.exc_return & THREAD_PSP != 0
+  }
+}
+
+impl fmt::Display for FaultReport {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    let frame = &self.frame;
+    write!(f, "HardFault at pc={:#010x} ", frame.pc)?;
+    match self.task {
+      Some(task) if self.in_task() => writeln!(f, "in task {} ({})", task.tid, task.name)?,
+      Some(task) => writeln!(f, "in kernel, task {} ({}) was running", task.tid, task.name)?,
+      None => writeln!(f, "before the scheduler started")?,
+    }
+    writeln!(f, "  r0={:#010x} r1={:#010x} r2={:#010x} r3={:#010x}",
+             frame.r0, frame.r1, frame.r2, frame.r3)?;
+    writeln!(f, "  r12={:#010x} lr={:#010x} xpsr={:#010x}", frame.r12, frame.lr, frame.xpsr)
+  }
+}
+
+/// What to do once a fault has been reported.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum FaultPolicy {
+  /// Stop at a breakpoint so a debugger can look around, this is the default. Without a debugger
+  /// attached the processor locks up instead, which also stops it.
+  Halt,
+  /// Reset the system.
+  Reset,
+}
+
+static mut FAULT_SINK: Option<fn(&FaultReport)> = None;
+static mut FAULT_POLICY: FaultPolicy = FaultPolicy::Halt;
+
+/// Sets a function to be called with the report whenever a HardFault happens.
+///
+/// The report has already been saved to the crash log and written to the console by then.
+pub fn set_fault_sink(sink: fn(&FaultReport)) {
+  // UNSAFE: Function pointers are written in a single store
+  unsafe { FAULT_SINK = Some(sink) };
+}
+
+/// Sets what happens once a HardFault has been reported.
+pub fn set_fault_policy(policy: FaultPolicy) {
+  // UNSAFE: The policy is a single byte, it's written in a single store
+  unsafe { FAULT_POLICY = policy };
+}
+
+/// The HardFault handler, finds the exception frame and passes it on to `hard_fault_entry`.
+#[naked]
+pub fn hard_fault_handler() {
+  unsafe {
+    #[cfg(target_arch="arm")]
+    asm!(
+      concat!(
+        "movs r0, #4\n", /* bit 2 of EXC_RETURN tells us which stack the frame is on */
+        "mov r1, lr\n", /* pass EXC_RETURN along too */
+        "tst r0, r1\n",
+        "beq fault_msp\n",
+        "mrs r0, psp\n", /* the fault happened in a task */
+        "b fault_report\n",
+      "fault_msp:\n",
+        "mrs r0, msp\n", /* the fault happened in the kernel */
+      "fault_report:\n",
+        "ldr r2, fault_entry_const\n",
+        "bx r2\n",
+
+         ".align 4\n",
+        "fault_entry_const: .word hard_fault_entry\n")
+    : /* no outputs */
+    : /* no inputs */
+    : /* no clobbers */
+    : "volatile");
+  }
+}
+
+/// Reports the fault described by the exception frame, then halts or resets.
+#[no_mangle]
+pub extern "C" fn hard_fault_entry(frame: *const ExceptionFrame, exc_return: usize) -> ! {
+  // UNSAFE: The frame was pushed by the hardware on entry to the exception. CURRENT_TASK might be
+  // the reason we faulted, but the fault is already as bad as it gets
+  let report = unsafe {
+    FaultReport {
+      frame: *frame,
+      exc_return: exc_return,
+      task: CURRENT_TASK.as_ref().map(|task| FaultedTask { tid: task.tid(), name: task.name() }),
+    }
+  };
+
+  crash::save_fault(&report);
+  console::emergency_write(format_args!("{}", report));
+
+  // UNSAFE: The sink and policy are only ever written during initialization
+  unsafe {
+    if let Some(sink) = FAULT_SINK {
+      sink(&report);
+    }
+    match FAULT_POLICY {
+      FaultPolicy::Halt => {
+        bkpt();
+        loop {}
+      },
+      FaultPolicy::Reset => system_control::scb().system_reset(),
+    }
+  }
+}
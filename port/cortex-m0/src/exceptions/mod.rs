@@ -4,15 +4,29 @@
 // Created by Daniel Seitz on 11/30/16
 
 use arm::asm::bkpt;
-use altos_core::syscall;
+use altos_core::syscall::{self, svc};
 use time;
+#[cfg(feature="trace")]
+use altos_core::trace;
+
+pub mod fault;
+
+use self::fault::hard_fault_handler;
+
+/// The exception number of the SVCall exception, used to identify it in the trace.
+#[cfg(feature="trace")]
+const SVCALL_EXCEPTION: usize = 11;
+
+/// The exception number of the SysTick exception, used to identify it in the trace.
+#[cfg(feature="trace")]
+const SYSTICK_EXCEPTION: usize = 15;
 
 #[link_section = ".exceptions"]
 #[cfg(not(test))]
 #[cfg(target_arch="arm")]
 #[no_mangle]
 pub static EXCEPTIONS: [Option<fn()>; 14] = [Some(default_handler),  // NMI
-                                              Some(default_handler),  // Hard Fault
+                                              Some(hard_fault_handler), // Hard Fault
                                               Some(default_handler),  // Memory Management Fault
                                               Some(default_handler),  // Bus Fault
                                               Some(default_handler),  // Usage Fault
@@ -20,7 +34,7 @@ pub static EXCEPTIONS: [Option<fn()>; 14] = [Some(default_handler),  // NMI
                                               None,                   // Reserved
                                               None,                   // Reserved
                                               None,                   // Reserved
-                                              Some(default_handler),  // SVCall
+                                              Some(svc_handler),      // SVCall
                                               None,                   // Reserved for Debug
                                               None,                   // Reserved
                                               Some(pend_sv_handler),  // PendSV
@@ -33,8 +47,58 @@ fn default_handler() {
 }
 
 fn systick_handler() {
+  #[cfg(feature="trace")]
+  trace::isr_enter(SYSTICK_EXCEPTION);
   syscall::system_tick();
   time::system_tick();
+  #[cfg(feature="trace")]
+  trace::isr_exit(SYSTICK_EXCEPTION);
+}
+
+/// Enter the kernel from a task's `svc` instruction.
+///
+/// The syscall number and arguments were in r0-r3 when the task trapped, so they're read back out
+/// of the exception frame on whichever stack the task was using.
+#[naked]
+fn svc_handler() {
+  unsafe {
+    #[cfg(target_arch="arm")]
+    asm!(
+      concat!(
+        "movs r0, #4\n", /* bit 2 of EXC_RETURN tells us which stack the frame is on */
+        "mov r1, lr\n",
+        "tst r0, r1\n",
+        "beq svc_msp\n",
+        "mrs r0, psp\n", /* called from a task */
+        "b svc_dispatch\n",
+      "svc_msp:\n",
+        "mrs r0, msp\n", /* called from the kernel */
+      "svc_dispatch:\n",
+        "ldr r1, svc_entry_const\n",
+        "bx r1\n", /* lr still holds EXC_RETURN, so svc_entry returns from the exception */
+
+         ".align 4\n",
+        "svc_entry_const: .word svc_entry\n")
+    : /* no outputs */
+    : /* no inputs */
+    : /* no clobbers */
+    : "volatile");
+  }
+}
+
+/// Dispatches the syscall in the exception frame and stores its result over the stacked r0.
+#[no_mangle]
+pub extern "C" fn svc_entry(frame: *mut usize) {
+  #[cfg(feature="trace")]
+  trace::isr_enter(SVCALL_EXCEPTION);
+  // UNSAFE: The frame was pushed by the hardware on entry to the exception, r0-r3 are at the
+  // bottom of it
+  unsafe {
+    let args = [*frame.offset(1), *frame.offset(2), *frame.offset(3)];
+    *frame = svc::dispatch(*frame, args);
+  }
+  #[cfg(feature="trace")]
+  trace::isr_exit(SVCALL_EXCEPTION);
 }
 
 /// Tell OS to context switch tasks, this should be set to the lowest priority so that all other
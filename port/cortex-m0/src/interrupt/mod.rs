@@ -4,12 +4,51 @@
 // Created by Daniel Seitz on 11/30/16
 
 use peripheral::Control;
+use peripheral::serial;
 use volatile::Volatile;
 
 mod enable;
 mod pending;
 mod priority;
 
+/// The handlers for the external interrupts, in order of interrupt number.
+#[link_section = ".interrupts"]
+#[cfg(not(test))]
+#[cfg(target_arch="arm")]
+#[no_mangle]
+pub static INTERRUPTS: [Option<fn()>; 32] = [None,                            // 0: WWDG
+                                              None,                            // 1: PVD_VDDIO2
+                                              None,                            // 2: RTC
+                                              None,                            // 3: FLASH
+                                              None,                            // 4: RCC_CRS
+                                              None,                            // 5: EXTI0_1
+                                              None,                            // 6: EXTI2_3
+                                              None,                            // 7: EXTI4_15
+                                              None,                            // 8: TSC
+                                              None,                            // 9: DMA_CH1
+                                              None,                            // 10: DMA_CH2_3
+                                              None,                            // 11: DMA_CH4_7
+                                              None,                            // 12: ADC_COMP
+                                              None,                            // 13: TIM1_BRK_UP
+                                              None,                            // 14: TIM1_CC
+                                              None,                            // 15: TIM2
+                                              None,                            // 16: TIM3
+                                              None,                            // 17: TIM6_DAC
+                                              None,                            // 18: TIM7
+                                              None,                            // 19: TIM14
+                                              None,                            // 20: TIM15
+                                              None,                            // 21: TIM16
+                                              None,                            // 22: TIM17
+                                              None,                            // 23: I2C1
+                                              None,                            // 24: I2C2
+                                              None,                            // 25: SPI1
+                                              None,                            // 26: SPI2
+                                              Some(serial::usart1_handler),    // 27: USART1
+                                              Some(serial::usart2_handler),    // 28: USART2
+                                              None,                            // 29: USART3_8
+                                              None,                            // 30: CEC_CAN
+                                              None];                           // 31: USB
+
 #[derive(Copy, Clone)]
 pub struct NVIC {
   mem_addr: u32,
@@ -10,37 +10,53 @@
 #![feature(drop_types_in_const)] // Probably can come back and remove this later
 #![allow(dead_code)]
 #![feature(linkage)]
+#![feature(stmt_expr_attributes)]
+#![feature(macro_reexport)]
 //#![feature(compiler_builtins_lib)] // Keep this around in case we want to try and get it working
 #![no_std]
 
+#[macro_use]
+#[macro_reexport(log, error, warn, info, debug, trace)]
 extern crate altos_core;
 
 pub extern crate arm;
 //pub extern crate compiler_builtins; // See above comment
 
+mod arch;
+#[macro_use]
+pub mod console;
 mod exceptions;
 pub mod peripheral;
 pub mod time;
+pub mod panic;
+pub mod crash;
+pub mod semihosting;
 mod interrupt;
 mod system_control;
 
 use peripheral::gpio;
 use peripheral::rcc;
-use peripheral::systick;
 
 #[cfg(target_arch="arm")]
 pub use vector_table::RESET;
 #[cfg(target_arch="arm")]
 pub use exceptions::EXCEPTIONS;
+#[cfg(target_arch="arm")]
+pub use interrupt::INTERRUPTS;
+pub use exceptions::fault;
 
 use altos_core::volatile;
 
 pub mod kernel {
   pub use altos_core::syscall;
+  pub use altos_core::log;
+  pub use console;
 
   pub mod task {
     pub use altos_core::args;
-    pub use altos_core::TaskHandle;
+    pub use altos_core::local;
+    pub use altos_core::{TaskHandle, HandleError};
+    pub use altos_core::StaticTask;
     pub use altos_core::{start_scheduler};
     pub use altos_core::{Priority};
   }
@@ -48,14 +64,29 @@ pub mod kernel {
   // TODO: Do we want to expose an allocation interface?
   pub mod alloc {
     pub use altos_core::alloc::boxed::Box;
+    pub use altos_core::pool::{Pool, PoolBox};
+    pub use altos_core::heap;
   }
 
   pub mod collections {
     // TODO: Do we want to expose an allocation interface?
     pub use altos_core::collections::Vec;
-    pub use altos_core::queue::{SortedList, Queue, Node};
+    pub use altos_core::queue::{SortedList, Queue, Node, NodePtr};
+  }
+
+  pub mod sched {
+    pub use altos_core::sched::{lock, unlock, is_locked, SchedulerGuard};
+    pub use altos_core::sched::{set_time_slice, DEFAULT_TIME_SLICE};
+    pub use altos_core::sched::set_idle_hook;
+    #[cfg(feature="edf")]
+    pub use altos_core::sched::{set_deadline_miss_hook, Deadline, DeadlineMiss};
   }
 
+  #[cfg(feature="trace")]
+  pub use altos_core::trace;
+  #[cfg(feature="watchdog")]
+  pub use altos_core::watchdog;
+
   pub mod sync {
     pub use altos_core::sync::{Mutex, MutexGuard};
     pub use altos_core::sync::CondVar;
@@ -67,12 +98,8 @@ pub mod kernel {
 #[lang = "eh_personality"] extern "C" fn eh_personality() {}
 #[cfg(not(test))]
 #[lang = "panic_fmt"] 
-extern "C" fn panic_fmt(_fmt: core::fmt::Arguments, _file: &'static str, _line: usize) -> ! {
-  loop {
-    unsafe {
-      arm::asm::bkpt();
-    }
-  }
+extern "C" fn panic_fmt(fmt: core::fmt::Arguments, file: &'static str, line: usize) -> ! {
+  panic::handle(fmt, file, line)
 }
 
 extern {
@@ -89,7 +116,6 @@ pub fn init() -> ! {
   init_heap();
   init_led();
   init_clock();
-  init_ticks();
 
   unsafe { application_entry() };
 }
@@ -203,13 +229,3 @@ fn init_clock() {
   // Our system clock sets itself to interrupt every 1 ms
   time::set_resolution(1);
 }
-
-fn init_ticks() {
-  let systick = systick::systick();
-
-  systick.use_processor_clock();
-  systick.clear_current_value();
-  systick.enable_counter();
-  systick.enable_interrupts();
-
-}
@@ -0,0 +1,88 @@
+// panic.rs
+// AltOSRust
+//
+// Created by Daniel Seitz on 2/28/17
+
+//! Panic handling.
+//!
+//! When something panics the message is saved to the crash log along with the name of the task
+//! that was running, so it can still be read after the system resets. It's also written out to the
+//! console, if there is one. What happens after that is up to the panic policy, the system can
+//! halt, reset, or destroy just the task that panicked and keep running everything else.
+
+use core::fmt;
+use arm;
+use altos_core::{arch, sched, syscall, CURRENT_TASK};
+use crash;
+use console;
+use system_control;
+
+/// What to do once a panic has been recorded.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum PanicPolicy {
+  /// Stop at a breakpoint so a debugger can look around, this is the default.
+  Halt,
+  /// Reset the system.
+  Reset,
+  /// Destroy the task that panicked and keep running the rest of the system.
+  ///
+  /// If the panic didn't happen in a task the system halts instead. Syscalls check how they're
+  /// being used before trapping into the kernel, so misusing one panics in the task and only kills
+  /// that task.
+  ///
+  /// The task is destroyed without unwinding, so its guards are never dropped. The system halts
+  /// as well if the task is holding a `Mutex` or `SpinMutex` (like the console's or the log
+  /// buffer's), since nothing could ever take that lock again. A scheduler lock held by the task is
+  /// the exception, it's released when the task is destroyed.
+  KillTask,
+}
+
+static mut PANIC_POLICY: PanicPolicy = PanicPolicy::Halt;
+static mut PANICKING: bool = false;
+
+/// Sets what happens once a panic has been recorded.
+pub fn set_panic_policy(policy: PanicPolicy) {
+  // UNSAFE: The policy is a single byte, it's written in a single store
+  unsafe { PANIC_POLICY = policy };
+}
+
+/// Records the panic and then carries out the panic policy.
+pub fn handle(msg: fmt::Arguments, file: &'static str, line: usize) -> ! {
+  unsafe {
+    arm::asm::disable_interrupts();
+    // UNSAFE: Interrupts are disabled, nothing else can be touching these
+    if PANICKING {
+      // Panicked while handling a panic, don't make it worse
+      halt();
+    }
+    PANICKING = true;
+
+    let in_task = !arch::in_kernel_mode() && CURRENT_TASK.is_some();
+    crash::save_panic(msg, file, line, in_task);
+    console::emergency_write(format_args!("panicked at {}:{}: {}\r\n", file, line, msg));
+
+    match PANIC_POLICY {
+      PanicPolicy::Halt => halt(),
+      PanicPolicy::Reset => system_control::scb().system_reset(),
+      PanicPolicy::KillTask => {
+        if !in_task || CURRENT_TASK.as_ref().map_or(false, |task| task.held_locks() != 0) {
+          halt();
+        }
+        // Interrupt handlers release their scheduler locks before returning, so any lock that's
+        // still held belongs to the task
+        sched::force_unlock();
+        // The task never leaves its critical sections, so let the switch away from it happen
+        PANICKING = false;
+        arm::asm::enable_interrupts();
+        syscall::exit();
+      },
+    }
+  }
+}
+
+fn halt() -> ! {
+  loop {
+    // UNSAFE: Just a breakpoint
+    unsafe { arm::asm::bkpt() };
+  }
+}
@@ -6,15 +6,17 @@
 //! This module controls the RCC (Reset and Clock Controller), it handles enabling and disabling
 //! clocks, setting clock configurations and the reset flags that are set on a reset.
 
-use super::Control;
+use super::{Control, Register};
 use arm::asm::dsb;
 use volatile::Volatile;
 pub use self::clock_control::Clock;
 pub use self::enable::Peripheral;
+pub use self::reset_status::ResetFlag;
 
 mod clock_control;
 mod config;
 mod enable;
+mod reset_status;
 
 pub fn rcc() -> RCC {
   RCC::rcc()
@@ -27,6 +29,7 @@ pub struct RCC {
   cr: clock_control::ClockControl,
   cfgr: config::ConfigControl,
   enr: enable::PeripheralControl,
+  csr: reset_status::CSR,
 }
 
 impl Control for RCC {
@@ -43,6 +46,7 @@ impl RCC {
       cr: clock_control::ClockControl::new(RCC_ADDR),
       cfgr: config::ConfigControl::new(RCC_ADDR),
       enr: enable::PeripheralControl::new(RCC_ADDR),
+      csr: reset_status::CSR::new(RCC_ADDR),
     }
   }
 
@@ -133,5 +137,15 @@ impl RCC {
   pub fn peripheral_is_enabled(&self, peripheral: Peripheral) -> bool {
     self.enr.peripheral_is_enabled(peripheral)
   }
+
+  /// Return true if the specified source caused the last reset
+  pub fn reset_flag_is_set(&self, flag: ResetFlag) -> bool {
+    self.csr.get_reset_flag(flag)
+  }
+
+  /// Clear the reset flags, otherwise they are kept through any reset other than a power reset
+  pub fn clear_reset_flags(&self) {
+    self.csr.clear_reset_flags();
+  }
 }
 
@@ -0,0 +1,77 @@
+// peripheral/rcc/reset_status.rs
+// AltOSRust
+//
+// Created by Daniel Seitz on 3/1/17
+
+//! This module is used to control the CSR (control/status register), which holds the flags that
+//! record what caused the last reset.
+
+use super::super::{Register, Field};
+
+/// A source of reset, the hardware sets the flag for each one that caused the last reset.
+#[derive(Copy, Clone)]
+pub enum ResetFlag {
+  LowPower,
+  WindowWatchdog,
+  IndependentWatchdog,
+  Software,
+  /// A power on, power down or brown out reset, the hardware doesn't tell them apart.
+  Power,
+  Pin,
+  OptionByte,
+}
+
+impl Field for ResetFlag {
+  fn mask(&self) -> u32 {
+    match *self {
+      ResetFlag::LowPower => 0b1 << 31,
+      ResetFlag::WindowWatchdog => 0b1 << 30,
+      ResetFlag::IndependentWatchdog => 0b1 << 29,
+      ResetFlag::Software => 0b1 << 28,
+      ResetFlag::Power => 0b1 << 27,
+      ResetFlag::Pin => 0b1 << 26,
+      ResetFlag::OptionByte => 0b1 << 25,
+    }
+  }
+}
+
+#[derive(Copy, Clone)]
+pub struct CSR {
+  base_addr: u32,
+}
+
+impl Register for CSR {
+  fn new(base_addr: u32) -> Self {
+    CSR { base_addr: base_addr }
+  }
+
+  fn base_addr(&self) -> u32 {
+    self.base_addr
+  }
+
+  fn mem_offset(&self) -> u32 {
+    0x24
+  }
+}
+
+impl CSR {
+  pub fn get_reset_flag(&self, flag: ResetFlag) -> bool {
+    let mask = flag.mask();
+
+    unsafe {
+      let reg = self.addr();
+
+      *reg & mask != 0
+    }
+  }
+
+  /// Clears every reset flag, they stay cleared until the next reset.
+  pub fn clear_reset_flags(&self) {
+    const RMVF: u32 = 0b1 << 24;
+
+    unsafe {
+      let mut reg = self.addr();
+      *reg |= RMVF;
+    }
+  }
+}
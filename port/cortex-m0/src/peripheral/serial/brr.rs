@@ -0,0 +1,71 @@
+// peripheral/serial/brr.rs
+// AltOSRust
+//
+// Created by Daniel Seitz on 3/2/17
+
+use super::super::Register;
+
+/// The baud rate register, divides the peripheral clock down to the baud rate.
+#[derive(Copy, Clone)]
+pub struct BRR {
+  base_addr: u32,
+}
+
+impl Register for BRR {
+  fn new(base_addr: u32) -> Self {
+    BRR { base_addr: base_addr }
+  }
+
+  fn base_addr(&self) -> u32 {
+    self.base_addr
+  }
+
+  fn mem_offset(&self) -> u32 {
+    0x0C
+  }
+}
+
+impl BRR {
+  /// Sets the divider for `baud_rate` from a peripheral clock running at `clock_rate`, this can
+  /// only be changed while the USART is disabled.
+  pub fn set_baud_rate(&self, baud_rate: u32, clock_rate: u32) {
+    unsafe {
+      let mut reg = self.addr();
+      reg.store(divider(baud_rate, clock_rate));
+    }
+  }
+}
+
+/// Calculates the baud rate divider with 16x oversampling, rounded to the nearest rate.
+///
+/// # Panics
+///
+/// This function will panic if the baud rate is 0, or too fast for the clock.
+pub fn divider(baud_rate: u32, clock_rate: u32) -> u32 {
+  if baud_rate == 0 {
+    panic!("divider - baud rate must be greater than 0!");
+  }
+  let divider = (clock_rate + baud_rate / 2) / baud_rate;
+  if divider < 16 {
+    panic!("divider - baud rate is too fast for the clock!");
+  }
+  divider
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn divider_for_common_rates() {
+    assert_eq!(divider(9600, 48_000_000), 5000);
+    assert_eq!(divider(115_200, 48_000_000), 417);
+    assert_eq!(divider(115_200, 8_000_000), 69);
+  }
+
+  #[test]
+  #[should_panic]
+  fn divider_too_fast() {
+    divider(4_000_000, 48_000_000);
+  }
+}
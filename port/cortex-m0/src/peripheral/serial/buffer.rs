@@ -0,0 +1,87 @@
+// peripheral/serial/buffer.rs
+// AltOSRust
+//
+// Created by Daniel Seitz on 3/2/17
+
+/// The number of bytes each direction of a USART can buffer.
+pub const SERIAL_BUFFER_LEN: usize = 64;
+
+/// A fixed size FIFO of bytes, shared between a task and the USART's interrupt handler.
+pub struct RingBuffer {
+  data: [u8; SERIAL_BUFFER_LEN],
+  head: usize,
+  len: usize,
+}
+
+impl RingBuffer {
+  pub const fn new() -> Self {
+    RingBuffer {
+      data: [0; SERIAL_BUFFER_LEN],
+      head: 0,
+      len: 0,
+    }
+  }
+
+  /// Adds a byte to the back of the buffer, returning false if the buffer is full.
+  pub fn push(&mut self, byte: u8) -> bool {
+    if self.is_full() {
+      return false;
+    }
+    let tail = (self.head + self.len) % SERIAL_BUFFER_LEN;
+    self.data[tail] = byte;
+    self.len += 1;
+    true
+  }
+
+  /// Removes the byte at the front of the buffer.
+  pub fn pop(&mut self) -> Option<u8> {
+    if self.is_empty() {
+      return None;
+    }
+    let byte = self.data[self.head];
+    self.head = (self.head + 1) % SERIAL_BUFFER_LEN;
+    self.len -= 1;
+    Some(byte)
+  }
+
+  pub fn is_empty(&self) -> bool {
+    self.len == 0
+  }
+
+  pub fn is_full(&self) -> bool {
+    self.len == SERIAL_BUFFER_LEN
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn fifo_order() {
+    let mut buffer = RingBuffer::new();
+    assert!(buffer.push(1));
+    assert!(buffer.push(2));
+    assert_eq!(buffer.pop(), Some(1));
+    assert_eq!(buffer.pop(), Some(2));
+    assert_eq!(buffer.pop(), None);
+  }
+
+  #[test]
+  fn full_buffer_wraps() {
+    let mut buffer = RingBuffer::new();
+    for i in 0..SERIAL_BUFFER_LEN {
+      assert!(buffer.push(i as u8));
+    }
+    assert!(buffer.is_full());
+    assert!(!buffer.push(0xFF));
+
+    assert_eq!(buffer.pop(), Some(0));
+    assert!(buffer.push(0xFF));
+    for i in 1..SERIAL_BUFFER_LEN {
+      assert_eq!(buffer.pop(), Some(i as u8));
+    }
+    assert_eq!(buffer.pop(), Some(0xFF));
+    assert!(buffer.is_empty());
+  }
+}
@@ -0,0 +1,81 @@
+// peripheral/serial/cr1.rs
+// AltOSRust
+//
+// Created by Daniel Seitz on 3/2/17
+
+use super::super::Register;
+
+/// Control register 1, enables the USART, the transmitter and receiver, their interrupts and
+/// sets the frame format.
+#[derive(Copy, Clone)]
+pub struct CR1 {
+  base_addr: u32,
+}
+
+impl Register for CR1 {
+  fn new(base_addr: u32) -> Self {
+    CR1 { base_addr: base_addr }
+  }
+
+  fn base_addr(&self) -> u32 {
+    self.base_addr
+  }
+
+  fn mem_offset(&self) -> u32 {
+    0x00
+  }
+}
+
+impl CR1 {
+  pub fn set_enable(&self, enable: bool) {
+    self.set_bit(0b1 << 0, enable);
+  }
+
+  pub fn set_receiver_enable(&self, enable: bool) {
+    self.set_bit(0b1 << 2, enable);
+  }
+
+  pub fn set_transmitter_enable(&self, enable: bool) {
+    self.set_bit(0b1 << 3, enable);
+  }
+
+  pub fn set_rx_interrupt(&self, enable: bool) {
+    self.set_bit(0b1 << 5, enable);
+  }
+
+  pub fn set_tx_interrupt(&self, enable: bool) {
+    self.set_bit(0b1 << 7, enable);
+  }
+
+  pub fn tx_interrupt_enabled(&self) -> bool {
+    unsafe {
+      let reg = self.addr();
+      *reg & (0b1 << 7) != 0
+    }
+  }
+
+  /// Turns parity on or off, when `odd` is false the parity is even.
+  ///
+  /// The parity bit takes the place of the most significant data bit, so the word length is
+  /// raised to 9 bits when parity is on to keep 8 data bits.
+  pub fn set_parity(&self, enable: bool, odd: bool) {
+    const M0: u32 = 0b1 << 12;
+    const PCE: u32 = 0b1 << 10;
+    const PS: u32 = 0b1 << 9;
+    self.set_bit(M0, enable);
+    self.set_bit(PCE, enable);
+    self.set_bit(PS, odd);
+  }
+
+  fn set_bit(&self, mask: u32, set: bool) {
+    unsafe {
+      let mut reg = self.addr();
+      if set {
+        *reg |= mask;
+      }
+      else {
+        *reg &= !mask;
+      }
+    }
+  }
+}
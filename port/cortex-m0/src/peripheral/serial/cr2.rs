@@ -0,0 +1,53 @@
+// peripheral/serial/cr2.rs
+// AltOSRust
+//
+// Created by Daniel Seitz on 3/2/17
+
+use super::super::{Register, Field};
+
+/// The number of stop bits at the end of each frame.
+#[derive(Copy, Clone, PartialEq)]
+pub enum StopBits {
+  One,
+  Two,
+}
+
+impl Field for StopBits {
+  fn mask(&self) -> u32 {
+    match *self {
+      StopBits::One => 0b00 << 12,
+      StopBits::Two => 0b10 << 12,
+    }
+  }
+}
+
+/// Control register 2, sets the number of stop bits.
+#[derive(Copy, Clone)]
+pub struct CR2 {
+  base_addr: u32,
+}
+
+impl Register for CR2 {
+  fn new(base_addr: u32) -> Self {
+    CR2 { base_addr: base_addr }
+  }
+
+  fn base_addr(&self) -> u32 {
+    self.base_addr
+  }
+
+  fn mem_offset(&self) -> u32 {
+    0x04
+  }
+}
+
+impl CR2 {
+  pub fn set_stop_bits(&self, stop_bits: StopBits) {
+    const STOP_MASK: u32 = 0b11 << 12;
+    unsafe {
+      let mut reg = self.addr();
+      *reg &= !STOP_MASK;
+      *reg |= stop_bits.mask();
+    }
+  }
+}
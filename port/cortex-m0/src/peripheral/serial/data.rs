@@ -0,0 +1,66 @@
+// peripheral/serial/data.rs
+// AltOSRust
+//
+// Created by Daniel Seitz on 3/2/17
+
+use super::super::Register;
+
+/// The receive data register.
+#[derive(Copy, Clone)]
+pub struct RDR {
+  base_addr: u32,
+}
+
+impl Register for RDR {
+  fn new(base_addr: u32) -> Self {
+    RDR { base_addr: base_addr }
+  }
+
+  fn base_addr(&self) -> u32 {
+    self.base_addr
+  }
+
+  fn mem_offset(&self) -> u32 {
+    0x24
+  }
+}
+
+impl RDR {
+  /// Reads the received byte, this clears the RXNE flag.
+  pub fn read(&self) -> u8 {
+    unsafe {
+      let reg = self.addr();
+      (*reg & 0xFF) as u8
+    }
+  }
+}
+
+/// The transmit data register.
+#[derive(Copy, Clone)]
+pub struct TDR {
+  base_addr: u32,
+}
+
+impl Register for TDR {
+  fn new(base_addr: u32) -> Self {
+    TDR { base_addr: base_addr }
+  }
+
+  fn base_addr(&self) -> u32 {
+    self.base_addr
+  }
+
+  fn mem_offset(&self) -> u32 {
+    0x28
+  }
+}
+
+impl TDR {
+  /// Queues a byte to be sent, this clears the TXE flag.
+  pub fn write(&self, byte: u8) {
+    unsafe {
+      let mut reg = self.addr();
+      reg.store(byte as u32);
+    }
+  }
+}
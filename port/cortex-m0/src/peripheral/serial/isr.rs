@@ -0,0 +1,93 @@
+// peripheral/serial/isr.rs
+// AltOSRust
+//
+// Created by Daniel Seitz on 3/2/17
+
+use super::super::Register;
+
+const PE: u32 = 0b1 << 0;
+const FE: u32 = 0b1 << 1;
+const NF: u32 = 0b1 << 2;
+const ORE: u32 = 0b1 << 3;
+const RXNE: u32 = 0b1 << 5;
+const TC: u32 = 0b1 << 6;
+const TXE: u32 = 0b1 << 7;
+
+/// The interrupt and status register.
+#[derive(Copy, Clone)]
+pub struct ISR {
+  base_addr: u32,
+}
+
+impl Register for ISR {
+  fn new(base_addr: u32) -> Self {
+    ISR { base_addr: base_addr }
+  }
+
+  fn base_addr(&self) -> u32 {
+    self.base_addr
+  }
+
+  fn mem_offset(&self) -> u32 {
+    0x1C
+  }
+}
+
+impl ISR {
+  /// Returns true if a received byte is waiting in the receive data register.
+  pub fn rx_not_empty(&self) -> bool {
+    self.is_set(RXNE)
+  }
+
+  /// Returns true if the transmit data register can take another byte.
+  pub fn tx_empty(&self) -> bool {
+    self.is_set(TXE)
+  }
+
+  /// Returns true if the last byte has been completely sent.
+  pub fn tx_complete(&self) -> bool {
+    self.is_set(TC)
+  }
+
+  /// Returns true if there was a parity, framing, noise or overrun error.
+  pub fn has_error(&self) -> bool {
+    self.is_set(PE | FE | NF | ORE)
+  }
+
+  fn is_set(&self, mask: u32) -> bool {
+    unsafe {
+      let reg = self.addr();
+      *reg & mask != 0
+    }
+  }
+}
+
+/// The interrupt flag clear register.
+#[derive(Copy, Clone)]
+pub struct ICR {
+  base_addr: u32,
+}
+
+impl Register for ICR {
+  fn new(base_addr: u32) -> Self {
+    ICR { base_addr: base_addr }
+  }
+
+  fn base_addr(&self) -> u32 {
+    self.base_addr
+  }
+
+  fn mem_offset(&self) -> u32 {
+    0x20
+  }
+}
+
+impl ICR {
+  /// Clears the parity, framing, noise and overrun error flags.
+  pub fn clear_errors(&self) {
+    unsafe {
+      let mut reg = self.addr();
+      reg.store(PE | FE | NF | ORE);
+    }
+  }
+}
@@ -3,33 +3,313 @@
 //
 // Created by Daniel Seitz on 11/30/16
 
-use super::Control;
-use volatile::Volatile;
-use peripheral::{gpio, rcc};
+//! This module is a driver for the USART1 and USART2 serial ports.
+//!
+//! Both directions are buffered in RAM and serviced by the USART's interrupt. A task that writes
+//! while the transmit buffer is full, or reads while nothing has been received, is put to sleep
+//! until the interrupt handler makes room or receives something.
+//!
+//! USART1 uses PA9 (TX) and PA10 (RX), USART2 uses PA2 (TX) and PA3 (RX).
+//!
+//! # Examples
+//!
+//! ```rust,no_run
+//! use cortex_m0::peripheral::serial::{Serial, Usart, Config};
+//!
+//! let serial = Serial::open(Usart::Usart1, Config::new(115_200));
+//! serial.write(b"hello\r\n");
+//! ```
 
-fn init() {
-  let rcc = rcc::rcc();
+use super::Register;
+use super::{gpio, rcc};
+use interrupt::NVIC;
+use altos_core::{arch, syscall};
+use altos_core::sync::{SpinMutex, CriticalSection};
+#[cfg(feature="trace")]
+use altos_core::trace;
+use self::buffer::RingBuffer;
+pub use self::cr2::StopBits;
+pub use self::buffer::SERIAL_BUFFER_LEN;
 
-  gpio::GPIO::enable(gpio::GPIOGroup::A);
-  rcc.enable_peripheral(rcc::Peripheral::USART1);
+mod cr1;
+mod cr2;
+mod brr;
+mod isr;
+mod data;
+mod buffer;
 
-  let pa9 = gpio::GPIOPort::new(9, gpio::GPIOGroup::A);
-  let pa10 = gpio::GPIOPort::new(10, gpio::GPIOGroup::A);
+/// One of the USART peripherals.
+#[derive(Copy, Clone, PartialEq)]
+pub enum Usart {
+  Usart1,
+  Usart2,
+}
+
+impl Usart {
+  fn base_addr(&self) -> u32 {
+    match *self {
+      Usart::Usart1 => 0x4001_3800,
+      Usart::Usart2 => 0x4000_4400,
+    }
+  }
+
+  /// The USART's interrupt number in the NVIC.
+  fn interrupt(&self) -> u8 {
+    match *self {
+      Usart::Usart1 => 27,
+      Usart::Usart2 => 28,
+    }
+  }
+
+  fn buffers(&self) -> &'static SpinMutex<Buffers> {
+    match *self {
+      Usart::Usart1 => &USART1_BUFFERS,
+      Usart::Usart2 => &USART2_BUFFERS,
+    }
+  }
+}
+
+/// The parity bit sent with each byte.
+#[derive(Copy, Clone, PartialEq)]
+pub enum Parity {
+  None,
+  Even,
+  Odd,
+}
+
+/// The frame format and speed of a serial port.
+#[derive(Copy, Clone)]
+pub struct Config {
+  pub baud_rate: u32,
+  pub parity: Parity,
+  pub stop_bits: StopBits,
+}
+
+impl Config {
+  /// A configuration for `baud_rate` with 8 data bits, no parity and one stop bit.
+  pub fn new(baud_rate: u32) -> Self {
+    Config {
+      baud_rate: baud_rate,
+      parity: Parity::None,
+      stop_bits: StopBits::One,
+    }
+  }
+}
+
+struct Buffers {
+  rx: RingBuffer,
+  tx: RingBuffer,
+}
+
+static USART1_BUFFERS: SpinMutex<Buffers> = SpinMutex::new(Buffers {
+  rx: RingBuffer::new(),
+  tx: RingBuffer::new(),
+});
 
-  pa9.set_function(gpio::AlternateFunction::One);
-  pa10.set_function(gpio::AlternateFunction::One);
+static USART2_BUFFERS: SpinMutex<Buffers> = SpinMutex::new(Buffers {
+  rx: RingBuffer::new(),
+  tx: RingBuffer::new(),
+});
 
-  pa9.set_speed(gpio::Speed::High);
-  pa10.set_speed(gpio::Speed::High);
+/// A serial port.
+#[derive(Copy, Clone)]
+pub struct Serial {
+  usart: Usart,
+  cr1: cr1::CR1,
+  cr2: cr2::CR2,
+  brr: brr::BRR,
+  isr: isr::ISR,
+  icr: isr::ICR,
+  rdr: data::RDR,
+  tdr: data::TDR,
+}
+
+impl Serial {
+  fn new(usart: Usart) -> Self {
+    let base_addr = usart.base_addr();
+    Serial {
+      usart: usart,
+      cr1: cr1::CR1::new(base_addr),
+      cr2: cr2::CR2::new(base_addr),
+      brr: brr::BRR::new(base_addr),
+      isr: isr::ISR::new(base_addr),
+      icr: isr::ICR::new(base_addr),
+      rdr: data::RDR::new(base_addr),
+      tdr: data::TDR::new(base_addr),
+    }
+  }
+
+  /// Sets up the pins and the USART with the given configuration and starts it.
+  ///
+  /// The baud rate is calculated from the system clock, which also drives the peripheral clocks
+  /// since the APB prescaler is left at 1. If the system clock changes the port must be opened
+  /// again.
+  ///
+  /// # Panics
+  ///
+  /// This function will panic if the baud rate is 0 or too fast for the clock.
+  pub fn open(usart: Usart, config: Config) -> Self {
+    let rcc = rcc::rcc();
+    let (peripheral, tx_pin, rx_pin) = match usart {
+      Usart::Usart1 => (rcc::Peripheral::USART1, 9, 10),
+      Usart::Usart2 => (rcc::Peripheral::USART2, 2, 3),
+    };
+
+    gpio::GPIO::enable(gpio::Group::A);
+    rcc.enable_peripheral(peripheral);
+
+    for pin in &[tx_pin, rx_pin] {
+      let mut port = gpio::Port::new(*pin, gpio::Group::A);
+      port.set_function(gpio::AlternateFunction::One);
+      port.set_speed(gpio::Speed::High);
+      port.set_mode(gpio::Mode::Alternate);
+      port.set_type(gpio::Type::PushPull);
+      port.set_pull(gpio::Pull::Up);
+    }
+
+    let serial = Serial::new(usart);
+    // The frame format can only be changed while the USART is off
+    serial.cr1.set_enable(false);
+    serial.brr.set_baud_rate(config.baud_rate, rcc.get_system_clock_rate());
+    serial.cr1.set_parity(config.parity != Parity::None, config.parity == Parity::Odd);
+    serial.cr2.set_stop_bits(config.stop_bits);
+    serial.cr1.set_rx_interrupt(true);
+    serial.cr1.set_receiver_enable(true);
+    serial.cr1.set_transmitter_enable(true);
+    serial.cr1.set_enable(true);
+
+    NVIC::nvic().enable_interrupt(usart.interrupt());
+    serial
+  }
 
-  pa9.set_mode(gpio::Mode::Alternate);
-  pa10.set_mode(gpio::Mode::Alternate);
+  /// Returns a handle to a USART that has already been opened.
+  pub fn get(usart: Usart) -> Self {
+    Serial::new(usart)
+  }
 
-  pa9.set_type(gpio::Type::PushPull);
-  pa10.set_type(gpio::Type::PushPull);
+  /// Sends `bytes`, blocking while the transmit buffer is full.
+  ///
+  /// When called from the kernel, like before the scheduler has started or from an interrupt
+  /// handler, there's no task to put to sleep so the bytes are sent by polling instead.
+  pub fn write(&self, bytes: &[u8]) {
+    if arch::in_kernel_mode() {
+      self.write_polled(bytes);
+      return;
+    }
+    for byte in bytes {
+      loop {
+        let _g = CriticalSection::begin();
+        {
+          let mut buffers = self.usart.buffers().lock();
+          if buffers.tx.push(*byte) {
+            self.cr1.set_tx_interrupt(true);
+            break;
+          }
+        }
+        // Going to sleep inside the critical section means the interrupt can't make room and try
+        // to wake us before we're asleep
+        syscall::sleep(self.tx_wchan());
+      }
+    }
+  }
 
-  pa9.set_pull(gpio::Pull::Up);
-  pa10.set_pull(gpio::Pull::Up);
+  /// Reads as many received bytes as are available into `buf`, returning how many were read. If
+  /// nothing has been received this blocks until something is.
+  pub fn read(&self, buf: &mut [u8]) -> usize {
+    if buf.is_empty() {
+      return 0;
+    }
+    loop {
+      let _g = CriticalSection::begin();
+      {
+        let mut buffers = self.usart.buffers().lock();
+        let mut count = 0;
+        while count < buf.len() {
+          match buffers.rx.pop() {
+            Some(byte) => buf[count] = byte,
+            None => break,
+          }
+          count += 1;
+        }
+        if count > 0 {
+          return count;
+        }
+      }
+      syscall::sleep(self.rx_wchan());
+    }
+  }
 
+  /// Blocks until everything that has been written is sent.
+  pub fn flush(&self) {
+    loop {
+      let _g = CriticalSection::begin();
+      if self.usart.buffers().lock().tx.is_empty() {
+        break;
+      }
+      syscall::sleep(self.tx_wchan());
+    }
+    // The last byte can still be in the shift register
+    while !self.isr.tx_complete() {}
+  }
+
+  /// Sends `bytes` by polling the USART, without the transmit buffer or interrupts.
+  ///
+  /// This never sleeps, so it can be used where the kernel can't be, like in the panic handler.
+  /// Anything still in the transmit buffer may be sent after these bytes.
+  pub fn write_polled(&self, bytes: &[u8]) {
+    for byte in bytes {
+      while !self.isr.tx_empty() {}
+      self.tdr.write(*byte);
+    }
+  }
+
+  fn rx_wchan(&self) -> usize {
+    self.usart.buffers() as *const _ as usize
+  }
+
+  fn tx_wchan(&self) -> usize {
+    self.rx_wchan() + 1
+  }
+
+  fn handle_interrupt(&self) {
+    let mut buffers = self.usart.buffers().lock();
+    if self.isr.has_error() {
+      // The byte that caused the error is still received, the errors are just dropped
+      self.icr.clear_errors();
+    }
+    if self.isr.rx_not_empty() {
+      // If nobody is reading fast enough the byte is lost
+      buffers.rx.push(self.rdr.read());
+      syscall::wake(self.rx_wchan());
+    }
+    if self.cr1.tx_interrupt_enabled() && self.isr.tx_empty() {
+      let was_full = buffers.tx.is_full();
+      match buffers.tx.pop() {
+        Some(byte) => self.tdr.write(byte),
+        None => self.cr1.set_tx_interrupt(false),
+      }
+      // Wake writers waiting for room, or for the buffer to be flushed
+      if was_full || buffers.tx.is_empty() {
+        syscall::wake(self.tx_wchan());
+      }
+    }
+  }
+}
+
+/// The interrupt handler for USART1.
+pub fn usart1_handler() {
+  #[cfg(feature="trace")]
+  trace::isr_enter(16 + 27);
+  Serial::get(Usart::Usart1).handle_interrupt();
+  #[cfg(feature="trace")]
+  trace::isr_exit(16 + 27);
+}
 
+/// The interrupt handler for USART2.
+pub fn usart2_handler() {
+  #[cfg(feature="trace")]
+  trace::isr_enter(16 + 28);
+  Serial::get(Usart::Usart2).handle_interrupt();
+  #[cfg(feature="trace")]
+  trace::isr_exit(16 + 28);
 }
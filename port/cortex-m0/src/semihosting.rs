@@ -0,0 +1,66 @@
+// semihosting.rs
+// AltOSRust
+//
+// Created by Daniel Seitz on 3/4/17
+
+//! Output to an attached debugger through ARM semihosting.
+//!
+//! Semihosting requests are made with a `bkpt 0xAB` instruction that the debugger catches, so
+//! they only work while a debugger with semihosting enabled is attached. Without one the
+//! breakpoint escalates to a HardFault. Each request also stops the processor while the debugger
+//! handles it, which makes it slow, it's meant for development rather than for a shipped system.
+
+use core::fmt::{self, Write};
+use altos_core::log::Record;
+
+/// The semihosting operation that writes a buffer to a file handle.
+const SYS_WRITE: usize = 0x05;
+
+/// The debugger's file handle for stdout.
+const STDOUT: usize = 1;
+
+/// Writes `bytes` to the debugger's console.
+pub fn write(bytes: &[u8]) {
+  let block = [STDOUT, bytes.as_ptr() as usize, bytes.len()];
+  // UNSAFE: The parameter block lives until the debugger has handled the request
+  unsafe { call(SYS_WRITE, block.as_ptr() as usize) };
+}
+
+/// A log sink that writes each record to the debugger's console.
+///
+/// # Examples
+///
+/// ```rust,no_run
+/// use cortex_m0::kernel::log;
+/// use cortex_m0::semihosting;
+///
+/// log::start_logger(semihosting::log_sink, 512);
+/// ```
+pub fn log_sink(record: &Record) {
+  let _ = write!(Writer, "{}\n", record);
+}
+
+struct Writer;
+
+impl Write for Writer {
+  fn write_str(&mut self, s: &str) -> fmt::Result {
+    write(s.as_bytes());
+    Ok(())
+  }
+}
+
+#[cfg(target_arch="arm")]
+unsafe fn call(operation: usize, arg: usize) -> usize {
+  let result: usize;
+  asm!("bkpt 0xAB"
+    : "={r0}"(result)
+    : "{r0}"(operation), "{r1}"(arg)
+    : "memory"
+    : "volatile");
+  result
+}
+
+#[cfg(not(target_arch="arm"))]
+unsafe fn call(_operation: usize, _arg: usize) -> usize {
+  0
+}
@@ -0,0 +1,37 @@
+// system_control/aircr.rs
+// AltOSRust
+//
+// Created by Daniel Seitz on 2/27/17
+
+use ::peripheral::Register;
+
+#[derive(Copy, Clone)]
+pub struct AIRCR {
+  base_addr: u32,
+}
+
+impl Register for AIRCR {
+  fn new(base_addr: u32) -> Self {
+    AIRCR { base_addr: base_addr }
+  }
+
+  fn base_addr(&self) -> u32 {
+    self.base_addr
+  }
+
+  fn mem_offset(&self) -> u32 {
+    0x0C
+  }
+}
+
+impl AIRCR {
+  pub fn request_system_reset(&self) {
+    // Writes are ignored unless the key is in the top half of the register
+    const VECTKEY: u32 = 0x05FA << 16;
+    const SYSRESETREQ: u32 = 0b1 << 2;
+    unsafe {
+      let mut reg = self.addr();
+      reg.store(VECTKEY | SYSRESETREQ);
+    }
+  }
+}
@@ -5,8 +5,10 @@
 
 use ::volatile::Volatile;
 use ::peripheral::{Control, Register};
+use arm;
 
 mod icsr;
+mod aircr;
 
 pub fn scb() -> SCB {
   SCB::scb()
@@ -17,6 +19,7 @@ pub fn scb() -> SCB {
 pub struct SCB {
   mem_addr: u32,
   icsr: icsr::ICSR,
+  aircr: aircr::AIRCR,
 }
 
 impl Control for SCB {
@@ -31,6 +34,7 @@ impl SCB {
     SCB {
       mem_addr: SCB_ADDR,
       icsr: icsr::ICSR::new(SCB_ADDR),
+      aircr: aircr::AIRCR::new(SCB_ADDR),
     }
   }
 
@@ -41,4 +45,16 @@ impl SCB {
   pub fn clear_pend_sv(&self) {
     self.icsr.clear_pend_sv();
   }
+
+  /// Resets the whole system, this never returns.
+  pub fn system_reset(&self) -> ! {
+    unsafe {
+      // Make sure any outstanding writes finish before the reset
+      arm::asm::dsb();
+      self.aircr.request_system_reset();
+      arm::asm::dsb();
+    }
+    // The reset takes a few cycles to happen
+    loop {}
+  }
 }
@@ -0,0 +1,186 @@
+// main.rs
+// AltOSRust
+//
+// Created by Daniel Seitz on 2/22/17
+
+//! Decodes a dump of the kernel's trace buffer into a timeline.
+//!
+//! The dump is the raw bytes of the drained trace records, as produced by
+//! `altos_core::trace::Record::to_bytes`. Each record is three little-endian 32-bit words: the
+//! timestamp, the event kind and the event's argument.
+//!
+//! Usage: `trace_decode <dump file>`, or pipe the dump in on stdin.
+
+use std::env;
+use std::fmt;
+use std::fs::File;
+use std::io::{self, Read};
+use std::process;
+
+const RECORD_SIZE: usize = 12;
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+enum Event {
+  SwitchIn(u32),
+  SwitchOut(u32),
+  TaskCreate(u32),
+  TaskDestroy(u32),
+  Sleep(u32),
+  Wake(u32),
+  MutexLock(u32),
+  MutexUnlock(u32),
+  IsrEnter(u32),
+  IsrExit(u32),
+  Unknown(u32, u32),
+}
+
+impl Event {
+  // These must match `altos_core::trace::Event::encode`
+  fn decode(kind: u32, arg: u32) -> Self {
+    match kind {
+      1 => Event::SwitchIn(arg),
+      2 => Event::SwitchOut(arg),
+      3 => Event::TaskCreate(arg),
+      4 => Event::TaskDestroy(arg),
+      5 => Event::Sleep(arg),
+      6 => Event::Wake(arg),
+      7 => Event::MutexLock(arg),
+      8 => Event::MutexUnlock(arg),
+      9 => Event::IsrEnter(arg),
+      10 => Event::IsrExit(arg),
+      _ => Event::Unknown(kind, arg),
+    }
+  }
+}
+
+impl fmt::Display for Event {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    match *self {
+      Event::SwitchIn(tid) => write!(f, "switch in to task {}", tid),
+      Event::SwitchOut(tid) => write!(f, "switch out of task {}", tid),
+      Event::TaskCreate(tid) => write!(f, "create task {}", tid),
+      Event::TaskDestroy(tid) => write!(f, "destroy task {}", tid),
+      Event::Sleep(wchan) => write!(f, "sleep on {:#010x}", wchan),
+      Event::Wake(wchan) => write!(f, "wake {:#010x}", wchan),
+      Event::MutexLock(addr) => write!(f, "lock mutex {:#010x}", addr),
+      Event::MutexUnlock(addr) => write!(f, "unlock mutex {:#010x}", addr),
+      Event::IsrEnter(number) => write!(f, "enter exception {}", number),
+      Event::IsrExit(number) => write!(f, "exit exception {}", number),
+      Event::Unknown(kind, arg) => write!(f, "unknown event {} ({:#010x})", kind, arg),
+    }
+  }
+}
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+struct Record {
+  timestamp: u32,
+  event: Event,
+}
+
+fn read_word(bytes: &[u8]) -> u32 {
+  (bytes[0] as u32) | (bytes[1] as u32) << 8 | (bytes[2] as u32) << 16 | (bytes[3] as u32) << 24
+}
+
+/// Splits a dump into records, any partial record at the end is ignored.
+fn parse(dump: &[u8]) -> Vec<Record> {
+  dump.chunks(RECORD_SIZE)
+    .filter(|chunk| chunk.len() == RECORD_SIZE)
+    .map(|chunk| Record {
+      timestamp: read_word(&chunk[0..4]),
+      event: Event::decode(read_word(&chunk[4..8]), read_word(&chunk[8..12])),
+    })
+    .collect()
+}
+
+/// Formats the records as a timeline, one line per event.
+///
+/// Each line shows the timestamp, the time since the previous event and the task that was running
+/// when the event happened, if it's known.
+fn timeline(records: &[Record]) -> Vec<String> {
+  let mut lines = Vec::new();
+  let mut running: Option<u32> = None;
+  let mut last: Option<u32> = None;
+  for record in records {
+    let delta = match last {
+      Some(last) => record.timestamp.wrapping_sub(last),
+      None => 0,
+    };
+    let task = match running {
+      Some(tid) => format!("task {}", tid),
+      None => "-".to_string(),
+    };
+    lines.push(format!("{:>10} {:>+8} {:>10}  {}", record.timestamp, delta, task, record.event));
+
+    match record.event {
+      Event::SwitchIn(tid) => running = Some(tid),
+      Event::SwitchOut(_) => running = None,
+      _ => {},
+    }
+    last = Some(record.timestamp);
+  }
+  lines
+}
+
+fn read_dump() -> io::Result<Vec<u8>> {
+  let mut dump = Vec::new();
+  match env::args().nth(1) {
+    Some(path) => File::open(path)?.read_to_end(&mut dump)?,
+    None => io::stdin().read_to_end(&mut dump)?,
+  };
+  Ok(dump)
+}
+
+fn main() {
+  let dump = match read_dump() {
+    Ok(dump) => dump,
+    Err(err) => {
+      eprintln!("trace_decode: {}", err);
+      process::exit(1);
+    },
+  };
+  if dump.len() % RECORD_SIZE != 0 {
+    eprintln!("trace_decode: ignoring {} trailing bytes", dump.len() % RECORD_SIZE);
+  }
+
+  println!("{:>10} {:>8} {:>10}  event", "timestamp", "delta", "running");
+  for line in timeline(&parse(&dump)) {
+    println!("{}", line);
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn parse_records() {
+    let dump = [4, 3, 2, 1, 1, 0, 0, 0, 5, 0, 0, 0,
+                10, 0, 0, 0, 6, 0, 0, 0, 0xef, 0xbe, 0xad, 0xde,
+                0xff];
+    let records = parse(&dump);
+
+    assert_eq!(records.len(), 2);
+    assert_eq!(records[0], Record { timestamp: 0x0102_0304, event: Event::SwitchIn(5) });
+    assert_eq!(records[1], Record { timestamp: 10, event: Event::Wake(0xdead_beef) });
+  }
+
+  #[test]
+  fn timeline_tracks_running_task() {
+    let records = [
+      Record { timestamp: 1, event: Event::SwitchIn(2) },
+      Record { timestamp: 4, event: Event::Sleep(0x20) },
+      Record { timestamp: 5, event: Event::SwitchOut(2) },
+    ];
+    let lines = timeline(&records);
+
+    assert!(lines[1].contains("task 2"));
+    assert!(lines[1].contains("+3"));
+    assert!(lines[1].ends_with("sleep on 0x00000020"));
+    assert!(lines[2].contains("task 2"));
+  }
+
+  #[test]
+  fn unknown_event() {
+    assert_eq!(Event::decode(42, 1), Event::Unknown(42, 1));
+  }
+}